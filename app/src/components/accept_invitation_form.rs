@@ -0,0 +1,114 @@
+use crate::components::{ErrorAlert, PwdPolicyFeedback};
+use crate::server_fns::ServerError;
+use crate::Error;
+use leptos::{component, create_action, create_effect, create_signal, event_target_value};
+use leptos::{expect_context, server, spawn_local, ServerFnError};
+use leptos::{view, IntoView, Show, SignalGet, SignalSet};
+use lib_utils::pwd_policy::check_pwd;
+use web_sys::{MouseEvent, SubmitEvent};
+
+// region:        --- Server functions
+
+#[server]
+async fn accept_invitation(token: String, pwd: String) -> Result<(), ServerFnError<ServerError>> {
+    use lib_core::model::app_state::AppState;
+    use lib_core::model::Error;
+
+    let app_state: AppState = expect_context();
+
+    match lib_core::model::invitation::accept_invitation(app_state.mm.clone(), &token, &pwd).await {
+        Ok(_) => Ok(()),
+        Err(Error::InvitationTokenInvalid) => Err(ServerError::InvitationTokenInvalid.into()),
+        Err(Error::PwdPolicy { issues }) => Err(ServerError::pwd_policy(&issues).into()),
+        Err(Error::EmailAlreadyExists) => Err(ServerError::EmailAlreadyExists.into()),
+        Err(_) => Err(ServerError::TryAgain.into()),
+    }
+}
+
+// endregion:     --- Server functions
+
+/// Chooses the password of the invited account with the token received by email.
+#[component]
+pub fn AcceptInvitationForm(token: String) -> impl IntoView {
+    let (error, set_error) = create_signal::<Option<Error>>(None);
+    let (done, set_done) = create_signal(false);
+    let (pwd, set_pwd) = create_signal::<String>(String::new());
+    let (pwd_errors, set_pwd_errors) = create_signal::<Vec<String>>(Vec::new());
+    let (token, _) = create_signal(token);
+
+    let accept_action = create_action(|input: &(String, String)| {
+        let (token, pwd) = input.clone();
+        async move { accept_invitation(token, pwd).await }
+    });
+
+    let handle_accept = move |_: MouseEvent| {
+        spawn_local(async move { accept_action.dispatch((token.get(), pwd.get())) })
+    };
+
+    create_effect(move |_| {
+        if let Some(res) = accept_action.value().get() {
+            match res {
+                Ok(()) => {
+                    set_error.set(None);
+                    set_done.set(true);
+                }
+                Err(ServerFnError::WrappedServerError(se @ ServerError::InvalidFields { .. })) => {
+                    set_pwd_errors.set(se.field_errors("pwd"));
+                    set_error.set(Some(Error::InvalidFields));
+                }
+                Err(ServerFnError::WrappedServerError(
+                    ServerError::InvitationTokenInvalid | ServerError::EmailAlreadyExists,
+                )) => set_error.set(Some(Error::InvalidInvitationToken)),
+                Err(_) => set_error.set(Some(Error::TryLater)),
+            }
+        }
+    });
+
+    view! {
+        <div class="font-serif mx-auto bg-gray-300 rounded-md shadow-md w-2/4 p-3">
+            <ErrorAlert error=error/>
+            <Show
+                when=move || !done.get()
+                fallback=|| {
+                    view! {
+                        <div class="bg-lime-200 p-2 text-center rounded-md mb-4">
+                            <a href="/">"Account activated, you can now sign in."</a>
+                        </div>
+                    }
+                }
+            >
+
+                <form class="flex flex-col" on:submit=|ev: SubmitEvent| ev.prevent_default()>
+                    <div class="flex flex-col mb-3">
+                        <label class="mb-2" for="pwd-input">
+                            Password:
+                        </label>
+                        <input
+                            class="bg-white rounded-md h-8 p-2"
+                            type="password"
+                            placeholder="*************"
+                            id="pwd-input"
+                            on:input=move |ev| {
+                                set_pwd_errors.set(Vec::new());
+                                set_pwd.set(event_target_value(&ev))
+                            }
+
+                            prop:value=pwd
+                        />
+                    </div>
+                    <PwdPolicyFeedback pwd=pwd server_errors=pwd_errors/>
+                    <button
+                        class="mt-5 rounded-md h-8 bg-lime-300 hover:bg-lime-100"
+                        on:click=handle_accept
+                        // the email rule is checked by the server
+                        disabled=move || {
+                            !check_pwd(&pwd.get(), "").is_empty() || accept_action.pending().get()
+                        }
+                    >
+                        Activate account
+                    </button>
+                </form>
+            </Show>
+        </div>
+    }
+}
@@ -0,0 +1,102 @@
+use crate::server_fns::ServerError;
+use leptos::{component, create_action, create_effect, create_resource, server, spawn_local};
+use leptos::{view, window, IntoView, ServerFnError, SignalGet, Suspense};
+use serde::{Deserialize, Serialize};
+use web_sys::MouseEvent;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImpersonationInfo {
+    /// Email of the user who signed in.
+    pub real_email: String,
+    /// Email of the user seen.
+    pub email: String,
+}
+
+// region:        --- Server functions
+
+/// Who is seen as whom, `None` when not impersonating.
+#[server]
+async fn impersonation() -> Result<Option<ImpersonationInfo>, ServerFnError<ServerError>> {
+    use leptos::{expect_context, use_context};
+    use lib_core::ctx::Ctx;
+    use lib_core::model::app_state::AppState;
+    use lib_core::model::user::get_user;
+
+    let Some(ctx) = use_context::<Ctx>().filter(Ctx::is_impersonating) else {
+        return Ok(None);
+    };
+    let app_state: AppState = expect_context();
+
+    let real_user = get_user(app_state.mm.clone(), ctx.real_user_id())
+        .await
+        .map_err(|_| ServerError::TryAgain)?;
+    let user = get_user(app_state.mm.clone(), ctx.user_id())
+        .await
+        .map_err(|_| ServerError::TryAgain)?;
+
+    Ok(Some(ImpersonationInfo {
+        real_email: real_user.email,
+        email: user.email,
+    }))
+}
+
+#[server]
+async fn stop_impersonating() -> Result<(), ServerFnError<ServerError>> {
+    use crate::server_fns::require_ctx;
+    use leptos::expect_context;
+    use lib_core::model::app_state::AppState;
+    use lib_core::model::impersonation::stop_impersonation;
+
+    let ctx = require_ctx()?;
+    let app_state: AppState = expect_context();
+
+    stop_impersonation(app_state.mm.clone(), &ctx)
+        .await
+        .map_err(|_| ServerError::TryAgain.into())
+}
+
+// endregion:     --- Server functions
+
+/// Shown on every page while impersonating, with the way back to the real user.
+#[component]
+pub fn ImpersonationBanner() -> impl IntoView {
+    let impersonation = create_resource(|| (), |_| impersonation());
+
+    let stop_action = create_action(|_: &()| stop_impersonating());
+
+    let handle_stop = move |_: MouseEvent| spawn_local(async move { stop_action.dispatch(()) });
+
+    // the whole page was rendered for the impersonated user
+    create_effect(move |_| {
+        if let Some(Ok(())) = stop_action.value().get() {
+            let _ = window().location().reload();
+        }
+    });
+
+    view! {
+        <Suspense fallback=|| ()>
+            {move || {
+                impersonation
+                    .get()
+                    .and_then(|res| res.ok().flatten())
+                    .map(|imp| {
+                        view! {
+                            <div class="flex items-center justify-center gap-4 bg-amber-200 p-2 rounded-md mb-4 font-serif">
+                                <span>
+                                    {format!("{} is viewing the intranet as {}", imp.real_email, imp.email)}
+                                </span>
+                                <button
+                                    class="rounded-md h-8 px-3 bg-amber-100 hover:bg-white"
+                                    on:click=handle_stop
+                                    disabled=move || stop_action.pending().get()
+                                >
+                                    Stop impersonating
+                                </button>
+                            </div>
+                        }
+                    })
+            }}
+
+        </Suspense>
+    }
+}
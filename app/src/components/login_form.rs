@@ -1,96 +1,288 @@
-use crate::components::ErrorAlert;
-use crate::server_fns::error::serialize_error_response;
+use crate::components::{ErrorAlert, PwdPolicyFeedback, SignOut};
 use crate::server_fns::{ServerError, ServerResult};
 use crate::utils::validate_email;
 use crate::Error;
 use leptos::logging::log;
 use leptos::{
-    component, create_action, create_effect, create_resource, create_signal, event_target_value,
-    expect_context, Suspense,
+    component, create_action, create_effect, create_resource, create_signal, event_target_checked,
+    event_target_value, expect_context, Suspense,
 };
 use leptos::{server, spawn_local, ServerFnError};
-use leptos::{view, IntoView, Show, SignalGet, SignalSet};
-use leptos_router::Form;
+use leptos::{view, IntoView, Show, SignalGet, SignalSet, SignalUpdate};
+use leptos_router::{Form, A};
+use lib_utils::pwd_policy::check_pwd;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use web_sys::MouseEvent;
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginResponse {
+    pub user_id: i64,
+    pub email: String,
+    pub verified: bool,
+    /// The password is right, the TOTP code is expected by `login_totp`.
+    pub totp_required: bool,
+}
+
+#[server]
+async fn login(
+    email: String,
+    pwd: String,
+    remember: bool,
+) -> Result<LoginResponse, ServerFnError<ServerError>> {
+    use crate::server_fns::{client_ip, throttle_error};
+    use leptos_axum::extract;
+    use lib_core::model::app_state::AppState;
+    use lib_core::model::auth_backend::authenticate;
+    use lib_core::model::email_verify::can_login;
+    use lib_core::model::login_throttle::{
+        record_login_success, release_login_attempt, start_login_attempt,
+    };
+    use lib_core::web::set_login_2fa_cookie;
+    use tower_cookies::Cookies;
+
+    let app_state: AppState = expect_context();
+    let mm = app_state.mm.clone();
+
+    // counted before the password, a locked account stays locked with the right one
+    let ip = client_ip().await;
+    start_login_attempt(mm.clone(), &email, ip.as_deref())
+        .await
+        .map_err(throttle_error)?;
+
+    // local password and/or LDAP bind, see `SERVICE_AUTH_BACKENDS`
+    let Ok(user) = authenticate(mm.clone(), &email, &pwd).await else {
+        release_login_attempt(mm, &email, ip.as_deref())
+            .await
+            .map_err(|_| ServerError::TryAgain)?;
+        return Err(ServerError::TryAgain.into());
+    };
+    // the attempt stays counted
+    let Some(user) = user else {
+        return Err(ServerError::LoginFail.into());
+    };
+
+    // right password, the account counter is only cleared once signed in
+    if !can_login(user.verified_at) || user.totp_enabled {
+        release_login_attempt(mm.clone(), &email, ip.as_deref())
+            .await
+            .map_err(|_| ServerError::TryAgain)?;
+    }
+
+    // only once the password is checked, to not disclose the account state
+    if !can_login(user.verified_at) {
+        return Err(ServerError::EmailNotVerified.into());
+    }
+
+    let cookies: Cookies = extract().await.map_err(|_| ServerError::TryAgain)?;
+
+    // second step, the session is opened by `login_totp`
+    if user.totp_enabled {
+        set_login_2fa_cookie(&cookies, user.id).map_err(|_| ServerError::TryAgain)?;
+        return Ok(LoginResponse {
+            user_id: user.id,
+            email: user.email,
+            verified: user.verified_at.is_some(),
+            totp_required: true,
+        });
+    }
+
+    record_login_success(mm, &email, ip.as_deref())
+        .await
+        .map_err(|_| ServerError::TryAgain)?;
+    open_session(&cookies, user, remember).await
+}
+
 #[server]
-async fn add_user(email: String, pwd: String) -> Result<Value, ServerFnError<ServerError>> {
-    use axum::http::header::CONTENT_TYPE;
-    use axum::http::HeaderValue;
-    use axum::http::StatusCode;
-    use leptos_axum::ResponseOptions;
+async fn login_totp(
+    code: String,
+    remember: bool,
+) -> Result<LoginResponse, ServerFnError<ServerError>> {
+    use crate::server_fns::{client_ip, throttle_error};
+    use leptos_axum::extract;
     use lib_core::model::app_state::AppState;
-    use lib_core::model::user::create_user;
+    use lib_core::model::login_throttle::{
+        record_login_success, release_login_attempt, start_login_attempt,
+    };
+    use lib_core::model::user::get_user_for_login_by_id;
+    use lib_core::model::user_totp::verify_totp;
+    use lib_core::model::Error;
+    use lib_core::web::{get_login_2fa_user_id, remove_login_2fa_cookie};
+    use tower_cookies::Cookies;
 
     let app_state: AppState = expect_context();
-    let res: ResponseOptions = expect_context();
-    // useless?
-    // res.insert_header(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-
-    match create_user(app_state.mm.clone(), &email, &pwd).await {
-        Ok(id) => {
-            res.set_status(StatusCode::NOT_FOUND);
-            Ok(serialize_error_response(ServerFnError::WrappedServerError(
-                ServerError::CannotLogin { code: id },
-            )))
+    let mm = app_state.mm.clone();
+
+    let cookies: Cookies = extract().await.map_err(|_| ServerError::TryAgain)?;
+    let user_id = get_login_2fa_user_id(&cookies).ok_or(ServerError::LoginFail)?;
+    let user = get_user_for_login_by_id(mm.clone(), user_id)
+        .await
+        .map_err(|_| ServerError::TryAgain)?
+        .filter(|user| user.is_active)
+        .ok_or(ServerError::LoginFail)?;
+
+    // the codes are guessed like passwords, same throttle
+    let ip = client_ip().await;
+    start_login_attempt(mm.clone(), &user.email, ip.as_deref())
+        .await
+        .map_err(throttle_error)?;
+
+    match verify_totp(mm.clone(), user_id, &code).await {
+        Ok(()) => (),
+        // the attempt stays counted
+        Err(Error::TotpInvalidCode | Error::TotpNotEnrolled) => {
+            return Err(ServerError::TotpInvalidCode.into());
         }
         Err(_) => {
-            res.set_status(StatusCode::SERVICE_UNAVAILABLE);
-            Ok(json!({
-              "error":{
-                "message":"Try again",
-              }
-            }))
-            // Ok(serialize_error_response(ServerFnError::WrappedServerError(
-            //     ServerError::TryAgain,
-            // )))
+            release_login_attempt(mm, &user.email, ip.as_deref())
+                .await
+                .map_err(|_| ServerError::TryAgain)?;
+            return Err(ServerError::TryAgain.into());
         }
     }
 
-    // match create_user(app_state.mm.clone(), &email, &pwd).await {
-    //     Ok(id) => Ok(id),
-    //     Err(e) => {
-    //         let res: ResponseOptions = expect_context();
-    //         res.set_status(StatusCode::INTERNAL_SERVER_ERROR);
-    //         res.insert_header(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-    //         Ok(None)
-    //     }
-    // }
+    record_login_success(mm, &user.email, ip.as_deref())
+        .await
+        .map_err(|_| ServerError::TryAgain)?;
+    remove_login_2fa_cookie(&cookies);
+
+    open_session(&cookies, user, remember).await
+}
+
+/// The TOTP step left pending by the password step or an SSO sign-in.
+#[server]
+async fn pending_login_totp() -> Result<Option<LoginResponse>, ServerFnError<ServerError>> {
+    use leptos_axum::extract;
+    use lib_core::model::app_state::AppState;
+    use lib_core::model::user::get_user_for_login_by_id;
+    use lib_core::web::get_login_2fa_user_id;
+    use tower_cookies::Cookies;
+
+    let app_state: AppState = expect_context();
+    let cookies: Cookies = extract().await.map_err(|_| ServerError::TryAgain)?;
+    let Some(user_id) = get_login_2fa_user_id(&cookies) else {
+        return Ok(None);
+    };
+    let user = get_user_for_login_by_id(app_state.mm.clone(), user_id)
+        .await
+        .map_err(|_| ServerError::TryAgain)?
+        .filter(|user| user.is_active);
+
+    Ok(user.map(|user| LoginResponse {
+        user_id: user.id,
+        email: user.email,
+        verified: user.verified_at.is_some(),
+        totp_required: true,
+    }))
+}
+
+/// Opens a server-side session shared by the SSR pages and `/res/*`. With
+/// `remember`, a refresh token reopens it once the browser was closed.
+#[cfg(feature = "ssr")]
+async fn open_session(
+    cookies: &tower_cookies::Cookies,
+    user: lib_core::model::user::UserForLogin,
+    remember: bool,
+) -> Result<LoginResponse, ServerFnError<ServerError>> {
+    use crate::server_fns::device_label;
+    use lib_core::model::app_state::AppState;
+    use lib_core::model::refresh_token::create_refresh_token;
+    use lib_core::model::session::create_session;
+    use lib_core::web::{set_refresh_cookie, set_token_cookie};
+
+    let app_state: AppState = expect_context();
+
+    let session = create_session(app_state.mm.clone(), user.id)
+        .await
+        .map_err(|_| ServerError::TryAgain)?;
+    set_token_cookie(cookies, &session.id).map_err(|_| ServerError::TryAgain)?;
+
+    if remember {
+        let token = create_refresh_token(app_state.mm.clone(), user.id, &device_label().await)
+            .await
+            .map_err(|_| ServerError::TryAgain)?;
+        set_refresh_cookie(cookies, &token);
+    }
+
+    Ok(LoginResponse {
+        user_id: user.id,
+        email: user.email,
+        verified: user.verified_at.is_some(),
+        totp_required: false,
+    })
+}
+
+#[server]
+async fn add_user(email: String, pwd: String) -> Result<i64, ServerFnError<ServerError>> {
+    use lib_core::model::app_state::AppState;
+    use lib_core::model::email_verify::register_user;
+    use lib_core::model::Error;
 
-    // let res: ResponseOptions = expect_context();
-    // res.set_status(StatusCode::IM_A_TEAPOT);
+    let app_state: AppState = expect_context();
+    let notifier = app_state.notifier.as_ref();
+
+    match register_user(app_state.mm.clone(), notifier, &email, &pwd).await {
+        Ok(id) => Ok(id),
+        Err(Error::EmailAlreadyExists) => Err(ServerError::EmailAlreadyExists.into()),
+        Err(Error::RegistrationClosed) => Err(ServerError::RegistrationClosed.into()),
+        Err(Error::PwdPolicy { issues }) => Err(ServerError::pwd_policy(&issues).into()),
+        Err(_) => Err(ServerError::TryAgain.into()),
+    }
+}
 
-    // let id = create_user(app_state.mm.clone(), &email, &pwd)
-    //     .await
-    //     .map_err(|e| Error::ServerFunctionError(e.to_string()))?;
+/// Whether accounts can be created from the login form, or only by invitation.
+#[server]
+async fn registration_open() -> Result<bool, ServerFnError<ServerError>> {
+    Ok(lib_core::model::email_verify::is_registration_open())
+}
 
-    // Ok(id)
+/// Whether the "Sign in with SSO" link leads somewhere.
+#[server]
+async fn sso_enabled() -> Result<bool, ServerFnError<ServerError>> {
+    Ok(lib_core::oidc::is_enabled())
 }
 
 #[component]
 pub fn LoginForm() -> impl IntoView {
     // define signals (states)
     let (error, set_error) = create_signal::<Option<Error>>(None);
+    let (logged_user, set_logged_user) = create_signal::<Option<LoginResponse>>(None);
     let (email, set_email) = create_signal::<String>(String::new());
     let (pwd, set_pwd) = create_signal::<String>(String::new());
+    let (totp_code, set_totp_code) = create_signal::<String>(String::new());
+    let (remember, set_remember) = create_signal(false);
+    let (signing_up, set_signing_up) = create_signal(false);
+    let (signed_up, set_signed_up) = create_signal(false);
+    let (pwd_errors, set_pwd_errors) = create_signal::<Vec<String>>(Vec::new());
 
     // derived signals
     let empty_email = move || email.get().is_empty();
     let empty_pwd = move || pwd.get().is_empty();
     let valid_email = move || validate_email(&email.get());
+    let valid_new_pwd = move || check_pwd(&pwd.get(), &email.get()).is_empty();
+
+    let sso = create_resource(|| (), |_| sso_enabled());
+    let registration = create_resource(|| (), |_| registration_open());
+    let pending_totp = create_resource(|| (), |_| pending_login_totp());
+
+    // back from SSO, or reloaded, during the TOTP step
+    create_effect(move |_| {
+        if let Some(Ok(Some(user))) = pending_totp.get() {
+            set_logged_user.set(Some(user));
+        }
+    });
 
     // region:        --- Login action
 
     // create action
-    let login_action = create_action(|input: &(String, String)| {
-        let (email, pwd) = input.clone();
-        async move { add_user(email, pwd).await }
+    let login_action = create_action(|input: &(String, String, bool)| {
+        let (email, pwd, remember) = input.clone();
+        async move { login(email, pwd, remember).await }
     });
 
     // trigger action
     let handle_login = move |_: MouseEvent| {
-        spawn_local(async move { login_action.dispatch((email.get(), pwd.get())) })
+        spawn_local(async move { login_action.dispatch((email.get(), pwd.get(), remember.get())) })
     };
 
     // react to action response
@@ -98,43 +290,112 @@ pub fn LoginForm() -> impl IntoView {
         if let Some(res) = login_action.value().get() {
             log!("{:?}", res);
             match res {
-                Ok(value) => {
+                Ok(user) => {
+                    set_error.set(None);
+                    set_logged_user.set(Some(user));
+                }
+                Err(ServerFnError::WrappedServerError(ServerError::LoginFail)) => {
+                    set_logged_user.set(None);
+                    set_error.set(Some(Error::InvalidCredentials));
+                }
+                Err(ServerFnError::WrappedServerError(ServerError::EmailNotVerified)) => {
+                    set_logged_user.set(None);
+                    set_error.set(Some(Error::EmailNotVerified));
+                }
+                Err(ServerFnError::WrappedServerError(ServerError::AccountLocked {
+                    retry_after_sec,
+                })) => {
+                    set_logged_user.set(None);
+                    set_error.set(Some(Error::AccountLocked { retry_after_sec }));
+                }
+                Err(ServerFnError::WrappedServerError(_)) => {
+                    set_logged_user.set(None);
                     set_error.set(Some(Error::TryLater));
-                    // if let Some(code) = value
-                    //     .get("error")
-                    //     .unwrap()
-                    //     .get("code")
-                    //     .unwrap()
-                    //     .as_number()
-                    //     .unwrap()
-                    //     .as_i64()
-                    // {
-                    //     set_error.set(Some(Error::ServerError { code }))
-                    // } else {
-                    //     set_error.set(Some(Error::TryLater))
-                    // }
                 }
                 Err(e) => set_error.set(Some(Error::ServerFunctionError(e.to_string()))),
             }
         }
     });
-    // create_effect(move |_| {
-    //     if let Some(res) = login_action.value().get() {
-    //         match res {
-    //             Ok(id) => {
-    //                 if let Some(id) = id {
-    //                     set_error.set(Some(Error::ServerError { code: id }))
-    //                 } else {
-    //                     set_error.set(Some(Error::TryLater))
-    //                 }
-    //             }
-    //             Err(_) => set_error.set(Some(Error::TryLater)),
-    //         }
-    //     }
-    // });
 
     // endregion:     --- Login action
 
+    // region:        --- Login TOTP action
+
+    let login_totp_action = create_action(|input: &(String, bool)| {
+        let (code, remember) = input.clone();
+        async move { login_totp(code, remember).await }
+    });
+
+    let handle_login_totp = move |_: MouseEvent| {
+        spawn_local(async move { login_totp_action.dispatch((totp_code.get(), remember.get())) })
+    };
+
+    create_effect(move |_| {
+        if let Some(res) = login_totp_action.value().get() {
+            match res {
+                Ok(user) => {
+                    set_error.set(None);
+                    set_totp_code.set(String::new());
+                    set_logged_user.set(Some(user));
+                }
+                Err(ServerFnError::WrappedServerError(ServerError::TotpInvalidCode)) => {
+                    set_error.set(Some(Error::InvalidTotpCode));
+                }
+                Err(ServerFnError::WrappedServerError(ServerError::AccountLocked {
+                    retry_after_sec,
+                })) => {
+                    set_error.set(Some(Error::AccountLocked { retry_after_sec }));
+                }
+                // the password step expired, start again
+                Err(ServerFnError::WrappedServerError(ServerError::LoginFail)) => {
+                    set_logged_user.set(None);
+                    set_error.set(Some(Error::InvalidCredentials));
+                }
+                Err(ServerFnError::WrappedServerError(_)) => set_error.set(Some(Error::TryLater)),
+                Err(e) => set_error.set(Some(Error::ServerFunctionError(e.to_string()))),
+            }
+        }
+    });
+
+    // endregion:     --- Login TOTP action
+
+    // region:        --- Sign up action
+
+    let sign_up_action = create_action(|input: &(String, String)| {
+        let (email, pwd) = input.clone();
+        async move { add_user(email, pwd).await }
+    });
+
+    let handle_sign_up = move |_: MouseEvent| {
+        spawn_local(async move { sign_up_action.dispatch((email.get(), pwd.get())) })
+    };
+
+    create_effect(move |_| {
+        if let Some(res) = sign_up_action.value().get() {
+            match res {
+                Ok(_) => {
+                    set_error.set(None);
+                    set_signing_up.set(false);
+                    set_signed_up.set(true);
+                    set_pwd.set(String::new());
+                }
+                Err(ServerFnError::WrappedServerError(se @ ServerError::InvalidFields { .. })) => {
+                    set_pwd_errors.set(se.field_errors("pwd"));
+                    set_error.set(Some(Error::InvalidFields));
+                }
+                Err(ServerFnError::WrappedServerError(ServerError::EmailAlreadyExists)) => {
+                    set_error.set(Some(Error::EmailAlreadyExists))
+                }
+                Err(ServerFnError::WrappedServerError(ServerError::RegistrationClosed)) => {
+                    set_error.set(Some(Error::RegistrationClosed))
+                }
+                Err(_) => set_error.set(Some(Error::TryLater)),
+            }
+        }
+    });
+
+    // endregion:     --- Sign up action
+
     view! {
         <div class="font-serif mx-auto bg-gray-300 rounded-md shadow-md w-2/4 p-3">
             <Show
@@ -147,6 +408,61 @@ pub fn LoginForm() -> impl IntoView {
             >
 
                 <ErrorAlert error=error/>
+                {move || {
+                    logged_user
+                        .get()
+                        .map(|user| {
+                            if user.totp_required {
+                                return view! {
+                                    <div class="flex flex-col mb-4">
+                                        <label class="mb-2" for="totp-input">
+                                            "Authenticator code (or recovery code):"
+                                        </label>
+                                        <input
+                                            class="bg-white rounded-md h-8 p-2"
+                                            type="text"
+                                            autocomplete="one-time-code"
+                                            placeholder="123456"
+                                            id="totp-input"
+                                            on:input=move |ev| {
+                                                set_totp_code.set(event_target_value(&ev))
+                                            }
+
+                                            prop:value=totp_code
+                                        />
+                                        <button
+                                            class="mt-3 rounded-md h-8 bg-lime-300 hover:bg-lime-100"
+                                            on:click=handle_login_totp
+                                            disabled=move || {
+                                                totp_code.get().is_empty()
+                                                    || login_totp_action.pending().get()
+                                            }
+                                        >
+
+                                            Verify
+                                        </button>
+                                    </div>
+                                }
+                                    .into_view();
+                            }
+                            view! {
+                                <div class="bg-lime-200 p-2 text-center rounded-md mb-4">
+                                    {format!("Logged in as {}", user.email)}
+                                </div>
+                                <Show when=move || !user.verified fallback=|| ()>
+                                    <div class="bg-yellow-200 p-2 text-center rounded-md mb-4">
+                                        "Check your inbox to verify your email address."
+                                    </div>
+                                </Show>
+                                <A class="block text-center text-sm underline mb-4" href="/account/2fa">
+                                    Two-factor authentication
+                                </A>
+                                <SignOut on_signed_out=move |_| set_logged_user.set(None)/>
+                            }
+                                .into_view()
+                        })
+                }}
+
             </Show>
 
             <Form action="" class=" flex flex-col">
@@ -163,7 +479,7 @@ pub fn LoginForm() -> impl IntoView {
                         type="email"
                         placeholder="e@mail.com"
                         id="email-input"
-                        value=(move || email.get())()
+                        value=email.get()
                         on:input=move |ev| { set_email.set(event_target_value(&ev)) }
                         prop:value=email
                     />
@@ -177,28 +493,109 @@ pub fn LoginForm() -> impl IntoView {
                         type="password"
                         placeholder="*************"
                         id="pwd-input"
-                        value=(move || pwd.get())()
-                        on:input=move |ev| { set_pwd.set(event_target_value(&ev)) }
+                        value=pwd.get()
+                        on:input=move |ev| {
+                            set_pwd_errors.set(Vec::new());
+                            set_pwd.set(event_target_value(&ev))
+                        }
+
                         prop:value=pwd
                     />
                 </div>
-                <button
-                    class=move || {
-                        if !empty_email() && !empty_pwd() && valid_email() {
-                            "mt-5 rounded-md h-8 bg-lime-300 hover:bg-lime-100"
-                        } else {
-                            "mt-5 rounded-md h-8 bg-gray-100"
+                <Show
+                    when=move || signing_up.get()
+                    fallback=move || {
+                        view! {
+                            <label class="flex items-center gap-2 text-sm">
+                                <input
+                                    type="checkbox"
+                                    id="remember-input"
+                                    on:change=move |ev| set_remember.set(event_target_checked(&ev))
+                                    prop:checked=remember
+                                />
+                                "Remember me on this device"
+                            </label>
+                            <button
+                                class=move || {
+                                    if !empty_email() && !empty_pwd() && valid_email() {
+                                        "mt-5 rounded-md h-8 bg-lime-300 hover:bg-lime-100"
+                                    } else {
+                                        "mt-5 rounded-md h-8 bg-gray-100"
+                                    }
+                                }
+
+                                // send info to SQLite
+                                on:click=handle_login
+                                disabled=move || { empty_email() || empty_pwd() || !valid_email() }
+                            >
+
+                                Sign in
+                            </button>
                         }
                     }
-
-                    // send info to SQLite
-                    on:click=handle_login
-                    disabled=move || { empty_email() || empty_pwd() || !valid_email() }
                 >
 
-                    Sign in
-                </button>
+                    <PwdPolicyFeedback pwd=pwd email=email server_errors=pwd_errors/>
+                    <button
+                        class=move || {
+                            if valid_email() && valid_new_pwd() {
+                                "mt-2 rounded-md h-8 bg-lime-300 hover:bg-lime-100"
+                            } else {
+                                "mt-2 rounded-md h-8 bg-gray-100"
+                            }
+                        }
+
+                        on:click=handle_sign_up
+                        disabled=move || {
+                            !valid_email() || !valid_new_pwd() || sign_up_action.pending().get()
+                        }
+                    >
+
+                        Create account
+                    </button>
+                </Show>
             </Form>
+            <Show when=move || signed_up.get() fallback=|| ()>
+                <div class="bg-lime-200 p-2 text-center rounded-md mt-4">
+                    "Account created, check your inbox to verify your email address."
+                </div>
+            </Show>
+            <Suspense fallback=|| ()>
+                <Show when=move || matches!(registration.get(), Some(Ok(true)))>
+                    <button
+                        class="block w-full mt-3 text-center text-sm underline"
+                        on:click=move |_| {
+                            set_error.set(None);
+                            set_signing_up.update(|signing_up| *signing_up = !*signing_up)
+                        }
+                    >
+
+                        {move || {
+                            if signing_up.get() {
+                                "Already have an account? Sign in"
+                            } else {
+                                "No account yet? Create one"
+                            }
+                        }}
+
+                    </button>
+                </Show>
+            </Suspense>
+            <A class="block mt-3 text-center text-sm underline" href="/reset-password">
+                Forgot your password?
+            </A>
+            <Suspense fallback=|| ()>
+                <Show when=move || matches!(sso.get(), Some(Ok(true)))>
+                    // external: the server redirects to the identity provider
+                    <a
+                        class="block mt-5 rounded-md h-8 leading-8 text-center bg-sky-200 hover:bg-sky-100"
+                        href="/auth/oidc/login"
+                        rel="external"
+                    >
+                        Sign in with SSO
+                    </a>
+                </Show>
+            </Suspense>
 
         </div>
     }
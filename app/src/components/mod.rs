@@ -1,5 +1,17 @@
+mod accept_invitation_form;
 mod error_alert;
+mod impersonation_banner;
 mod login_form;
+mod pwd_policy_feedback;
+mod reset_pwd_form;
+mod sign_out;
+mod totp_setup;
 
+pub use accept_invitation_form::AcceptInvitationForm;
 pub use error_alert::ErrorAlert;
+pub use impersonation_banner::ImpersonationBanner;
 pub use login_form::LoginForm;
+pub use pwd_policy_feedback::PwdPolicyFeedback;
+pub use reset_pwd_form::{RequestPwdResetForm, ResetPwdForm};
+pub use sign_out::SignOut;
+pub use totp_setup::TotpSetup;
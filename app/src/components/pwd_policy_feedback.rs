@@ -0,0 +1,37 @@
+use leptos::{component, view, For, IntoView, MaybeSignal, Signal, SignalGet};
+use lib_utils::pwd_policy::{check_pwd, PwdIssue};
+
+/// Rules of the password policy, checked as the user types. The email rule is
+/// left to the server when the email is not known here.
+#[component]
+pub fn PwdPolicyFeedback(
+    #[prop(into)] pwd: Signal<String>,
+    #[prop(into, optional)] email: MaybeSignal<String>,
+    /// Errors of the `pwd` field returned by the server.
+    #[prop(into, optional)]
+    server_errors: MaybeSignal<Vec<String>>,
+) -> impl IntoView {
+    let rules = move || {
+        let email = email.get();
+        let issues = check_pwd(&pwd.get(), &email);
+        PwdIssue::ALL
+            .into_iter()
+            .filter(|rule| *rule != PwdIssue::ContainsEmail || !email.is_empty())
+            .map(|rule| (rule, !issues.contains(&rule)))
+            .collect::<Vec<_>>()
+    };
+
+    view! {
+        <ul class="text-sm mb-3">
+            <For each=rules key=|rule| *rule let:rule>
+                <li class=if rule.1 { "text-lime-700" } else { "text-gray-600" }>
+                    {if rule.1 { "✓ " } else { "✗ " }}
+                    {rule.0.to_string()}
+                </li>
+            </For>
+            <For each=move || server_errors.get() key=|error| error.clone() let:error>
+                <li class="text-red-700">{error}</li>
+            </For>
+        </ul>
+    }
+}
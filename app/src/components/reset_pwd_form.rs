@@ -0,0 +1,208 @@
+use crate::components::{ErrorAlert, PwdPolicyFeedback};
+use crate::server_fns::ServerError;
+use crate::utils::validate_email;
+use crate::Error;
+use leptos::{component, create_action, create_effect, create_signal, event_target_value};
+use leptos::{expect_context, server, spawn_local, ServerFnError};
+use leptos::{view, IntoView, Show, SignalGet, SignalSet};
+use lib_utils::pwd_policy::check_pwd;
+use web_sys::{MouseEvent, SubmitEvent};
+
+// region:        --- Server functions
+
+#[server]
+async fn request_pwd_reset(email: String) -> Result<(), ServerFnError<ServerError>> {
+    use crate::server_fns::{client_ip, throttle_error};
+    use lib_core::model::app_state::AppState;
+
+    let app_state: AppState = expect_context();
+
+    // the same email or IP waits before the next link
+    let ip = client_ip().await;
+    lib_core::model::pwd_reset::request_pwd_reset(
+        app_state.mm.clone(),
+        app_state.notifier.as_ref(),
+        &email,
+        ip.as_deref(),
+    )
+    .await
+    .map_err(throttle_error)
+}
+
+#[server]
+async fn reset_pwd(token: String, pwd: String) -> Result<(), ServerFnError<ServerError>> {
+    use lib_core::model::app_state::AppState;
+    use lib_core::model::pwd_reset::complete_pwd_reset;
+    use lib_core::model::Error;
+
+    let app_state: AppState = expect_context();
+
+    match complete_pwd_reset(app_state.mm.clone(), &token, &pwd).await {
+        Ok(()) => Ok(()),
+        Err(Error::PwdResetTokenInvalid) => Err(ServerError::PwdResetTokenInvalid.into()),
+        Err(Error::PwdPolicy { issues }) => Err(ServerError::pwd_policy(&issues).into()),
+        Err(_) => Err(ServerError::TryAgain.into()),
+    }
+}
+
+// endregion:     --- Server functions
+
+/// Asks for the email where the reset link is sent.
+#[component]
+pub fn RequestPwdResetForm() -> impl IntoView {
+    let (error, set_error) = create_signal::<Option<Error>>(None);
+    let (sent, set_sent) = create_signal(false);
+    let (email, set_email) = create_signal::<String>(String::new());
+
+    let valid_email = move || validate_email(&email.get());
+
+    let request_action = create_action(|email: &String| {
+        let email = email.clone();
+        async move { request_pwd_reset(email).await }
+    });
+
+    let handle_request =
+        move |_: MouseEvent| spawn_local(async move { request_action.dispatch(email.get()) });
+
+    create_effect(move |_| {
+        if let Some(res) = request_action.value().get() {
+            match res {
+                Ok(()) => {
+                    set_error.set(None);
+                    set_sent.set(true);
+                }
+                Err(ServerFnError::WrappedServerError(ServerError::AccountLocked {
+                    retry_after_sec,
+                })) => set_error.set(Some(Error::AccountLocked { retry_after_sec })),
+                Err(_) => set_error.set(Some(Error::TryLater)),
+            }
+        }
+    });
+
+    view! {
+        <div class="font-serif mx-auto bg-gray-300 rounded-md shadow-md w-2/4 p-3">
+            <ErrorAlert error=error/>
+            <Show when=move || sent.get() fallback=|| ()>
+                <div class="bg-lime-200 p-2 text-center rounded-md mb-4">
+                    "If this email is registered, a reset link is on its way."
+                </div>
+            </Show>
+
+            <form class="flex flex-col" on:submit=|ev: SubmitEvent| ev.prevent_default()>
+                <div class="flex flex-col mb-3">
+                    <label class="mb-2" for="email-input">
+                        Email:
+                    </label>
+                    <input
+                        class="bg-white rounded-md h-8 p-2"
+                        type="email"
+                        placeholder="e@mail.com"
+                        id="email-input"
+                        on:input=move |ev| { set_email.set(event_target_value(&ev)) }
+                        prop:value=email
+                    />
+                </div>
+                <button
+                    class=move || {
+                        if valid_email() {
+                            "mt-5 rounded-md h-8 bg-lime-300 hover:bg-lime-100"
+                        } else {
+                            "mt-5 rounded-md h-8 bg-gray-100"
+                        }
+                    }
+
+                    on:click=handle_request
+                    disabled=move || !valid_email() || request_action.pending().get()
+                >
+                    Send reset link
+                </button>
+            </form>
+        </div>
+    }
+}
+
+/// Sets the new password with the token received by email.
+#[component]
+pub fn ResetPwdForm(token: String) -> impl IntoView {
+    let (error, set_error) = create_signal::<Option<Error>>(None);
+    let (done, set_done) = create_signal(false);
+    let (pwd, set_pwd) = create_signal::<String>(String::new());
+    let (pwd_errors, set_pwd_errors) = create_signal::<Vec<String>>(Vec::new());
+    let (token, _) = create_signal(token);
+
+    let reset_action = create_action(|input: &(String, String)| {
+        let (token, pwd) = input.clone();
+        async move { reset_pwd(token, pwd).await }
+    });
+
+    let handle_reset = move |_: MouseEvent| {
+        spawn_local(async move { reset_action.dispatch((token.get(), pwd.get())) })
+    };
+
+    create_effect(move |_| {
+        if let Some(res) = reset_action.value().get() {
+            match res {
+                Ok(()) => {
+                    set_error.set(None);
+                    set_done.set(true);
+                }
+                Err(ServerFnError::WrappedServerError(se @ ServerError::InvalidFields { .. })) => {
+                    set_pwd_errors.set(se.field_errors("pwd"));
+                    set_error.set(Some(Error::InvalidFields));
+                }
+                Err(ServerFnError::WrappedServerError(ServerError::PwdResetTokenInvalid)) => {
+                    set_error.set(Some(Error::InvalidResetToken))
+                }
+                Err(_) => set_error.set(Some(Error::TryLater)),
+            }
+        }
+    });
+
+    view! {
+        <div class="font-serif mx-auto bg-gray-300 rounded-md shadow-md w-2/4 p-3">
+            <ErrorAlert error=error/>
+            <Show
+                when=move || !done.get()
+                fallback=|| {
+                    view! {
+                        <div class="bg-lime-200 p-2 text-center rounded-md mb-4">
+                            <a href="/">"Password changed, you can now sign in."</a>
+                        </div>
+                    }
+                }
+            >
+
+                <form class="flex flex-col" on:submit=|ev: SubmitEvent| ev.prevent_default()>
+                    <div class="flex flex-col mb-3">
+                        <label class="mb-2" for="pwd-input">
+                            New password:
+                        </label>
+                        <input
+                            class="bg-white rounded-md h-8 p-2"
+                            type="password"
+                            placeholder="*************"
+                            id="pwd-input"
+                            on:input=move |ev| {
+                                set_pwd_errors.set(Vec::new());
+                                set_pwd.set(event_target_value(&ev))
+                            }
+
+                            prop:value=pwd
+                        />
+                    </div>
+                    <PwdPolicyFeedback pwd=pwd server_errors=pwd_errors/>
+                    <button
+                        class="mt-5 rounded-md h-8 bg-lime-300 hover:bg-lime-100"
+                        on:click=handle_reset
+                        // the email rule is checked by the server
+                        disabled=move || {
+                            !check_pwd(&pwd.get(), "").is_empty() || reset_action.pending().get()
+                        }
+                    >
+                        Change password
+                    </button>
+                </form>
+            </Show>
+        </div>
+    }
+}
@@ -0,0 +1,124 @@
+use crate::components::ErrorAlert;
+use crate::server_fns::ServerError;
+use crate::Error;
+use leptos::{component, create_action, create_effect, create_signal, spawn_local};
+use leptos::{server, view, Callable, Callback, IntoView, ServerFnError, SignalGet, SignalSet};
+use web_sys::MouseEvent;
+
+// region:        --- Server functions
+
+/// Ends the session of this browser and revokes its "remember me" token, the
+/// cookies are cleared even without them.
+#[server]
+async fn logout() -> Result<(), ServerFnError<ServerError>> {
+    use leptos::{expect_context, use_context};
+    use leptos_axum::extract;
+    use lib_core::ctx::Ctx;
+    use lib_core::model::app_state::AppState;
+    use lib_core::model::refresh_token::revoke_refresh_token;
+    use lib_core::model::session::delete_session;
+    use lib_core::web::{get_refresh_token, remove_refresh_cookie, remove_token_cookie};
+    use tower_cookies::Cookies;
+
+    let app_state: AppState = expect_context();
+    let cookies: Cookies = extract().await.map_err(|_| ServerError::TryAgain)?;
+
+    if let Some(ctx) = use_context::<Ctx>() {
+        if let Some(session_id) = ctx.session_id() {
+            delete_session(app_state.mm.clone(), session_id)
+                .await
+                .map_err(|_| ServerError::TryAgain)?;
+        }
+    }
+    if let Some(token) = get_refresh_token(&cookies) {
+        revoke_refresh_token(app_state.mm.clone(), &token)
+            .await
+            .map_err(|_| ServerError::TryAgain)?;
+    }
+    remove_token_cookie(&cookies);
+    remove_refresh_cookie(&cookies);
+
+    Ok(())
+}
+
+/// Ends every session of the user, on all devices. The signed in user when
+/// impersonating.
+#[server]
+async fn logout_everywhere() -> Result<u64, ServerFnError<ServerError>> {
+    use crate::server_fns::require_ctx;
+    use leptos::expect_context;
+    use leptos_axum::extract;
+    use lib_core::model::app_state::AppState;
+    use lib_core::model::session::revoke_user_sessions;
+    use lib_core::web::{remove_refresh_cookie, remove_token_cookie};
+    use tower_cookies::Cookies;
+
+    let ctx = require_ctx()?;
+    let app_state: AppState = expect_context();
+    let cookies: Cookies = extract().await.map_err(|_| ServerError::TryAgain)?;
+
+    let count = revoke_user_sessions(app_state.mm.clone(), ctx.real_user_id())
+        .await
+        .map_err(|_| ServerError::TryAgain)?;
+    remove_token_cookie(&cookies);
+    remove_refresh_cookie(&cookies);
+
+    Ok(count)
+}
+
+// endregion:     --- Server functions
+
+#[component]
+pub fn SignOut(#[prop(into)] on_signed_out: Callback<()>) -> impl IntoView {
+    let (error, set_error) = create_signal::<Option<Error>>(None);
+
+    let logout_action = create_action(|everywhere: &bool| {
+        let everywhere = *everywhere;
+        async move {
+            if everywhere {
+                logout_everywhere().await.map(|_| ())
+            } else {
+                logout().await
+            }
+        }
+    });
+
+    let handle_logout = move |everywhere: bool| {
+        move |_: MouseEvent| spawn_local(async move { logout_action.dispatch(everywhere) })
+    };
+
+    create_effect(move |_| {
+        if let Some(res) = logout_action.value().get() {
+            match res {
+                Ok(()) => {
+                    set_error.set(None);
+                    on_signed_out.call(());
+                }
+                Err(ServerFnError::WrappedServerError(ServerError::Unauthorized)) => {
+                    set_error.set(Some(Error::Unauthorized))
+                }
+                Err(_) => set_error.set(Some(Error::TryLater)),
+            }
+        }
+    });
+
+    view! {
+        <ErrorAlert error=error/>
+        <div class="flex gap-2 mb-4">
+            <button
+                class="flex-1 rounded-md h-8 bg-gray-100 hover:bg-white"
+                on:click=handle_logout(false)
+                disabled=move || logout_action.pending().get()
+            >
+                Sign out
+            </button>
+            <button
+                class="flex-1 rounded-md h-8 bg-gray-100 hover:bg-white"
+                on:click=handle_logout(true)
+                disabled=move || logout_action.pending().get()
+            >
+                Sign out of all devices
+            </button>
+        </div>
+    }
+}
@@ -0,0 +1,216 @@
+use crate::components::ErrorAlert;
+use crate::server_fns::ServerError;
+use crate::Error;
+use leptos::{component, create_action, create_effect, create_signal, event_target_value};
+use leptos::{server, spawn_local, ServerFnError};
+use leptos::{view, CollectView, IntoView, Show, SignalGet, SignalSet};
+use serde::{Deserialize, Serialize};
+use web_sys::{MouseEvent, SubmitEvent};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TotpEnrollmentResponse {
+    pub uri: String,
+    pub qr_svg: String,
+}
+
+// region:        --- Server functions
+
+#[server]
+async fn start_totp_enrollment() -> Result<TotpEnrollmentResponse, ServerFnError<ServerError>> {
+    use crate::server_fns::require_ctx;
+    use lib_core::model::app_state::AppState;
+    use lib_core::model::impersonation::check_not_impersonating;
+    use lib_core::model::user::get_user_for_login_by_id;
+    use lib_core::model::user_totp;
+    use lib_core::model::Error;
+
+    let ctx = require_ctx()?;
+    check_not_impersonating(&ctx).map_err(|_| ServerError::NotAllowedWhileImpersonating)?;
+    let app_state: AppState = leptos::expect_context();
+    let mm = app_state.mm.clone();
+
+    let user = get_user_for_login_by_id(mm.clone(), ctx.user_id())
+        .await
+        .map_err(|_| ServerError::TryAgain)?
+        .ok_or(ServerError::Unauthorized)?;
+
+    match user_totp::start_totp_enrollment(mm, user.id, &user.email).await {
+        Ok(enrollment) => Ok(TotpEnrollmentResponse {
+            uri: enrollment.uri,
+            qr_svg: enrollment.qr_svg,
+        }),
+        Err(Error::TotpAlreadyEnabled) => Err(ServerError::TotpAlreadyEnabled.into()),
+        Err(_) => Err(ServerError::TryAgain.into()),
+    }
+}
+
+/// Returns the recovery codes, shown only once.
+#[server]
+async fn enable_totp(code: String) -> Result<Vec<String>, ServerFnError<ServerError>> {
+    use crate::server_fns::require_ctx;
+    use lib_core::model::app_state::AppState;
+    use lib_core::model::impersonation::check_not_impersonating;
+    use lib_core::model::user_totp;
+    use lib_core::model::Error;
+
+    let ctx = require_ctx()?;
+    check_not_impersonating(&ctx).map_err(|_| ServerError::NotAllowedWhileImpersonating)?;
+    let app_state: AppState = leptos::expect_context();
+
+    match user_totp::enable_totp(app_state.mm.clone(), ctx.user_id(), &code).await {
+        Ok(recovery_codes) => Ok(recovery_codes),
+        Err(Error::TotpInvalidCode | Error::TotpNotEnrolled) => {
+            Err(ServerError::TotpInvalidCode.into())
+        }
+        Err(Error::TotpAlreadyEnabled) => Err(ServerError::TotpAlreadyEnabled.into()),
+        Err(_) => Err(ServerError::TryAgain.into()),
+    }
+}
+
+// endregion:     --- Server functions
+
+#[component]
+pub fn TotpSetup() -> impl IntoView {
+    let (error, set_error) = create_signal::<Option<Error>>(None);
+    let (enrollment, set_enrollment) = create_signal::<Option<TotpEnrollmentResponse>>(None);
+    let (recovery_codes, set_recovery_codes) = create_signal::<Vec<String>>(Vec::new());
+    let (code, set_code) = create_signal::<String>(String::new());
+
+    let to_error = |e: ServerFnError<ServerError>| match e {
+        ServerFnError::WrappedServerError(ServerError::Unauthorized) => Error::Unauthorized,
+        ServerFnError::WrappedServerError(ServerError::TotpInvalidCode) => Error::InvalidTotpCode,
+        ServerFnError::WrappedServerError(ServerError::TotpAlreadyEnabled) => {
+            Error::TotpAlreadyEnabled
+        }
+        ServerFnError::WrappedServerError(ServerError::NotAllowedWhileImpersonating) => {
+            Error::NotAllowedWhileImpersonating
+        }
+        _ => Error::TryLater,
+    };
+
+    // region:        --- Start action
+
+    let start_action = create_action(|_: &()| start_totp_enrollment());
+
+    let handle_start = move |_: MouseEvent| spawn_local(async move { start_action.dispatch(()) });
+
+    create_effect(move |_| {
+        if let Some(res) = start_action.value().get() {
+            match res {
+                Ok(res) => {
+                    set_error.set(None);
+                    set_enrollment.set(Some(res));
+                }
+                Err(e) => set_error.set(Some(to_error(e))),
+            }
+        }
+    });
+
+    // endregion:     --- Start action
+
+    // region:        --- Enable action
+
+    let enable_action = create_action(|code: &String| {
+        let code = code.clone();
+        async move { enable_totp(code).await }
+    });
+
+    let handle_enable =
+        move |_: MouseEvent| spawn_local(async move { enable_action.dispatch(code.get()) });
+
+    create_effect(move |_| {
+        if let Some(res) = enable_action.value().get() {
+            match res {
+                Ok(codes) => {
+                    set_error.set(None);
+                    set_enrollment.set(None);
+                    set_recovery_codes.set(codes);
+                }
+                Err(e) => set_error.set(Some(to_error(e))),
+            }
+        }
+    });
+
+    // endregion:     --- Enable action
+
+    view! {
+        <div class="font-serif mx-auto bg-gray-300 rounded-md shadow-md w-2/4 p-3">
+            <ErrorAlert error=error/>
+
+            <Show
+                when=move || recovery_codes.get().is_empty()
+                fallback=move || {
+                    view! {
+                        <div class="bg-lime-200 p-2 rounded-md mb-4">
+                            <p class="mb-2">
+                                "Two-factor authentication enabled. Keep these recovery codes, each works once:"
+                            </p>
+                            <ul class="font-mono">
+                                {move || {
+                                    recovery_codes
+                                        .get()
+                                        .into_iter()
+                                        .map(|code| view! { <li>{code}</li> })
+                                        .collect_view()
+                                }}
+
+                            </ul>
+                        </div>
+                    }
+                }
+            >
+
+                {move || match enrollment.get() {
+                    None => {
+                        view! {
+                            <button
+                                class="w-full rounded-md h-8 bg-lime-300 hover:bg-lime-100"
+                                on:click=handle_start
+                                disabled=move || start_action.pending().get()
+                            >
+                                Set up two-factor authentication
+                            </button>
+                        }
+                            .into_view()
+                    }
+                    Some(enrollment) => {
+                        view! {
+                            <p class="mb-2">"Scan this code with your authenticator app:"</p>
+                            <div class="flex justify-center mb-2" inner_html=enrollment.qr_svg></div>
+                            <p class="mb-4 text-xs break-all">{enrollment.uri}</p>
+                            <form
+                                class="flex flex-col"
+                                on:submit=|ev: SubmitEvent| ev.prevent_default()
+                            >
+                                <label class="mb-2" for="totp-input">
+                                    "Code from the app:"
+                                </label>
+                                <input
+                                    class="bg-white rounded-md h-8 p-2"
+                                    type="text"
+                                    autocomplete="one-time-code"
+                                    placeholder="123456"
+                                    id="totp-input"
+                                    on:input=move |ev| { set_code.set(event_target_value(&ev)) }
+                                    prop:value=code
+                                />
+                                <button
+                                    class="mt-3 rounded-md h-8 bg-lime-300 hover:bg-lime-100"
+                                    on:click=handle_enable
+                                    disabled=move || {
+                                        code.get().is_empty() || enable_action.pending().get()
+                                    }
+                                >
+
+                                    Enable
+                                </button>
+                            </form>
+                        }
+                            .into_view()
+                    }
+                }}
+
+            </Show>
+        </div>
+    }
+}
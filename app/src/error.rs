@@ -6,9 +6,27 @@ pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Debug, Clone, From)]
 pub enum Error {
-    ServerError { code: i64 },
+    ServerError {
+        code: i64,
+    },
     TryLater,
     Unauthorized,
+    InvalidCredentials,
+    InvalidResetToken,
+    EmailNotVerified,
+    InvalidVerifyToken,
+    InvalidInvitationToken,
+    EmailAlreadyExists,
+    RegistrationClosed,
+    /// Details are shown next to the fields.
+    InvalidFields,
+    InvalidTotpCode,
+    TotpAlreadyEnabled,
+    #[from(ignore)]
+    AccountLocked {
+        retry_after_sec: i64,
+    },
+    NotAllowedWhileImpersonating,
     CannotConvertToString,
 
     // -- Server
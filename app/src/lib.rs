@@ -20,9 +20,14 @@ pub fn App() -> impl IntoView {
         <Title text="Client intranet"/>
         <Router fallback=|| pages::Page404.into_view()>
             <main class="bg-gradient-to-tr from-blue-100 to-blue-50 min-h-screen p-7">
+                <components::ImpersonationBanner/>
                 <Routes>
                     <Route path="/" view=pages::Login/>
                     <Route path="/error" view=pages::Error/>
+                    <Route path="/reset-password" view=pages::ResetPassword/>
+                    <Route path="/verify-email" view=pages::VerifyEmail/>
+                    <Route path="/accept-invitation" view=pages::AcceptInvitation/>
+                    <Route path="/account/2fa" view=pages::TwoFactor/>
                 </Routes>
             </main>
         </Router>
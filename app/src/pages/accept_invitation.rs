@@ -0,0 +1,26 @@
+use crate::components::{AcceptInvitationForm, ErrorAlert};
+use crate::Error;
+use leptos::{component, create_signal, view, IntoView, SignalWith};
+use leptos_router::use_query_map;
+
+#[component]
+pub fn AcceptInvitation() -> impl IntoView {
+    let query = use_query_map();
+    let token = move || query.with(|q| q.get("token").cloned());
+
+    view! {
+        <h1 class="text-4xl text-center font-serif my-5">Activate your account</h1>
+        {move || match token() {
+            Some(token) => view! { <AcceptInvitationForm token=token/> }.into_view(),
+            None => {
+                let (error, _) = create_signal(Some(Error::InvalidInvitationToken));
+                view! {
+                    <div class="font-serif mx-auto bg-gray-300 rounded-md shadow-md w-2/4 p-3">
+                        <ErrorAlert error=error/>
+                    </div>
+                }
+                    .into_view()
+            }
+        }}
+    }
+}
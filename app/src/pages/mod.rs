@@ -1,7 +1,15 @@
+mod accept_invitation;
 mod error;
 mod login;
 mod page_404;
+mod reset_password;
+mod two_factor;
+mod verify_email;
 
+pub use accept_invitation::AcceptInvitation;
 pub use error::Error;
 pub use login::Login;
 pub use page_404::Page404;
+pub use reset_password::ResetPassword;
+pub use two_factor::TwoFactor;
+pub use verify_email::VerifyEmail;
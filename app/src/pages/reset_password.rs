@@ -0,0 +1,17 @@
+use crate::components::{RequestPwdResetForm, ResetPwdForm};
+use leptos::{component, view, IntoView, SignalWith};
+use leptos_router::use_query_map;
+
+#[component]
+pub fn ResetPassword() -> impl IntoView {
+    let query = use_query_map();
+    let token = move || query.with(|q| q.get("token").cloned());
+
+    view! {
+        <h1 class="text-4xl text-center font-serif my-5">Reset your password</h1>
+        {move || match token() {
+            Some(token) => view! { <ResetPwdForm token=token/> }.into_view(),
+            None => view! { <RequestPwdResetForm/> }.into_view(),
+        }}
+    }
+}
@@ -0,0 +1,10 @@
+use crate::components::TotpSetup;
+use leptos::{component, view, IntoView};
+
+#[component]
+pub fn TwoFactor() -> impl IntoView {
+    view! {
+        <h1 class="text-4xl text-center font-serif my-5">Two-factor authentication</h1>
+        <TotpSetup/>
+    }
+}
@@ -0,0 +1,65 @@
+use crate::components::ErrorAlert;
+use crate::server_fns::ServerError;
+use crate::Error;
+use leptos::{component, create_resource, create_signal, server, view, IntoView};
+use leptos::{ServerFnError, SignalGet, SignalWith, Suspense, View};
+use leptos_router::use_query_map;
+
+#[server]
+async fn verify_email(token: String) -> Result<(), ServerFnError<ServerError>> {
+    use leptos::expect_context;
+    use lib_core::model::app_state::AppState;
+    use lib_core::model::Error;
+
+    let app_state: AppState = expect_context();
+
+    match lib_core::model::email_verify::verify_email(app_state.mm.clone(), &token).await {
+        Ok(_) => Ok(()),
+        Err(Error::EmailVerifyTokenInvalid) => Err(ServerError::EmailVerifyTokenInvalid.into()),
+        Err(_) => Err(ServerError::TryAgain.into()),
+    }
+}
+
+#[component]
+pub fn VerifyEmail() -> impl IntoView {
+    let query = use_query_map();
+    let token = move || query.with(|q| q.get("token").cloned().unwrap_or_default());
+
+    let verification = create_resource(token, verify_email);
+
+    view! {
+        <h1 class="text-4xl text-center font-serif my-5">Email verification</h1>
+        <div class="font-serif mx-auto bg-gray-300 rounded-md shadow-md w-2/4 p-3">
+            <Suspense fallback=|| {
+                view! {
+                    <div class="bg-yellow-200 p-2 text-center rounded-md mb-4">Loading...</div>
+                }
+            }>
+                {move || {
+                    verification
+                        .get()
+                        .map(|res| match res {
+                            Ok(()) => {
+                                view! {
+                                    <div class="bg-lime-200 p-2 text-center rounded-md mb-4">
+                                        <a href="/">"Email verified, you can now sign in."</a>
+                                    </div>
+                                }
+                                    .into_view()
+                            }
+                            Err(ServerFnError::WrappedServerError(
+                                ServerError::EmailVerifyTokenInvalid,
+                            )) => error_alert(Error::InvalidVerifyToken),
+                            Err(_) => error_alert(Error::TryLater),
+                        })
+                }}
+
+            </Suspense>
+        </div>
+    }
+}
+
+fn error_alert(error: Error) -> View {
+    let (error, _) = create_signal(Some(error));
+    view! { <ErrorAlert error=error/> }.into_view()
+}
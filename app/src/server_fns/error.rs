@@ -1,28 +1,74 @@
 use std::str::FromStr;
 
 use leptos::ServerFnError;
+use lib_utils::pwd_policy::{pwd_field_errors, PwdIssue};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::collections::BTreeMap;
 
 pub type ServerResult<T> = core::result::Result<T, ServerFnError<ServerError>>;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum ServerError {
-    // -- SQL constraints (data already exist in DB)
     TryAgain,
 
-    // -- Data saved
-    CannotLogin { code: i64 },
+    // -- SQL constraints (data already exist in DB)
+    EmailAlreadyExists,
+
+    // -- Registration
+    RegistrationClosed,
+    InvitationTokenInvalid,
+
+    // -- Validation, messages by field name
+    InvalidFields {
+        fields: BTreeMap<String, Vec<String>>,
+    },
+    InvalidListQuery {
+        reason: String,
+    },
+
+    // -- Auth
+    LoginFail,
+    Unauthorized,
+    PermissionDenied,
+    PwdResetTokenInvalid,
+    EmailNotVerified,
+    EmailVerifyTokenInvalid,
+    TotpInvalidCode,
+    TotpAlreadyEnabled,
+    AccountLocked {
+        retry_after_sec: i64,
+    },
+    NotAllowedWhileImpersonating,
 
     // -- Leptos server error
     ServerFunction(String),
 }
 
+impl ServerError {
+    /// Password rejected by the policy, as the errors of the `pwd` field.
+    pub fn pwd_policy(issues: &[PwdIssue]) -> Self {
+        Self::InvalidFields {
+            fields: pwd_field_errors(issues),
+        }
+    }
+
+    /// Messages of the field, empty when it is valid.
+    pub fn field_errors(&self, field: &str) -> Vec<String> {
+        match self {
+            Self::InvalidFields { fields } => fields.get(field).cloned().unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Leptos sends `WrappedServerError` to the client with `Display` and reads
+/// it back with `FromStr`, JSON keeps the variant typed on both sides.
 impl FromStr for ServerError {
     type Err = Self;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self::ServerFunction(s.to_string()))
+        Ok(serde_json::from_str(s).unwrap_or_else(|_| Self::ServerFunction(s.to_string())))
     }
 }
 
@@ -30,7 +76,10 @@ impl FromStr for ServerError {
 
 impl core::fmt::Display for ServerError {
     fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
-        write!(fmt, "{self:?}")
+        match serde_json::to_string(self) {
+            Ok(json) => write!(fmt, "{json}"),
+            Err(_) => write!(fmt, "{self:?}"),
+        }
     }
 }
 
@@ -48,11 +97,40 @@ pub fn serialize_error_response(error: ServerFnError<ServerError>) -> Value {
     match error {
         // fake errors from backend business logic
         ServerFnError::WrappedServerError(se) => match se {
-            ServerError::CannotLogin { code } => {
+            ServerError::EmailAlreadyExists => {
+                json!({
+                  "error":{
+                    "message":"Email already registered",
+                  }
+                })
+            }
+            ServerError::RegistrationClosed => {
+                json!({
+                  "error":{
+                    "message":"Registration is by invitation only",
+                  }
+                })
+            }
+            ServerError::InvitationTokenInvalid => {
+                json!({
+                  "error":{
+                    "message":"Invitation link invalid or expired",
+                  }
+                })
+            }
+            ServerError::InvalidFields { fields } => {
+                json!({
+                  "error":{
+                    "message":"Invalid fields",
+                    "detail":{ "fields": fields },
+                  }
+                })
+            }
+            ServerError::InvalidListQuery { reason } => {
                 json!({
                   "error":{
-                    "message":"Cannot login",
-                    "code":code
+                    "message":"Invalid list query",
+                    "detail":{ "reason": reason },
                   }
                 })
             }
@@ -63,6 +141,77 @@ pub fn serialize_error_response(error: ServerFnError<ServerError>) -> Value {
                   }
                 })
             }
+            ServerError::LoginFail => {
+                json!({
+                  "error":{
+                    "message":"Wrong email or password",
+                  }
+                })
+            }
+            ServerError::Unauthorized => {
+                json!({
+                  "error":{
+                    "message":"Please login first",
+                  }
+                })
+            }
+            ServerError::PermissionDenied => {
+                json!({
+                  "error":{
+                    "message":"Permission denied",
+                  }
+                })
+            }
+            ServerError::PwdResetTokenInvalid => {
+                json!({
+                  "error":{
+                    "message":"Reset link invalid or expired",
+                  }
+                })
+            }
+            ServerError::EmailNotVerified => {
+                json!({
+                  "error":{
+                    "message":"Please verify your email first",
+                  }
+                })
+            }
+            ServerError::EmailVerifyTokenInvalid => {
+                json!({
+                  "error":{
+                    "message":"Verification link invalid or expired",
+                  }
+                })
+            }
+            ServerError::TotpInvalidCode => {
+                json!({
+                  "error":{
+                    "message":"Invalid authentication code",
+                  }
+                })
+            }
+            ServerError::TotpAlreadyEnabled => {
+                json!({
+                  "error":{
+                    "message":"Two-factor authentication already enabled",
+                  }
+                })
+            }
+            ServerError::AccountLocked { retry_after_sec } => {
+                json!({
+                  "error":{
+                    "message":"Too many failed attempts",
+                    "detail":{ "retry_after_sec": retry_after_sec },
+                  }
+                })
+            }
+            ServerError::NotAllowedWhileImpersonating => {
+                json!({
+                  "error":{
+                    "message":"Not allowed while impersonating",
+                  }
+                })
+            }
             ServerError::ServerFunction(_) => generic_error,
         },
 
@@ -70,3 +219,38 @@ pub fn serialize_error_response(error: ServerFnError<ServerError>) -> Value {
         _ => generic_error,
     }
 }
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+    use leptos::server_fn::error::ServerFnErrorSerde;
+
+    #[test]
+    fn test_server_error_round_trip() -> Result<()> {
+        let error: ServerFnError<ServerError> = ServerError::LoginFail.into();
+
+        let serialized = error.ser()?;
+        let deserialized = ServerFnError::<ServerError>::de(&serialized);
+
+        assert!(matches!(
+            deserialized,
+            ServerFnError::WrappedServerError(ServerError::LoginFail)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_server_error_from_plain_str() -> Result<()> {
+        let error = ServerError::from_str("not json")?;
+
+        assert!(matches!(error, ServerError::ServerFunction(s) if s == "not json"));
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
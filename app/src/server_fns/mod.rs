@@ -1,3 +1,67 @@
 pub mod error;
+pub mod users;
 
 pub use error::{ServerError, ServerResult};
+
+/// `Ctx` provided by the server handlers, `Unauthorized` if the user is not logged in.
+/// The API keys have no session and are for the REST API only.
+#[cfg(feature = "ssr")]
+pub fn require_ctx() -> ServerResult<lib_core::ctx::Ctx> {
+    leptos::use_context::<lib_core::ctx::Ctx>()
+        .filter(|ctx| ctx.session_id().is_some())
+        .ok_or_else(|| ServerError::Unauthorized.into())
+}
+
+/// `Ctx` of a logged in user holding `permission`, to call first in a server function.
+///
+/// ```ignore
+/// let ctx = require_permission(perms::USER_READ).await?;
+/// ```
+#[cfg(feature = "ssr")]
+pub async fn require_permission(permission: &str) -> ServerResult<lib_core::ctx::Ctx> {
+    use lib_core::model::{app_state::AppState, rbac::check_permission, Error};
+
+    let ctx = require_ctx()?;
+    let app_state: AppState = leptos::expect_context();
+
+    match check_permission(app_state.mm.clone(), &ctx, permission).await {
+        Ok(()) => Ok(ctx),
+        Err(Error::PermissionDenied { .. }) => Err(ServerError::PermissionDenied.into()),
+        Err(_) => Err(ServerError::TryAgain.into()),
+    }
+}
+
+/// Address of the client, `None` if the server was not started with connect info.
+#[cfg(feature = "ssr")]
+pub async fn client_ip() -> Option<String> {
+    use axum::extract::ConnectInfo;
+    use std::net::SocketAddr;
+
+    let ConnectInfo(addr) = leptos_axum::extract::<ConnectInfo<SocketAddr>>()
+        .await
+        .ok()?;
+    Some(addr.ip().to_string())
+}
+
+/// Label of the device, its `User-Agent`, for the "remember me" tokens.
+#[cfg(feature = "ssr")]
+pub async fn device_label() -> String {
+    use axum::http::{header::USER_AGENT, HeaderMap};
+
+    leptos_axum::extract::<HeaderMap>()
+        .await
+        .ok()
+        .and_then(|headers| Some(headers.get(USER_AGENT)?.to_str().ok()?.to_string()))
+        .unwrap_or_else(|| "Unknown device".to_string())
+}
+
+/// `AccountLocked` for the login and reset throttles, `TryAgain` for anything else.
+#[cfg(feature = "ssr")]
+pub fn throttle_error(ex: lib_core::model::Error) -> leptos::ServerFnError<ServerError> {
+    match ex {
+        lib_core::model::Error::LoginLocked { retry_after_sec } => {
+            ServerError::AccountLocked { retry_after_sec }.into()
+        }
+        _ => ServerError::TryAgain.into(),
+    }
+}
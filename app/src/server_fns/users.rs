@@ -0,0 +1,55 @@
+use crate::server_fns::ServerError;
+use leptos::server_fn::codec::Json;
+use leptos::{server, ServerFnError};
+use lib_utils::list::ListOptions;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserItem {
+    pub id: i64,
+    pub email: String,
+    pub display_name: Option<String>,
+    pub is_active: bool,
+    pub created_at: i64,
+}
+
+/// A page of the users matching `filters`, written as described in
+/// `lib_core::model::list`. Sent as JSON to keep the filter values typed.
+#[server(input = Json)]
+pub async fn list_users(
+    filters: Option<Value>,
+    list_options: Option<ListOptions>,
+) -> Result<Vec<UserItem>, ServerFnError<ServerError>> {
+    use crate::server_fns::require_permission;
+    use leptos::expect_context;
+    use lib_core::model::app_state::AppState;
+    use lib_core::model::list::ListFilter;
+    use lib_core::model::{rbac::perms, user, Error};
+
+    require_permission(perms::USER_READ).await?;
+    let app_state: AppState = expect_context();
+
+    let list_error = |ex| match ex {
+        Error::ListQueryInvalid { reason } => ServerError::InvalidListQuery { reason },
+        _ => ServerError::TryAgain,
+    };
+    let filter = filters
+        .map(ListFilter::from_value)
+        .transpose()
+        .map_err(list_error)?;
+    let users = user::list_users(app_state.mm.clone(), filter, list_options)
+        .await
+        .map_err(list_error)?;
+
+    Ok(users
+        .into_iter()
+        .map(|user| UserItem {
+            id: user.id,
+            email: user.email,
+            display_name: user.display_name,
+            is_active: user.is_active,
+            created_at: user.created_at,
+        })
+        .collect())
+}
@@ -15,12 +15,12 @@ mod tests {
 
     #[test]
     fn test_validate_email_ok() -> Result<()> {
-        assert_eq!(true, validate_email("popo@momo.com"));
+        assert!(validate_email("popo@momo.com"));
         Ok(())
     }
     #[test]
     fn test_validate_email_false() -> Result<()> {
-        assert_eq!(false, validate_email("popom"));
+        assert!(!validate_email("popom"));
         Ok(())
     }
 }
@@ -0,0 +1,7 @@
+-- Schema of the released app, as the one of SQLite. Timestamps are unix seconds.
+
+CREATE TABLE "user" (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    email varchar(128) NOT NULL UNIQUE,
+    pwd varchar(256)
+);
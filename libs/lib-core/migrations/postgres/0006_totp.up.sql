@@ -0,0 +1,13 @@
+CREATE TABLE user_totp (
+    user_id BIGINT PRIMARY KEY REFERENCES "user"(id) ON DELETE CASCADE,
+    secret_enc varchar(128) NOT NULL,
+    enabled_at BIGINT,
+    last_step BIGINT
+);
+
+CREATE TABLE recovery_code (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+    code_hash varchar(64) NOT NULL UNIQUE,
+    used_at BIGINT
+);
@@ -0,0 +1,8 @@
+CREATE TABLE login_throttle (
+    scope varchar(16) NOT NULL,
+    subject varchar(128) NOT NULL,
+    failures BIGINT NOT NULL,
+    last_failed_at BIGINT NOT NULL,
+    blocked_until BIGINT NOT NULL,
+    PRIMARY KEY (scope, subject)
+);
@@ -0,0 +1,11 @@
+CREATE TABLE api_key (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+    name varchar(128) NOT NULL,
+    key_hash varchar(64) NOT NULL UNIQUE,
+    scopes varchar(1024) NOT NULL,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT,
+    last_used_at BIGINT,
+    revoked_at BIGINT
+);
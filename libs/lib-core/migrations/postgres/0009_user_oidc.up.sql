@@ -0,0 +1,7 @@
+CREATE TABLE user_oidc (
+    issuer varchar(256) NOT NULL,
+    subject varchar(256) NOT NULL,
+    user_id BIGINT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+    linked_at BIGINT NOT NULL,
+    PRIMARY KEY (issuer, subject)
+);
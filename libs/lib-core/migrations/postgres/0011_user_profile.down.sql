@@ -0,0 +1,4 @@
+ALTER TABLE "user" DROP COLUMN updated_at;
+ALTER TABLE "user" DROP COLUMN created_at;
+ALTER TABLE "user" DROP COLUMN is_active;
+ALTER TABLE "user" DROP COLUMN display_name;
@@ -0,0 +1,6 @@
+-- Timestamps of the users created before are 0.
+
+ALTER TABLE "user" ADD COLUMN display_name varchar(128);
+ALTER TABLE "user" ADD COLUMN is_active BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE "user" ADD COLUMN created_at BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE "user" ADD COLUMN updated_at BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,10 @@
+CREATE TABLE invitation (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    email varchar(128) NOT NULL,
+    role_id BIGINT REFERENCES role(id) ON DELETE SET NULL,
+    token_hash varchar(64) NOT NULL UNIQUE,
+    invited_by BIGINT REFERENCES "user"(id) ON DELETE SET NULL,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    accepted_at BIGINT
+);
@@ -0,0 +1,11 @@
+ALTER TABLE session ADD COLUMN impersonated_user_id BIGINT REFERENCES "user"(id) ON DELETE SET NULL;
+
+CREATE TABLE impersonation_log (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    real_user_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    session_id varchar(36) NOT NULL,
+    action varchar(16) NOT NULL,
+    detail TEXT,
+    created_at BIGINT NOT NULL
+);
@@ -0,0 +1,11 @@
+CREATE TABLE refresh_token (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    family_id varchar(36) NOT NULL,
+    user_id BIGINT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+    token_hash varchar(64) NOT NULL UNIQUE,
+    device varchar(128) NOT NULL,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    used_at BIGINT,
+    revoked_at BIGINT
+);
@@ -0,0 +1,8 @@
+-- Schema of the released app, before the versioned migrations. `IF NOT EXISTS`
+-- adopts the databases it created, the next migrations bring them up to date.
+
+CREATE TABLE IF NOT EXISTS user (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    email varchar(128) NOT NULL UNIQUE,
+    pwd varchar(256)
+);
@@ -0,0 +1,6 @@
+CREATE TABLE session (
+    id varchar(36) PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES user(id) ON DELETE CASCADE,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL
+);
@@ -0,0 +1,21 @@
+CREATE TABLE role (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name varchar(64) NOT NULL UNIQUE
+);
+
+CREATE TABLE permission (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name varchar(128) NOT NULL UNIQUE
+);
+
+CREATE TABLE role_permission (
+    role_id INTEGER NOT NULL REFERENCES role(id) ON DELETE CASCADE,
+    permission_id INTEGER NOT NULL REFERENCES permission(id) ON DELETE CASCADE,
+    PRIMARY KEY (role_id, permission_id)
+);
+
+CREATE TABLE user_role (
+    user_id INTEGER NOT NULL REFERENCES user(id) ON DELETE CASCADE,
+    role_id INTEGER NOT NULL REFERENCES role(id) ON DELETE CASCADE,
+    PRIMARY KEY (user_id, role_id)
+);
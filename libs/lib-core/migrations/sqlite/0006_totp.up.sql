@@ -0,0 +1,13 @@
+CREATE TABLE user_totp (
+    user_id INTEGER PRIMARY KEY REFERENCES user(id) ON DELETE CASCADE,
+    secret_enc varchar(128) NOT NULL,
+    enabled_at INTEGER,
+    last_step INTEGER
+);
+
+CREATE TABLE recovery_code (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES user(id) ON DELETE CASCADE,
+    code_hash varchar(64) NOT NULL UNIQUE,
+    used_at INTEGER
+);
@@ -0,0 +1,8 @@
+CREATE TABLE login_throttle (
+    scope varchar(16) NOT NULL,
+    subject varchar(128) NOT NULL,
+    failures INTEGER NOT NULL,
+    last_failed_at INTEGER NOT NULL,
+    blocked_until INTEGER NOT NULL,
+    PRIMARY KEY (scope, subject)
+);
@@ -0,0 +1,11 @@
+CREATE TABLE api_key (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES user(id) ON DELETE CASCADE,
+    name varchar(128) NOT NULL,
+    key_hash varchar(64) NOT NULL UNIQUE,
+    scopes varchar(1024) NOT NULL,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER,
+    last_used_at INTEGER,
+    revoked_at INTEGER
+);
@@ -0,0 +1,5 @@
+CREATE TABLE user_ldap (
+    dn varchar(512) PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES user(id) ON DELETE CASCADE,
+    synced_at INTEGER NOT NULL
+);
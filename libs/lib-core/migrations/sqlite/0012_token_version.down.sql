@@ -0,0 +1,2 @@
+ALTER TABLE session DROP COLUMN token_version;
+ALTER TABLE user DROP COLUMN token_version;
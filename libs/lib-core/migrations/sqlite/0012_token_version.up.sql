@@ -0,0 +1,3 @@
+ALTER TABLE user ADD COLUMN token_version INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE session ADD COLUMN token_version INTEGER NOT NULL DEFAULT 0;
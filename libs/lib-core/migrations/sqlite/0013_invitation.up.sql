@@ -0,0 +1,10 @@
+CREATE TABLE invitation (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    email varchar(128) NOT NULL,
+    role_id INTEGER REFERENCES role(id) ON DELETE SET NULL,
+    token_hash varchar(64) NOT NULL UNIQUE,
+    invited_by INTEGER REFERENCES user(id) ON DELETE SET NULL,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    accepted_at INTEGER
+);
@@ -0,0 +1,2 @@
+DROP TABLE impersonation_log;
+ALTER TABLE session DROP COLUMN impersonated_user_id;
@@ -0,0 +1,11 @@
+ALTER TABLE session ADD COLUMN impersonated_user_id INTEGER REFERENCES user(id) ON DELETE SET NULL;
+
+CREATE TABLE impersonation_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    real_user_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    session_id varchar(36) NOT NULL,
+    action varchar(16) NOT NULL,
+    detail TEXT,
+    created_at INTEGER NOT NULL
+);
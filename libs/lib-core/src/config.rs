@@ -1,6 +1,9 @@
+use crate::model::auth_backend::{load_auth_backends_from_env, AuthBackend};
+use crate::model::email_verify::UnverifiedPolicy;
+use crate::oidc::OidcConfig;
 use std::sync::OnceLock;
 
-use lib_utils::envs::get_env;
+use lib_utils::envs::{get_env, get_env_b64u_as_u8s, get_env_parse};
 
 pub fn config() -> &'static Config {
     static INSTANCE: OnceLock<Config> = OnceLock::new();
@@ -14,12 +17,85 @@ pub fn config() -> &'static Config {
 #[allow(non_snake_case)]
 pub struct Config {
     pub DB_URL: String,
+    pub ADMIN_EMAIL: Option<String>,
+    pub WEB_URL: String,
+
+    // -- Auth (backends tried in order at login)
+    pub AUTH_BACKENDS: Vec<AuthBackend>,
+
+    // -- Session
+    pub SESSION_DURATION_SEC: i64,
+    pub SESSION_CLEANUP_INTERVAL_SEC: u64,
+    /// Lifetime of the "remember me" tokens, renewed on each use.
+    pub REFRESH_TOKEN_DURATION_SEC: i64,
+
+    // -- Token
+    pub TOKEN_KEY: Vec<u8>,
+    pub TOKEN_DURATION_SEC: i64,
+
+    // -- Password reset
+    pub PWD_RESET_DURATION_SEC: i64,
+
+    // -- Email verification
+    pub EMAIL_VERIFY_DURATION_SEC: i64,
+    pub UNVERIFIED_POLICY: UnverifiedPolicy,
+
+    // -- Registration (invitations only when closed)
+    pub OPEN_REGISTRATION: bool,
+    pub INVITATION_DURATION_SEC: i64,
+
+    // -- Login throttle
+    pub LOGIN_MAX_FAILURES: i64,
+    pub LOGIN_MAX_FAILURES_PER_IP: i64,
+    pub LOGIN_LOCKOUT_SEC: i64,
+
+    // -- TOTP
+    pub TOTP_KEY: Vec<u8>,
+    pub TOTP_ISSUER: String,
+
+    // -- OIDC (SSO disabled when None)
+    pub OIDC: Option<OidcConfig>,
+
+    // -- Notify
+    pub NOTIFY_FILE: Option<String>,
 }
 
 impl Config {
     pub fn load_from_env() -> lib_utils::Result<Config> {
+        let web_url = get_env("SERVICE_WEB_URL")?;
+
         Ok(Config {
             DB_URL: get_env("SERVICE_DB_URL")?,
+            ADMIN_EMAIL: get_env("SERVICE_ADMIN_EMAIL").ok(),
+            WEB_URL: web_url.clone(),
+
+            AUTH_BACKENDS: load_auth_backends_from_env()?,
+
+            SESSION_DURATION_SEC: get_env_parse("SERVICE_SESSION_DURATION_SEC")?,
+            SESSION_CLEANUP_INTERVAL_SEC: get_env_parse("SERVICE_SESSION_CLEANUP_INTERVAL_SEC")?,
+            REFRESH_TOKEN_DURATION_SEC: get_env_parse("SERVICE_REFRESH_TOKEN_DURATION_SEC")?,
+
+            TOKEN_KEY: get_env_b64u_as_u8s("SERVICE_TOKEN_KEY")?,
+            TOKEN_DURATION_SEC: get_env_parse("SERVICE_TOKEN_DURATION_SEC")?,
+
+            PWD_RESET_DURATION_SEC: get_env_parse("SERVICE_PWD_RESET_DURATION_SEC")?,
+
+            EMAIL_VERIFY_DURATION_SEC: get_env_parse("SERVICE_EMAIL_VERIFY_DURATION_SEC")?,
+            UNVERIFIED_POLICY: get_env_parse("SERVICE_UNVERIFIED_POLICY")?,
+
+            OPEN_REGISTRATION: get_env_parse("SERVICE_OPEN_REGISTRATION")?,
+            INVITATION_DURATION_SEC: get_env_parse("SERVICE_INVITATION_DURATION_SEC")?,
+
+            LOGIN_MAX_FAILURES: get_env_parse("SERVICE_LOGIN_MAX_FAILURES")?,
+            LOGIN_MAX_FAILURES_PER_IP: get_env_parse("SERVICE_LOGIN_MAX_FAILURES_PER_IP")?,
+            LOGIN_LOCKOUT_SEC: get_env_parse("SERVICE_LOGIN_LOCKOUT_SEC")?,
+
+            TOTP_KEY: get_env_b64u_as_u8s("SERVICE_TOTP_KEY")?,
+            TOTP_ISSUER: get_env("SERVICE_TOTP_ISSUER")?,
+
+            OIDC: OidcConfig::load_from_env(&web_url)?,
+
+            NOTIFY_FILE: get_env("SERVICE_NOTIFY_FILE").ok(),
         })
     }
 }
@@ -0,0 +1,85 @@
+//! Request context, resolved once per request from the auth token or API key.
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Ctx {
+    /// Effective user, whose rights apply.
+    user_id: i64,
+    /// User who signed in, differs from `user_id` while impersonating.
+    real_user_id: i64,
+    /// Browser session, `None` for API keys.
+    session_id: Option<String>,
+    /// Permissions an API key is limited to, `None` for the full user rights.
+    scopes: Option<Vec<String>>,
+}
+
+// Constructors
+impl Ctx {
+    pub fn new(user_id: i64, session_id: impl Into<String>) -> Self {
+        Self {
+            user_id,
+            real_user_id: user_id,
+            session_id: Some(session_id.into()),
+            scopes: None,
+        }
+    }
+
+    /// Session of `real_user_id` seeing the app as `user_id`.
+    pub fn new_impersonating(
+        real_user_id: i64,
+        user_id: i64,
+        session_id: impl Into<String>,
+    ) -> Self {
+        Self {
+            user_id,
+            real_user_id,
+            session_id: Some(session_id.into()),
+            scopes: None,
+        }
+    }
+
+    pub fn new_for_api_key(user_id: i64, scopes: Vec<String>) -> Self {
+        Self {
+            user_id,
+            real_user_id: user_id,
+            session_id: None,
+            scopes: Some(scopes),
+        }
+    }
+}
+
+// Property Accessors
+impl Ctx {
+    pub fn user_id(&self) -> i64 {
+        self.user_id
+    }
+
+    pub fn real_user_id(&self) -> i64 {
+        self.real_user_id
+    }
+
+    pub fn is_impersonating(&self) -> bool {
+        self.user_id != self.real_user_id
+    }
+
+    /// Same context with the rights of the real user.
+    pub fn as_real_user(&self) -> Ctx {
+        Self {
+            user_id: self.real_user_id,
+            ..self.clone()
+        }
+    }
+
+    pub fn session_id(&self) -> Option<&str> {
+        self.session_id.as_deref()
+    }
+
+    /// True if the permission is not excluded by the API key scopes, the user
+    /// must still hold it.
+    pub fn in_scope(&self, permission: &str) -> bool {
+        self.scopes
+            .as_ref()
+            .is_none_or(|scopes| scopes.iter().any(|scope| scope == permission))
+    }
+}
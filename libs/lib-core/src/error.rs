@@ -1,4 +1,4 @@
-use crate::model;
+use crate::{model, oidc, pwd, token, totp};
 use derive_more::From;
 
 pub type Result<T> = core::result::Result<T, Error>;
@@ -8,6 +8,14 @@ pub enum Error {
     // -- Modules
     #[from]
     Model(model::Error),
+    #[from]
+    Oidc(oidc::Error),
+    #[from]
+    Pwd(pwd::Error),
+    #[from]
+    Token(token::Error),
+    #[from]
+    Totp(totp::Error),
 }
 
 // region:    --- Error Boilerplate
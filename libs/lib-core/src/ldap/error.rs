@@ -0,0 +1,33 @@
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[serde_as]
+#[derive(Debug, Serialize)]
+pub enum Error {
+    // -- Entry
+    EntryNotFound { dn: String },
+    EntryAttrMissing { dn: String, attr: String },
+
+    // -- Externals
+    Ldap(#[serde_as(as = "DisplayFromStr")] ldap3::LdapError),
+}
+
+impl From<ldap3::LdapError> for Error {
+    fn from(ex: ldap3::LdapError) -> Self {
+        Self::Ldap(ex)
+    }
+}
+
+// region:    --- Error Boilerplate
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
+        write!(fmt, "{self:?}")
+    }
+}
+
+impl std::error::Error for Error {}
+
+// endregion: --- Error Boilerplate
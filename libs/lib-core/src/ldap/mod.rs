@@ -0,0 +1,352 @@
+//! LDAP simple bind
+//!
+//! The user DN is built from a template (`{email}` and `{username}`, the
+//! local part of the email, are replaced by the escaped login), and the
+//! bind is done with the password given at login. On success, the entry of
+//! the user is read to get the email and display name.
+//! LDAP is configured when `ldap` is in `SERVICE_AUTH_BACKENDS`.
+
+mod error;
+
+pub use self::error::{Error, Result};
+
+use lazy_regex::regex_replace_all;
+use ldap3::{dn_escape, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use lib_utils::envs::get_env;
+use std::time::Duration;
+
+/// `invalidCredentials` result code (RFC 4511).
+const RC_INVALID_CREDENTIALS: u32 = 49;
+const CONN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// region:        --- Config
+
+#[derive(Debug, Clone)]
+pub struct LdapConfig {
+    /// `ldap://` or `ldaps://` URL of the directory.
+    pub url: String,
+    /// e.g. `uid={username},ou=people,dc=example,dc=org`
+    pub bind_dn_template: String,
+    pub attr_email: String,
+    pub attr_display_name: String,
+}
+
+impl LdapConfig {
+    pub fn load_from_env() -> lib_utils::Result<Self> {
+        Ok(LdapConfig {
+            url: get_env("SERVICE_LDAP_URL")?,
+            bind_dn_template: get_env("SERVICE_LDAP_BIND_DN")?,
+            attr_email: get_env("SERVICE_LDAP_ATTR_EMAIL").unwrap_or_else(|_| "mail".to_string()),
+            attr_display_name: get_env("SERVICE_LDAP_ATTR_DISPLAY_NAME")
+                .unwrap_or_else(|_| "displayName".to_string()),
+        })
+    }
+}
+
+// endregion:     --- Config
+
+/// Directory entry of a user who passed the bind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LdapUser {
+    pub dn: String,
+    pub email: String,
+    pub display_name: Option<String>,
+}
+
+/// Binds as the user, `None` when the directory rejects the credentials.
+pub async fn ldap_bind(config: &LdapConfig, login: &str, pwd: &str) -> Result<Option<LdapUser>> {
+    // an empty password is an unauthenticated bind, which succeeds (RFC 4513)
+    if pwd.is_empty() {
+        return Ok(None);
+    }
+
+    let dn = bind_dn(&config.bind_dn_template, login);
+    let settings = LdapConnSettings::new().set_conn_timeout(CONN_TIMEOUT);
+    let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &config.url).await?;
+    ldap3::drive!(conn);
+
+    let res = ldap.simple_bind(&dn, pwd).await?;
+    if res.rc == RC_INVALID_CREDENTIALS {
+        ldap.unbind().await?;
+        return Ok(None);
+    }
+    res.success()?;
+
+    let (entries, _) = ldap
+        .search(
+            &dn,
+            Scope::Base,
+            "(objectClass=*)",
+            vec![&config.attr_email, &config.attr_display_name],
+        )
+        .await?
+        .success()?;
+    ldap.unbind().await?;
+
+    let entry = entries
+        .into_iter()
+        .next()
+        .map(SearchEntry::construct)
+        .ok_or_else(|| Error::EntryNotFound { dn: dn.clone() })?;
+    let first_value = |attr: &str| entry.attrs.get(attr).and_then(|vals| vals.first()).cloned();
+
+    Ok(Some(LdapUser {
+        email: first_value(&config.attr_email).ok_or_else(|| Error::EntryAttrMissing {
+            dn: entry.dn.clone(),
+            attr: config.attr_email.clone(),
+        })?,
+        display_name: first_value(&config.attr_display_name),
+        dn: entry.dn,
+    }))
+}
+
+/// DN the login binds as.
+pub(crate) fn bind_dn(template: &str, login: &str) -> String {
+    let username = login.split_once('@').map_or(login, |(local, _)| local);
+
+    regex_replace_all!(r"\{(email|username)\}", template, |_, name: &str| {
+        match name {
+            "email" => dn_escape(login),
+            _ => dn_escape(username),
+        }
+    })
+    .into_owned()
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+pub(crate) mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    pub(crate) const BIND_DN_TEMPLATE: &str = "uid={username},ou=people,dc=example,dc=org";
+
+    // region:        --- LDAP stand-in
+
+    /// Directory entry of the stand-in: password and attributes.
+    #[derive(Clone)]
+    pub(crate) struct StandInEntry {
+        pub pwd: String,
+        pub attrs: Vec<(String, String)>,
+    }
+
+    /// Minimal LDAPv3 server, enough for a simple bind and a base search.
+    pub(crate) struct LdapStandIn {
+        pub url: String,
+    }
+
+    impl LdapStandIn {
+        /// Starts the stand-in on a random local port, entries by DN.
+        pub async fn start(entries: HashMap<String, StandInEntry>) -> Result<Self> {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let url = format!("ldap://{}", listener.local_addr()?);
+            let entries = Arc::new(entries);
+
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(serve(stream, entries.clone()));
+                }
+            });
+
+            Ok(Self { url })
+        }
+
+        pub fn config(&self) -> LdapConfig {
+            LdapConfig {
+                url: self.url.clone(),
+                bind_dn_template: BIND_DN_TEMPLATE.to_string(),
+                attr_email: "mail".to_string(),
+                attr_display_name: "displayName".to_string(),
+            }
+        }
+    }
+
+    pub(crate) fn stand_in_entry(uid: &str, pwd: &str, mail: &str) -> (String, StandInEntry) {
+        (
+            format!("uid={uid},ou=people,dc=example,dc=org"),
+            StandInEntry {
+                pwd: pwd.to_string(),
+                attrs: vec![
+                    ("mail".to_string(), mail.to_string()),
+                    ("displayName".to_string(), format!("User {uid}")),
+                ],
+            },
+        )
+    }
+
+    async fn serve(
+        mut stream: TcpStream,
+        entries: Arc<HashMap<String, StandInEntry>>,
+    ) -> std::io::Result<()> {
+        let mut buf = Vec::new();
+        let mut bound = false;
+
+        loop {
+            let Some((msg_len, msg)) =
+                read_tlv(&buf).map(|(_, content, rest)| (buf.len() - rest.len(), content.to_vec()))
+            else {
+                let mut chunk = [0u8; 1024];
+                match stream.read(&mut chunk).await? {
+                    0 => return Ok(()),
+                    n => buf.extend_from_slice(&chunk[..n]),
+                }
+                continue;
+            };
+            buf.drain(..msg_len);
+
+            let Some((_, id, rest)) = read_tlv(&msg) else {
+                return Ok(());
+            };
+            let Some((op, op_content, _)) = read_tlv(rest) else {
+                return Ok(());
+            };
+
+            let mut response = Vec::new();
+            match op {
+                // BindRequest: version, name, simple password
+                0x60 => {
+                    let (_, _, rest) = read_tlv(op_content).unwrap_or_default();
+                    let (_, dn, rest) = read_tlv(rest).unwrap_or_default();
+                    let (_, pwd, _) = read_tlv(rest).unwrap_or_default();
+                    let dn = String::from_utf8_lossy(dn);
+                    bound = entries
+                        .get(dn.as_ref())
+                        .is_some_and(|entry| entry.pwd.as_bytes() == pwd);
+                    let rc = if bound { 0 } else { 49 };
+                    response.extend(message(id, 0x61, &ldap_result(rc)));
+                }
+                // SearchRequest: only the base object matters
+                0x63 => {
+                    let (_, base, _) = read_tlv(op_content).unwrap_or_default();
+                    let base = String::from_utf8_lossy(base);
+                    let entry = entries.get(base.as_ref()).filter(|_| bound);
+                    if let Some(entry) = entry {
+                        let attrs: Vec<u8> = entry
+                            .attrs
+                            .iter()
+                            .flat_map(|(name, val)| {
+                                let vals = tlv(0x31, &tlv(0x04, val.as_bytes()));
+                                tlv(0x30, &[tlv(0x04, name.as_bytes()), vals].concat())
+                            })
+                            .collect();
+                        let content = [tlv(0x04, base.as_bytes()), tlv(0x30, &attrs)].concat();
+                        response.extend(message(id, 0x64, &content));
+                    }
+                    let rc = if entry.is_some() { 0 } else { 32 };
+                    response.extend(message(id, 0x65, &ldap_result(rc)));
+                }
+                // UnbindRequest, or anything else
+                _ => return Ok(()),
+            }
+            stream.write_all(&response).await?;
+        }
+    }
+
+    /// Tag, content and rest of a definite length BER element.
+    fn read_tlv(buf: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+        let (&tag, rest) = buf.split_first()?;
+        let (&first, rest) = rest.split_first()?;
+        let (len, rest) = if first < 0x80 {
+            (first as usize, rest)
+        } else {
+            let n = (first & 0x7f) as usize;
+            let len = rest
+                .get(..n)?
+                .iter()
+                .fold(0usize, |len, b| (len << 8) | *b as usize);
+            (len, &rest[n..])
+        };
+
+        Some((tag, rest.get(..len)?, &rest[len..]))
+    }
+
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let len = content.len();
+        let mut out = vec![tag];
+        if len < 0x80 {
+            out.push(len as u8);
+        } else {
+            let bytes: Vec<u8> = len
+                .to_be_bytes()
+                .into_iter()
+                .skip_while(|b| *b == 0)
+                .collect();
+            out.push(0x80 | bytes.len() as u8);
+            out.extend(bytes);
+        }
+        out.extend_from_slice(content);
+        out
+    }
+
+    fn ldap_result(rc: u8) -> Vec<u8> {
+        [tlv(0x0a, &[rc]), tlv(0x04, b""), tlv(0x04, b"")].concat()
+    }
+
+    fn message(id: &[u8], op: u8, content: &[u8]) -> Vec<u8> {
+        tlv(0x30, &[tlv(0x02, id), tlv(op, content)].concat())
+    }
+
+    // endregion:     --- LDAP stand-in
+
+    #[tokio::test]
+    async fn test_ldap_bind_ok() -> Result<()> {
+        let stand_in = LdapStandIn::start(HashMap::from([stand_in_entry(
+            "jdoe",
+            "secret",
+            "john@corp.com",
+        )]))
+        .await?;
+
+        let user = ldap_bind(&stand_in.config(), "jdoe@corp.com", "secret")
+            .await?
+            .ok_or("bind failed")?;
+
+        assert_eq!(user.dn, "uid=jdoe,ou=people,dc=example,dc=org");
+        assert_eq!(user.email, "john@corp.com");
+        assert_eq!(user.display_name.as_deref(), Some("User jdoe"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ldap_bind_invalid_credentials() -> Result<()> {
+        let stand_in = LdapStandIn::start(HashMap::from([stand_in_entry(
+            "jdoe",
+            "secret",
+            "john@corp.com",
+        )]))
+        .await?;
+        let config = stand_in.config();
+
+        assert!(ldap_bind(&config, "jdoe@corp.com", "wrong")
+            .await?
+            .is_none());
+        assert!(ldap_bind(&config, "nobody@corp.com", "secret")
+            .await?
+            .is_none());
+        // would be an anonymous bind
+        assert!(ldap_bind(&config, "jdoe@corp.com", "").await?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_dn_escaped() -> Result<()> {
+        assert_eq!(
+            bind_dn(BIND_DN_TEMPLATE, "jdoe@corp.com"),
+            "uid=jdoe,ou=people,dc=example,dc=org"
+        );
+        assert_eq!(
+            bind_dn("cn={email},dc=org", "a,ou=admins@corp.com"),
+            "cn=a\\2cou\\3dadmins@corp.com,dc=org"
+        );
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
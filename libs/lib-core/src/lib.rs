@@ -1,6 +1,14 @@
 mod config;
+pub mod ctx;
 mod error;
+pub mod ldap;
 pub mod model;
+pub mod notify;
+pub mod oidc;
+pub mod pwd;
+pub mod token;
+pub mod totp;
+pub mod web;
 
 use self::config::config;
 pub use error::{Error, Result};
@@ -0,0 +1,233 @@
+//! API keys for machine clients
+//!
+//! A key belongs to a user and is limited to some of the user permissions
+//! (its scopes). Only the SHA-256 of the key is stored, the key itself is
+//! returned once at creation. Keys can only be managed from a browser session,
+//! so a leaked key cannot mint new ones.
+
+use super::{impersonation::check_not_impersonating, rbac::perms, Error, ModelManager, Result};
+use crate::ctx::Ctx;
+use crate::token::{generate_secret, hash_secret};
+use lib_utils::time::now_utc_sec;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tracing::debug;
+
+const KEY_PREFIX: &str = "ak_";
+
+// region:        --- Types
+
+#[derive(FromRow, Serialize, Debug)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    /// Space separated permission names.
+    pub scopes: String,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct ApiKeyForCreate {
+    pub name: String,
+    pub scopes: Vec<String>,
+    /// No expiry when `None`.
+    pub expires_in_sec: Option<i64>,
+}
+
+/// The created key, the only time it is readable.
+#[derive(Serialize, Debug)]
+pub struct ApiKeyCreated {
+    pub id: i64,
+    pub key: String,
+}
+
+// endregion:     --- Types
+
+pub async fn create_api_key(
+    mm: ModelManager,
+    ctx: &Ctx,
+    key_c: ApiKeyForCreate,
+) -> Result<ApiKeyCreated> {
+    require_session(ctx)?;
+    // a key would keep the rights of the user after the impersonation
+    check_not_impersonating(ctx)?;
+    if let Some(scope) = key_c
+        .scopes
+        .iter()
+        .find(|s| !perms::ALL.contains(&s.as_str()))
+    {
+        return Err(Error::ApiKeyScopeUnknown {
+            scope: scope.to_string(),
+        });
+    }
+
+    let key = format!("{KEY_PREFIX}{}", generate_secret());
+    let now = now_utc_sec();
+
+    let db = mm.db;
+    let (id,) = sqlx::query_as::<_, (i64,)>(
+        "INSERT INTO api_key (user_id, name, key_hash, scopes, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+    )
+    .bind(ctx.user_id())
+    .bind(key_c.name)
+    .bind(hash_secret(&key))
+    .bind(key_c.scopes.join(" "))
+    .bind(now)
+    .bind(key_c.expires_in_sec.map(|sec| now + sec))
+    .fetch_one(&db)
+    .await?;
+
+    Ok(ApiKeyCreated { id, key })
+}
+
+/// Keys of the ctx user, revoked ones included.
+pub async fn list_api_keys(mm: ModelManager, ctx: &Ctx) -> Result<Vec<ApiKey>> {
+    require_session(ctx)?;
+
+    let db = mm.db;
+    let keys = sqlx::query_as::<_, ApiKey>(
+        "SELECT id, name, scopes, created_at, expires_at, last_used_at, revoked_at
+        FROM api_key WHERE user_id = $1",
+    )
+    .bind(ctx.user_id())
+    .fetch_all(&db)
+    .await?;
+
+    Ok(keys)
+}
+
+pub async fn revoke_api_key(mm: ModelManager, ctx: &Ctx, id: i64) -> Result<()> {
+    require_session(ctx)?;
+    check_not_impersonating(ctx)?;
+
+    let db = mm.db;
+    let res = sqlx::query(
+        "UPDATE api_key SET revoked_at = COALESCE(revoked_at, $1) WHERE id = $2 AND user_id = $3",
+    )
+    .bind(now_utc_sec())
+    .bind(id)
+    .bind(ctx.user_id())
+    .execute(&db)
+    .await?;
+
+    match res.rows_affected() {
+        0 => Err(Error::EntityNotFound {
+            entity: "api_key",
+            id,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// `Ctx` of a valid key, the key last use is recorded.
+pub async fn resolve_api_key(mm: ModelManager, key: &str) -> Result<Option<Ctx>> {
+    if !key.starts_with(KEY_PREFIX) {
+        return Ok(None);
+    }
+
+    let now = now_utc_sec();
+    let db = mm.db;
+    let ctx = sqlx::query_as::<_, (i64, String)>(
+        "UPDATE api_key SET last_used_at = $1
+        WHERE key_hash = $2 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > $1)
+        AND user_id IN (SELECT id FROM \"user\" WHERE is_active)
+        RETURNING user_id, scopes",
+    )
+    .bind(now)
+    .bind(hash_secret(key))
+    .fetch_optional(&db)
+    .await?
+    .map(|(user_id, scopes)| {
+        let scopes = scopes.split_whitespace().map(String::from).collect();
+        Ctx::new_for_api_key(user_id, scopes)
+    });
+
+    Ok(ctx)
+}
+
+fn require_session(ctx: &Ctx) -> Result<()> {
+    match ctx.session_id() {
+        Some(_) => Ok(()),
+        None => Err(Error::ApiKeyRequiresSession),
+    }
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+    use crate::model::rbac::{assign_admin_by_email, has_permission};
+
+    fn key_for_create(scopes: &[&str], expires_in_sec: Option<i64>) -> ApiKeyForCreate {
+        ApiKeyForCreate {
+            name: "script".to_string(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            expires_in_sec,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_api_key_resolve_scopes() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+        assign_admin_by_email(mm.clone(), "demo@mail.com").await?;
+        mm.seed_totp(user_id).await?;
+        let ctx = Ctx::new(user_id, "session");
+
+        let created =
+            create_api_key(mm.clone(), &ctx, key_for_create(&[perms::USER_READ], None)).await?;
+        let key_ctx = resolve_api_key(mm.clone(), &created.key)
+            .await?
+            .ok_or("key not resolved")?;
+
+        assert_eq!(key_ctx.user_id(), user_id);
+        assert!(has_permission(mm.clone(), &key_ctx, perms::USER_READ).await?);
+        assert!(!has_permission(mm.clone(), &key_ctx, perms::USER_CREATE).await?);
+        let keys = list_api_keys(mm.clone(), &ctx).await?;
+        assert!(keys[0].last_used_at.is_some());
+
+        // a key cannot create keys
+        let res = create_api_key(mm, &key_ctx, key_for_create(&[], None)).await;
+        assert!(matches!(res, Err(super::Error::ApiKeyRequiresSession)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_api_key_revoked_expired() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+        let ctx = Ctx::new(user_id, "session");
+
+        let expired = create_api_key(mm.clone(), &ctx, key_for_create(&[], Some(-1))).await?;
+        assert!(resolve_api_key(mm.clone(), &expired.key).await?.is_none());
+
+        let revoked = create_api_key(mm.clone(), &ctx, key_for_create(&[], None)).await?;
+        revoke_api_key(mm.clone(), &ctx, revoked.id).await?;
+        assert!(resolve_api_key(mm.clone(), &revoked.key).await?.is_none());
+
+        let other = Ctx::new(mm.seed_user("other@mail.com").await?, "session");
+        let res = revoke_api_key(mm, &other, expired.id).await;
+        assert!(matches!(res, Err(super::Error::EntityNotFound { .. })));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_api_key_scope_unknown() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let ctx = Ctx::new(mm.seed_user("demo@mail.com").await?, "session");
+
+        let res = create_api_key(mm, &ctx, key_for_create(&["user.everything"], None)).await;
+        assert!(matches!(res, Err(super::Error::ApiKeyScopeUnknown { .. })));
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
@@ -1,19 +1,41 @@
-use super::{user::create_user_table, ModelManager};
-use crate::Result;
+use super::{
+    migration::migrate,
+    rbac::{assign_admin_by_email, seed_rbac},
+    session::spawn_sessions_cleanup,
+    ModelManager,
+};
+use crate::notify::{new_notifier, Notifier};
+use crate::{config, Result};
 use axum::extract::FromRef;
 use leptos::LeptosOptions;
+use std::sync::Arc;
+use tracing::info;
 
 #[derive(FromRef, Debug, Clone)]
 pub struct AppState {
     pub leptos_options: LeptosOptions,
     pub mm: ModelManager,
+    pub notifier: Arc<dyn Notifier>,
 }
 
 impl AppState {
     pub async fn new(leptos_options: LeptosOptions) -> Result<Self> {
         let mm = ModelManager::new().await?;
-        create_user_table(mm.clone()).await?;
+        migrate(mm.clone()).await?;
+        seed_rbac(mm.clone()).await?;
 
-        Ok(Self { leptos_options, mm })
+        if let Some(email) = &config().ADMIN_EMAIL {
+            if assign_admin_by_email(mm.clone(), email).await? {
+                info!("{:<12} - {email} is admin, once TOTP is enabled", "RBAC");
+            }
+        }
+
+        spawn_sessions_cleanup(mm.clone());
+
+        Ok(Self {
+            leptos_options,
+            mm,
+            notifier: new_notifier(),
+        })
     }
 }
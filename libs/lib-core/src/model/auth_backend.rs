@@ -0,0 +1,298 @@
+//! Authentication backends
+//!
+//! The login tries the backends of `SERVICE_AUTH_BACKENDS` (e.g. `local,ldap`)
+//! in order, the first one accepting the credentials wins:
+//! - `local` checks the password hash stored in `user`,
+//! - `ldap` binds to the directory and syncs the entry into `user`, creating
+//!   the user only when the registration is open.
+//!
+//! A backend failing (e.g. the directory down) is logged and skipped, so the
+//! credentials are still checked by the others.
+
+use super::{
+    email_verify::is_registration_open,
+    user::{get_user_for_login, get_user_for_login_by_id, update_pwd, UserForLogin},
+    user_ldap::sync_ldap_user,
+    ModelManager, Result,
+};
+use crate::config;
+use crate::ldap::{ldap_bind, LdapConfig};
+use crate::pwd::{validate_pwd, SchemeStatus};
+use lib_utils::envs::get_env;
+use tracing::warn;
+
+#[derive(Debug, Clone)]
+pub enum AuthBackend {
+    Local,
+    Ldap(LdapConfig),
+}
+
+impl AuthBackend {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Local => "local",
+            Self::Ldap(_) => "ldap",
+        }
+    }
+}
+
+/// Backends of `SERVICE_AUTH_BACKENDS`, with the LDAP config when `ldap` is one of them.
+pub fn load_auth_backends_from_env() -> lib_utils::Result<Vec<AuthBackend>> {
+    get_env("SERVICE_AUTH_BACKENDS")?
+        .split(',')
+        .map(|name| match name.trim() {
+            "local" => Ok(AuthBackend::Local),
+            "ldap" => Ok(AuthBackend::Ldap(LdapConfig::load_from_env()?)),
+            _ => Err(lib_utils::Error::WrongEnvFormat("SERVICE_AUTH_BACKENDS")),
+        })
+        .collect()
+}
+
+/// User of the credentials for the first backend accepting them, `None` if none
+/// does. Fails only when every backend failed.
+pub async fn authenticate(
+    mm: ModelManager,
+    email: &str,
+    pwd: &str,
+) -> Result<Option<UserForLogin>> {
+    authenticate_with(mm, &config().AUTH_BACKENDS, email, pwd).await
+}
+
+async fn authenticate_with(
+    mm: ModelManager,
+    backends: &[AuthBackend],
+    email: &str,
+    pwd: &str,
+) -> Result<Option<UserForLogin>> {
+    let mut errors = Vec::new();
+    for backend in backends {
+        let res = match backend {
+            AuthBackend::Local => authenticate_local(mm.clone(), email, pwd).await,
+            AuthBackend::Ldap(ldap_config) => {
+                authenticate_ldap(mm.clone(), ldap_config, email, pwd).await
+            }
+        };
+
+        match res {
+            Ok(Some(user_id)) => {
+                let user = get_user_for_login_by_id(mm, user_id).await?;
+                return Ok(user.filter(|user| user.is_active));
+            }
+            Ok(None) => (),
+            Err(ex) => {
+                warn!("{:<12} - {} backend failed: {ex}", "AUTH", backend.name());
+                errors.push(ex);
+            }
+        }
+    }
+
+    // rejected by at least one backend
+    if errors.len() < backends.len() {
+        return Ok(None);
+    }
+    match errors.into_iter().next() {
+        Some(ex) => Err(ex),
+        None => Ok(None),
+    }
+}
+
+async fn authenticate_local(mm: ModelManager, email: &str, pwd: &str) -> Result<Option<i64>> {
+    let Some(user) = get_user_for_login(mm.clone(), email).await? else {
+        return Ok(None);
+    };
+    // no password for the users of the other backends
+    let Some(pwd_ref) = user.pwd else {
+        return Ok(None);
+    };
+    let Ok(pwd_status) = validate_pwd(pwd.to_string(), pwd_ref).await else {
+        return Ok(None);
+    };
+
+    if pwd_status == SchemeStatus::Outdated {
+        update_pwd(mm, user.id, pwd).await?;
+    }
+
+    Ok(Some(user.id))
+}
+
+async fn authenticate_ldap(
+    mm: ModelManager,
+    ldap_config: &LdapConfig,
+    email: &str,
+    pwd: &str,
+) -> Result<Option<i64>> {
+    match ldap_bind(ldap_config, email, pwd).await? {
+        Some(entry) => sync_ldap_user(mm, &entry, is_registration_open()).await,
+        None => Ok(None),
+    }
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+    use crate::ldap::tests::{stand_in_entry, LdapStandIn};
+    use crate::ldap::LdapUser;
+    use crate::model::rbac::{assign_role, create_role};
+    use std::collections::HashMap;
+
+    async fn display_name(mm: &ModelManager, user_id: i64) -> Result<Option<String>> {
+        let (name,) = sqlx::query_as("SELECT display_name FROM \"user\" WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(&mm.db)
+            .await?;
+        Ok(name)
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_local_ok() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+        update_pwd(mm.clone(), user_id, "welcome").await?;
+        let backends = [AuthBackend::Local];
+
+        let user = authenticate_with(mm.clone(), &backends, "demo@mail.com", "welcome").await?;
+        assert_eq!(user.map(|user| user.id), Some(user_id));
+
+        let user = authenticate_with(mm, &backends, "demo@mail.com", "wrong").await?;
+        assert!(user.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_ldap_syncs_user() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let stand_in = LdapStandIn::start(HashMap::from([stand_in_entry(
+            "jdoe",
+            "secret",
+            "john@corp.com",
+        )]))
+        .await?;
+        let backends = [AuthBackend::Ldap(stand_in.config())];
+
+        let user = authenticate_with(mm.clone(), &backends, "jdoe@corp.com", "secret")
+            .await?
+            .ok_or("ldap bind failed")?;
+        assert_eq!(user.email, "john@corp.com");
+        assert!(user.pwd.is_none());
+        assert!(user.verified_at.is_some());
+        assert_eq!(
+            display_name(&mm, user.id).await?.as_deref(),
+            Some("User jdoe")
+        );
+
+        // email changed in the directory, same user
+        let stand_in = LdapStandIn::start(HashMap::from([stand_in_entry(
+            "jdoe",
+            "secret",
+            "jdoe@corp.com",
+        )]))
+        .await?;
+        let backends = [AuthBackend::Ldap(stand_in.config())];
+        let synced = authenticate_with(mm, &backends, "jdoe@corp.com", "secret")
+            .await?
+            .ok_or("ldap bind failed")?;
+        assert_eq!(synced.id, user.id);
+        assert_eq!(synced.email, "jdoe@corp.com");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_ldap_user_no_provision() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let local_id = mm.seed_user("local@corp.com").await?;
+        let entry = |uid: &str, email: &str| LdapUser {
+            dn: format!("uid={uid},ou=people,dc=example,dc=org"),
+            email: email.to_string(),
+            display_name: None,
+        };
+
+        // registration closed, only the existing users are linked
+        let user_id = sync_ldap_user(mm.clone(), &entry("jdoe", "jdoe@corp.com"), false).await?;
+        assert!(user_id.is_none());
+        let user_id = sync_ldap_user(mm, &entry("local", "local@corp.com"), false).await?;
+        assert_eq!(user_id, Some(local_id));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_ldap_user_no_takeover() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let pwd_id = mm.seed_user("pwd@corp.com").await?;
+        update_pwd(mm.clone(), pwd_id, "welcome").await?;
+        let role_id = mm.seed_user("role@corp.com").await?;
+        let admin_role = create_role(mm.clone(), "admin").await?;
+        assign_role(mm.clone(), role_id, admin_role).await?;
+        let entry = |uid: &str, email: &str| LdapUser {
+            dn: format!("uid={uid},ou=people,dc=example,dc=org"),
+            email: email.to_string(),
+            display_name: Some("Mallory".to_string()),
+        };
+
+        // an entry with the mail of a local account does not get it
+        let user_id = sync_ldap_user(mm.clone(), &entry("mallory", "pwd@corp.com"), true).await?;
+        assert!(user_id.is_none());
+        let user_id = sync_ldap_user(mm.clone(), &entry("eve", "role@corp.com"), true).await?;
+        assert!(user_id.is_none());
+        assert_eq!(display_name(&mm, pwd_id).await?, None);
+        assert_eq!(display_name(&mm, role_id).await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_backends_in_order() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let local_id = mm.seed_user("local@corp.com").await?;
+        update_pwd(mm.clone(), local_id, "welcome").await?;
+        let stand_in = LdapStandIn::start(HashMap::from([stand_in_entry(
+            "jdoe",
+            "secret",
+            "jdoe@corp.com",
+        )]))
+        .await?;
+        let backends = [AuthBackend::Local, AuthBackend::Ldap(stand_in.config())];
+
+        // local user, no directory entry
+        let user = authenticate_with(mm.clone(), &backends, "local@corp.com", "welcome").await?;
+        assert_eq!(user.map(|user| user.id), Some(local_id));
+
+        // directory user, no local password
+        let user = authenticate_with(mm.clone(), &backends, "jdoe@corp.com", "secret").await?;
+        assert!(user.is_some());
+
+        // rejected by both
+        let user = authenticate_with(mm, &backends, "jdoe@corp.com", "wrong").await?;
+        assert!(user.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_backend_down() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let local_id = mm.seed_user("local@corp.com").await?;
+        update_pwd(mm.clone(), local_id, "welcome").await?;
+        let down = LdapConfig {
+            url: "ldap://127.0.0.1:1".to_string(),
+            ..LdapStandIn::start(HashMap::new()).await?.config()
+        };
+        let backends = [AuthBackend::Ldap(down.clone()), AuthBackend::Local];
+
+        // the directory is skipped, the local password still checked
+        let user = authenticate_with(mm.clone(), &backends, "local@corp.com", "welcome").await?;
+        assert_eq!(user.map(|user| user.id), Some(local_id));
+        let user = authenticate_with(mm.clone(), &backends, "local@corp.com", "wrong").await?;
+        assert!(user.is_none());
+
+        // no backend left to check them
+        let res =
+            authenticate_with(mm, &[AuthBackend::Ldap(down)], "local@corp.com", "welcome").await;
+        assert!(res.is_err());
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
@@ -0,0 +1,310 @@
+//! Generic CRUD of the entities
+//!
+//! An entity gets `create`, `get`, `list`, `update` and `delete` by naming its
+//! table:
+//!
+//! ```ignore
+//! pub struct NoteBmc;
+//!
+//! impl DbBmc for NoteBmc {
+//!     const TABLE: &'static str = "note";
+//! }
+//!
+//! let id = base::create::<NoteBmc, _>(mm.clone(), NoteForCreate { body }).await?;
+//! let note: Note = base::get::<NoteBmc, _>(mm, id).await?;
+//! ```
+//!
+//! Rows are read with `FromRow`, and written from any `Serialize` struct, each
+//! field being a column. On update, the fields skipped by serde (e.g.
+//! `skip_serializing_if = "Option::is_none"`) keep their value. Lists take the
+//! filters and options of `model::list`.
+
+use super::list::{push_list_clauses, ListFilter, ListOptions};
+use super::store::{DbBackend, DbRow};
+use super::{Error, ModelManager, Result};
+use lib_utils::time::now_utc_sec;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{FromRow, QueryBuilder};
+
+/// Backend model controller of an entity, keyed by its table.
+pub trait DbBmc {
+    const TABLE: &'static str;
+    /// Name of the entity in `EntityNotFound`, the table by default.
+    const ENTITY: &'static str = Self::TABLE;
+    /// `created_at` and `updated_at` are set by `create` and `update`.
+    const TIMESTAMPED: bool = false;
+    /// Columns the lists can be filtered and ordered on.
+    const LIST_COLUMNS: &'static [&'static str] = &["id"];
+}
+
+/// Inserts the fields as a new row, returns its id.
+pub async fn create<MC, D>(mm: ModelManager, data: D) -> Result<i64>
+where
+    MC: DbBmc,
+    D: Serialize,
+{
+    let mut fields = fields(&data)?;
+    if MC::TIMESTAMPED {
+        let now = now_utc_sec();
+        fields.push(("created_at".to_string(), now.into()));
+        fields.push(("updated_at".to_string(), now.into()));
+    }
+
+    let columns: Vec<&str> = fields.iter().map(|(column, _)| column.as_str()).collect();
+    let mut query = QueryBuilder::<DbBackend>::new(format!(
+        "INSERT INTO \"{}\" ({}) VALUES (",
+        MC::TABLE,
+        columns.join(", ")
+    ));
+    for (i, (_, value)) in fields.into_iter().enumerate() {
+        if i > 0 {
+            query.push(", ");
+        }
+        push_value(&mut query, value);
+    }
+    query.push(") RETURNING id");
+
+    let db = mm.db;
+    let (id,) = query.build_query_as::<(i64,)>().fetch_one(&db).await?;
+
+    Ok(id)
+}
+
+pub async fn get<MC, E>(mm: ModelManager, id: i64) -> Result<E>
+where
+    MC: DbBmc,
+    E: for<'r> FromRow<'r, DbRow> + Unpin + Send,
+{
+    let db = mm.db;
+    sqlx::query_as::<_, E>(&format!("SELECT * FROM \"{}\" WHERE id = $1", MC::TABLE))
+        .bind(id)
+        .fetch_optional(&db)
+        .await?
+        .ok_or(Error::EntityNotFound {
+            entity: MC::ENTITY,
+            id,
+        })
+}
+
+/// Rows matching the filter, a page of `ListOptions`, by id when not ordered.
+pub async fn list<MC, E>(
+    mm: ModelManager,
+    filter: Option<ListFilter>,
+    list_options: Option<ListOptions>,
+) -> Result<Vec<E>>
+where
+    MC: DbBmc,
+    E: for<'r> FromRow<'r, DbRow> + Unpin + Send,
+{
+    let mut query = QueryBuilder::<DbBackend>::new(format!("SELECT * FROM \"{}\"", MC::TABLE));
+    push_list_clauses(&mut query, MC::LIST_COLUMNS, filter, list_options)?;
+
+    let db = mm.db;
+    let entities = query.build_query_as::<E>().fetch_all(&db).await?;
+
+    Ok(entities)
+}
+
+pub async fn update<MC, D>(mm: ModelManager, id: i64, data: D) -> Result<()>
+where
+    MC: DbBmc,
+    D: Serialize,
+{
+    let mut fields = fields(&data)?;
+    if MC::TIMESTAMPED {
+        fields.push(("updated_at".to_string(), now_utc_sec().into()));
+    }
+    // nothing to set, still fails on a missing row
+    if fields.is_empty() {
+        return get::<MC, (i64,)>(mm, id).await.map(|_| ());
+    }
+
+    let mut query = QueryBuilder::<DbBackend>::new(format!("UPDATE \"{}\" SET ", MC::TABLE));
+    for (i, (column, value)) in fields.into_iter().enumerate() {
+        if i > 0 {
+            query.push(", ");
+        }
+        query.push(format!("{column} = "));
+        push_value(&mut query, value);
+    }
+    query.push(" WHERE id = ").push_bind(id);
+
+    let db = mm.db;
+    let res = query.build().execute(&db).await?;
+    if res.rows_affected() == 0 {
+        return Err(Error::EntityNotFound {
+            entity: MC::ENTITY,
+            id,
+        });
+    }
+
+    Ok(())
+}
+
+pub async fn delete<MC>(mm: ModelManager, id: i64) -> Result<()>
+where
+    MC: DbBmc,
+{
+    let db = mm.db;
+    let res = sqlx::query(&format!("DELETE FROM \"{}\" WHERE id = $1", MC::TABLE))
+        .bind(id)
+        .execute(&db)
+        .await?;
+    if res.rows_affected() == 0 {
+        return Err(Error::EntityNotFound {
+            entity: MC::ENTITY,
+            id,
+        });
+    }
+
+    Ok(())
+}
+
+/// Columns and values of a struct, in field order.
+fn fields<D: Serialize>(data: &D) -> Result<Vec<(String, Value)>> {
+    match serde_json::to_value(data)? {
+        Value::Object(map) => Ok(map.into_iter().collect()),
+        _ => Err(Error::FieldsNotStruct),
+    }
+}
+
+/// Binds the value with its SQL type, arrays and objects as JSON text. `NULL`
+/// is written as is, a bound one would be typed and Postgres checks the type.
+pub(super) fn push_value(query: &mut QueryBuilder<DbBackend>, value: Value) {
+    match value {
+        Value::Null => query.push("NULL"),
+        Value::Bool(value) => query.push_bind(value),
+        Value::Number(value) => match value.as_i64() {
+            Some(value) => query.push_bind(value),
+            None => query.push_bind(value.as_f64()),
+        },
+        Value::String(value) => query.push_bind(value),
+        value => query.push_bind(value.to_string()),
+    };
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+
+    struct NoteBmc;
+
+    impl DbBmc for NoteBmc {
+        const TABLE: &'static str = "note";
+        const TIMESTAMPED: bool = true;
+    }
+
+    #[derive(FromRow, Debug)]
+    struct Note {
+        id: i64,
+        title: String,
+        body: Option<String>,
+        created_at: i64,
+        updated_at: i64,
+    }
+
+    #[derive(Serialize)]
+    struct NoteForCreate {
+        title: &'static str,
+        body: Option<&'static str>,
+    }
+
+    #[derive(Serialize, Default)]
+    struct NoteForUpdate {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<&'static str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        body: Option<Option<&'static str>>,
+    }
+
+    #[cfg(feature = "postgres")]
+    const NOTE_ID: &str = "BIGSERIAL PRIMARY KEY";
+    #[cfg(not(feature = "postgres"))]
+    const NOTE_ID: &str = "INTEGER PRIMARY KEY AUTOINCREMENT";
+
+    async fn new_mm() -> Result<ModelManager> {
+        let mm = ModelManager::new_for_test().await?;
+        sqlx::query(&format!(
+            "CREATE TABLE note (id {NOTE_ID}, title TEXT NOT NULL, body TEXT,
+            created_at BIGINT NOT NULL, updated_at BIGINT NOT NULL)"
+        ))
+        .execute(&mm.db)
+        .await?;
+        Ok(mm)
+    }
+
+    #[tokio::test]
+    async fn test_base_create_get_list() -> Result<()> {
+        let mm = new_mm().await?;
+
+        let id = create::<NoteBmc, _>(
+            mm.clone(),
+            NoteForCreate {
+                title: "first",
+                body: Some("hello"),
+            },
+        )
+        .await?;
+        create::<NoteBmc, _>(
+            mm.clone(),
+            NoteForCreate {
+                title: "second",
+                body: None,
+            },
+        )
+        .await?;
+
+        let note: Note = get::<NoteBmc, _>(mm.clone(), id).await?;
+        assert_eq!(note.title, "first");
+        assert_eq!(note.body.as_deref(), Some("hello"));
+        assert!(note.created_at > 0 && note.created_at == note.updated_at);
+
+        let notes: Vec<Note> = list::<NoteBmc, _>(mm, None, None).await?;
+        let titles: Vec<&str> = notes.iter().map(|n| n.title.as_str()).collect();
+        assert_eq!(titles, ["first", "second"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_base_update_delete() -> Result<()> {
+        let mm = new_mm().await?;
+        let id = create::<NoteBmc, _>(
+            mm.clone(),
+            NoteForCreate {
+                title: "first",
+                body: Some("hello"),
+            },
+        )
+        .await?;
+
+        // skipped fields are kept, an explicit `None` clears
+        let note_u = NoteForUpdate {
+            body: Some(None),
+            ..Default::default()
+        };
+        update::<NoteBmc, _>(mm.clone(), id, note_u).await?;
+        let note: Note = get::<NoteBmc, _>(mm.clone(), id).await?;
+        assert_eq!(note.title, "first");
+        assert!(note.body.is_none());
+
+        delete::<NoteBmc>(mm.clone(), id).await?;
+        let res = get::<NoteBmc, Note>(mm.clone(), id).await;
+        assert!(matches!(
+            res,
+            Err(super::Error::EntityNotFound { entity: "note", .. })
+        ));
+        let res = update::<NoteBmc, _>(mm.clone(), id, NoteForUpdate::default()).await;
+        assert!(matches!(res, Err(super::Error::EntityNotFound { .. })));
+        let res = delete::<NoteBmc>(mm, id).await;
+        assert!(matches!(res, Err(super::Error::EntityNotFound { .. })));
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
@@ -0,0 +1,341 @@
+//! Database executor of the `ModelManager`, the pool or its transaction
+//!
+//! The model functions run their queries on `&mm.db`, a `Dbx`. A `ModelManager`
+//! from `new_with_txn` holds a transaction once begun, and the queries of all
+//! its clones go to it until committed or rolled back:
+//!
+//! ```ignore
+//! let mm = mm.new_with_txn();
+//! mm.begin().await?;
+//! let user_id = create_user(mm.clone(), user_c).await?;
+//! assign_role(mm.clone(), user_id, role_id).await?;
+//! mm.commit().await?;
+//! ```
+//!
+//! A nested `begin` joins the transaction and only the outermost `commit`
+//! commits. A `rollback` at any depth reverts it all: until the outermost level
+//! is closed, the queries, `begin` and `commit` then fail with `TxnRolledBack`.
+//! In transaction mode, the queries outside of `begin` fail with `TxnNotBegun`.
+//! Dropping the last clone before the commit rolls back.
+
+use super::store::{Db, DbBackend, DbRow};
+use super::{Error, Result};
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, StreamExt};
+use sqlx::{Database, Describe, Either, Execute, Executor, Transaction};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+type DbQueryResult = <DbBackend as Database>::QueryResult;
+type DbStatement<'q> = <DbBackend as Database>::Statement<'q>;
+type DbTypeInfo = <DbBackend as Database>::TypeInfo;
+
+#[derive(Debug, Clone)]
+pub struct Dbx {
+    pool: Db,
+    /// Set in transaction mode, shared by the clones.
+    txn_holder: Option<Arc<Mutex<TxnHolder>>>,
+}
+
+#[derive(Default)]
+struct TxnHolder {
+    txn: Option<Transaction<'static, DbBackend>>,
+    depth: usize,
+    /// Rolled back by a nested level, until the outermost one is closed.
+    rolled_back: bool,
+}
+
+impl TxnHolder {
+    /// The transaction for a query.
+    fn txn(&mut self) -> sqlx::Result<&mut Transaction<'static, DbBackend>> {
+        if self.rolled_back {
+            return Err(txn_state_error(Error::TxnRolledBack));
+        }
+        self.txn
+            .as_mut()
+            .ok_or_else(|| txn_state_error(Error::TxnNotBegun))
+    }
+
+    /// Closes a level, returns whether it was the outermost.
+    fn close_level(&mut self) -> Result<bool> {
+        if self.depth == 0 {
+            return Err(Error::TxnNotBegun);
+        }
+        self.depth -= 1;
+        if self.depth == 0 {
+            self.rolled_back = false;
+        }
+
+        Ok(self.depth == 0)
+    }
+}
+
+/// Carried by the `sqlx::Error` of the executor, back to `Error` by its `From`.
+fn txn_state_error(err: Error) -> sqlx::Error {
+    sqlx::Error::Configuration(Box::new(err))
+}
+
+impl core::fmt::Debug for TxnHolder {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        fmt.debug_struct("TxnHolder")
+            .field("open", &self.txn.is_some())
+            .field("depth", &self.depth)
+            .field("rolled_back", &self.rolled_back)
+            .finish()
+    }
+}
+
+impl Dbx {
+    pub fn new(pool: Db) -> Self {
+        Self {
+            pool,
+            txn_holder: None,
+        }
+    }
+
+    /// Same pool, in transaction mode with no transaction begun.
+    pub fn new_with_txn(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            txn_holder: Some(Arc::default()),
+        }
+    }
+
+    pub fn pool(&self) -> &Db {
+        &self.pool
+    }
+
+    pub async fn begin_txn(&self) -> Result<()> {
+        let mut txn_holder = self.txn_holder()?.lock().await;
+        if txn_holder.rolled_back {
+            return Err(Error::TxnRolledBack);
+        }
+        if txn_holder.txn.is_none() {
+            txn_holder.txn = Some(self.pool.begin().await?);
+        }
+        txn_holder.depth += 1;
+
+        Ok(())
+    }
+
+    /// Commits when closing the outermost `begin_txn`, fails when a nested
+    /// level rolled back.
+    pub async fn commit_txn(&self) -> Result<()> {
+        let mut txn_holder = self.txn_holder()?.lock().await;
+        let rolled_back = txn_holder.rolled_back;
+        let outermost = txn_holder.close_level()?;
+        if rolled_back {
+            return Err(Error::TxnRolledBack);
+        }
+
+        if outermost {
+            if let Some(txn) = txn_holder.txn.take() {
+                txn.commit().await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reverts the whole transaction, the outer levels then fail until closed.
+    pub async fn rollback_txn(&self) -> Result<()> {
+        let mut txn_holder = self.txn_holder()?.lock().await;
+        let outermost = txn_holder.close_level()?;
+        txn_holder.rolled_back = !outermost;
+        if let Some(txn) = txn_holder.txn.take() {
+            txn.rollback().await?;
+        }
+
+        Ok(())
+    }
+
+    fn txn_holder(&self) -> Result<&Mutex<TxnHolder>> {
+        self.txn_holder.as_deref().ok_or(Error::TxnModeRequired)
+    }
+}
+
+/// Queries go to the pool, or to the transaction in transaction mode. The
+/// transaction is locked for each query, its rows are collected before being
+/// streamed.
+impl<'c> Executor<'c> for &'c Dbx {
+    type Database = DbBackend;
+
+    fn fetch_many<'e, 'q: 'e, E>(
+        self,
+        query: E,
+    ) -> BoxStream<'e, sqlx::Result<Either<DbQueryResult, DbRow>>>
+    where
+        'c: 'e,
+        E: 'q + Execute<'q, DbBackend>,
+    {
+        let Some(txn_holder) = &self.txn_holder else {
+            return self.pool.fetch_many(query);
+        };
+
+        stream::once(async move {
+            let mut txn_holder = txn_holder.lock().await;
+            let results: Vec<_> = match txn_holder.txn() {
+                Ok(txn) => (&mut **txn).fetch_many(query).collect().await,
+                Err(err) => vec![Err(err)],
+            };
+            stream::iter(results)
+        })
+        .flatten()
+        .boxed()
+    }
+
+    fn fetch_optional<'e, 'q: 'e, E>(self, query: E) -> BoxFuture<'e, sqlx::Result<Option<DbRow>>>
+    where
+        'c: 'e,
+        E: 'q + Execute<'q, DbBackend>,
+    {
+        Box::pin(async move {
+            let Some(txn_holder) = &self.txn_holder else {
+                return self.pool.fetch_optional(query).await;
+            };
+
+            let mut txn_holder = txn_holder.lock().await;
+            (&mut **txn_holder.txn()?).fetch_optional(query).await
+        })
+    }
+
+    fn prepare_with<'e, 'q: 'e>(
+        self,
+        sql: &'q str,
+        parameters: &'e [DbTypeInfo],
+    ) -> BoxFuture<'e, sqlx::Result<DbStatement<'q>>>
+    where
+        'c: 'e,
+    {
+        Box::pin(async move {
+            let Some(txn_holder) = &self.txn_holder else {
+                return self.pool.prepare_with(sql, parameters).await;
+            };
+
+            let mut txn_holder = txn_holder.lock().await;
+            (&mut **txn_holder.txn()?)
+                .prepare_with(sql, parameters)
+                .await
+        })
+    }
+
+    fn describe<'e, 'q: 'e>(self, sql: &'q str) -> BoxFuture<'e, sqlx::Result<Describe<DbBackend>>>
+    where
+        'c: 'e,
+    {
+        Box::pin(async move {
+            let Some(txn_holder) = &self.txn_holder else {
+                return self.pool.describe(sql).await;
+            };
+
+            let mut txn_holder = txn_holder.lock().await;
+            (&mut **txn_holder.txn()?).describe(sql).await
+        })
+    }
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use crate::model::user::get_user_for_login;
+    use crate::model::ModelManager;
+
+    async fn has_user(mm: &ModelManager, email: &str) -> Result<bool> {
+        Ok(get_user_for_login(mm.clone(), email).await?.is_some())
+    }
+
+    #[tokio::test]
+    async fn test_txn_commit_and_rollback() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+
+        let mm_txn = mm.new_with_txn();
+        mm_txn.begin().await?;
+        mm_txn.seed_user("rolled-back@mail.com").await?;
+        assert!(has_user(&mm_txn, "rolled-back@mail.com").await?);
+        mm_txn.rollback().await?;
+
+        mm_txn.begin().await?;
+        mm_txn.seed_user("committed@mail.com").await?;
+        mm_txn.commit().await?;
+
+        assert!(!has_user(&mm, "rolled-back@mail.com").await?);
+        assert!(has_user(&mm, "committed@mail.com").await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_txn_nested_and_drop() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+
+        // the inner commit leaves the outer rollback to decide
+        let mm_txn = mm.new_with_txn();
+        mm_txn.begin().await?;
+        mm_txn.begin().await?;
+        mm_txn.seed_user("nested@mail.com").await?;
+        mm_txn.commit().await?;
+        mm_txn.rollback().await?;
+        assert!(matches!(
+            mm_txn.commit().await,
+            Err(super::Error::TxnNotBegun)
+        ));
+
+        mm_txn.begin().await?;
+        mm_txn.seed_user("dropped@mail.com").await?;
+        drop(mm_txn);
+
+        assert!(!has_user(&mm, "nested@mail.com").await?);
+        assert!(!has_user(&mm, "dropped@mail.com").await?);
+        assert!(matches!(
+            mm.begin().await,
+            Err(super::Error::TxnModeRequired)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_txn_nested_rollback_poisons() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+
+        // no query outside of `begin` in transaction mode
+        let mm_txn = mm.new_with_txn();
+        assert!(matches!(
+            mm_txn.seed_user("not-begun@mail.com").await,
+            Err(super::Error::TxnNotBegun)
+        ));
+
+        // the writes after the inner rollback fail, not going to the pool
+        mm_txn.begin().await?;
+        mm_txn.seed_user("outer@mail.com").await?;
+        mm_txn.begin().await?;
+        mm_txn.rollback().await?;
+        assert!(matches!(
+            mm_txn.seed_user("after-rollback@mail.com").await,
+            Err(super::Error::TxnRolledBack)
+        ));
+        assert!(matches!(
+            mm_txn.begin().await,
+            Err(super::Error::TxnRolledBack)
+        ));
+        assert!(matches!(
+            mm_txn.commit().await,
+            Err(super::Error::TxnRolledBack)
+        ));
+
+        // closed, the next transaction starts clean
+        mm_txn.begin().await?;
+        mm_txn.seed_user("next@mail.com").await?;
+        mm_txn.commit().await?;
+
+        assert!(!has_user(&mm, "not-begun@mail.com").await?);
+        assert!(!has_user(&mm, "outer@mail.com").await?);
+        assert!(!has_user(&mm, "after-rollback@mail.com").await?);
+        assert!(has_user(&mm, "next@mail.com").await?);
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
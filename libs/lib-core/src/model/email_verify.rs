@@ -0,0 +1,200 @@
+//! Email address verification
+//!
+//! The link sent on registration holds a signed token whose ident is
+//! `email-verify:<user_id>:<email>`, so it becomes useless if the email changes.
+//! Unverified users hold no permission, and with the `block` policy cannot log in.
+
+use super::{user::create_user, Error, ModelManager, Result};
+use crate::config;
+use crate::notify::{Message, Notifier};
+use crate::token::{generate_email_token, validate_email_token, Token};
+use lib_utils::time::now_utc_sec;
+use std::str::FromStr;
+use tracing::debug;
+
+const IDENT_PREFIX: &str = "email-verify";
+
+// region:        --- Policy
+
+/// What unverified users can do, from `SERVICE_UNVERIFIED_POLICY`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnverifiedPolicy {
+    /// Cannot log in until verified.
+    Block,
+    /// Can log in, but without any permission.
+    Restrict,
+}
+
+impl FromStr for UnverifiedPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(Self::Block),
+            "restrict" => Ok(Self::Restrict),
+            other => Err(format!("unknown unverified policy '{other}'")),
+        }
+    }
+}
+
+/// True if the user may open a session according to the configured policy.
+pub fn can_login(verified_at: Option<i64>) -> bool {
+    verified_at.is_some() || config().UNVERIFIED_POLICY == UnverifiedPolicy::Restrict
+}
+
+/// False when accounts are only created from invitations.
+pub fn is_registration_open() -> bool {
+    config().OPEN_REGISTRATION
+}
+
+// endregion:     --- Policy
+
+/// Creates the user and sends the verification link.
+/// Fails with `RegistrationClosed` when only invitations are accepted.
+pub async fn register_user(
+    mm: ModelManager,
+    notifier: &dyn Notifier,
+    email: &str,
+    pwd: &str,
+) -> Result<i64> {
+    if !config().OPEN_REGISTRATION {
+        return Err(Error::RegistrationClosed);
+    }
+    let user_id = create_user(mm, email, pwd).await?;
+    send_verification(notifier, user_id, email)?;
+
+    Ok(user_id)
+}
+
+pub fn send_verification(notifier: &dyn Notifier, user_id: i64, email: &str) -> Result<()> {
+    let token = generate_email_token(&format!("{IDENT_PREFIX}:{user_id}:{email}"))
+        .map_err(|_| Error::EmailVerifyTokenInvalid)?;
+
+    notifier.notify(&Message {
+        to: email.to_string(),
+        subject: "Verify your email".to_string(),
+        body: format!(
+            "Follow this link to verify your email address:\n{}/verify-email?token={token}",
+            config().WEB_URL
+        ),
+    })?;
+
+    Ok(())
+}
+
+/// Marks the email of the token as verified and returns the user id.
+/// Verifying twice is fine, as long as the token is still valid.
+pub async fn verify_email(mm: ModelManager, token: &str) -> Result<i64> {
+    let token: Token = token.parse().map_err(|_| Error::EmailVerifyTokenInvalid)?;
+    validate_email_token(&token).map_err(|_| Error::EmailVerifyTokenInvalid)?;
+
+    let (user_id, email) = parse_ident(&token.ident).ok_or(Error::EmailVerifyTokenInvalid)?;
+
+    let db = mm.db;
+    let (user_id,) = sqlx::query_as::<_, (i64,)>(
+        "UPDATE \"user\" SET verified_at = COALESCE(verified_at, $1)
+        WHERE id = $2 AND email = $3
+        RETURNING id",
+    )
+    .bind(now_utc_sec())
+    .bind(user_id)
+    .bind(email)
+    .fetch_optional(&db)
+    .await?
+    .ok_or(Error::EmailVerifyTokenInvalid)?;
+
+    debug!("{:<12} - user {user_id} verified", "EMAIL");
+
+    Ok(user_id)
+}
+
+fn parse_ident(ident: &str) -> Option<(i64, &str)> {
+    let rest = ident.strip_prefix(IDENT_PREFIX)?.strip_prefix(':')?;
+    let (user_id, email) = rest.split_once(':')?;
+
+    Some((user_id.parse().ok()?, email))
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+    use crate::ctx::Ctx;
+    use crate::model::rbac::{assign_role, get_role_by_name, has_permission, perms, ROLE_ADMIN};
+    use crate::notify::MemoryNotifier;
+
+    async fn seed_unverified_user(mm: &ModelManager, email: &str) -> Result<i64> {
+        let user_id = mm.seed_user(email).await?;
+        sqlx::query("UPDATE \"user\" SET verified_at = NULL WHERE id = $1")
+            .bind(user_id)
+            .execute(&mm.db)
+            .await?;
+
+        Ok(user_id)
+    }
+
+    #[tokio::test]
+    async fn test_verify_email_link() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = seed_unverified_user(&mm, "demo@mail.com").await?;
+        let notifier = MemoryNotifier::default();
+
+        send_verification(&notifier, user_id, "demo@mail.com")?;
+        let body = notifier.last_body().ok_or("no message sent")?;
+        let token = body
+            .split("token=")
+            .nth(1)
+            .ok_or("no token in link")?
+            .trim();
+
+        assert_eq!(verify_email(mm.clone(), token).await?, user_id);
+        // idempotent
+        assert_eq!(verify_email(mm.clone(), token).await?, user_id);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verify_email_err() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = seed_unverified_user(&mm, "demo@mail.com").await?;
+
+        // email changed since the link was sent
+        let token = generate_email_token(&format!("{IDENT_PREFIX}:{user_id}:old@mail.com"))?;
+        let res = verify_email(mm.clone(), &token.to_string()).await;
+        assert!(matches!(res, Err(super::Error::EmailVerifyTokenInvalid)));
+
+        // any other signed token
+        let token = generate_email_token("some-session-id")?;
+        let res = verify_email(mm.clone(), &token.to_string()).await;
+        assert!(matches!(res, Err(super::Error::EmailVerifyTokenInvalid)));
+
+        let res = verify_email(mm, "not-a-token").await;
+        assert!(matches!(res, Err(super::Error::EmailVerifyTokenInvalid)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unverified_no_permission() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = seed_unverified_user(&mm, "demo@mail.com").await?;
+        let ctx = Ctx::new(user_id, "session");
+        let admin = get_role_by_name(mm.clone(), ROLE_ADMIN)
+            .await?
+            .ok_or("admin role not seeded")?;
+        assign_role(mm.clone(), user_id, admin.id).await?;
+        mm.seed_totp(user_id).await?;
+
+        assert!(!has_permission(mm.clone(), &ctx, perms::USER_READ).await?);
+
+        let token = generate_email_token(&format!("{IDENT_PREFIX}:{user_id}:demo@mail.com"))?;
+        verify_email(mm.clone(), &token.to_string()).await?;
+        assert!(has_permission(mm, &ctx, perms::USER_READ).await?);
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
@@ -1,5 +1,8 @@
+use crate::{ldap, notify, pwd, totp};
 use axum::http::StatusCode;
 use derive_more::From;
+use lazy_regex::regex_captures;
+use lib_utils::pwd_policy::PwdIssue;
 use serde::Serialize;
 use serde_with::{serde_as, DisplayFromStr};
 
@@ -10,6 +13,111 @@ pub type Result<T> = core::result::Result<T, Error>;
 pub enum Error {
     // Store
     FailToCreatePool(String),
+    DbBackendMismatch {
+        url_backend: &'static str,
+        built_backend: &'static str,
+    },
+    TxnModeRequired,
+    TxnNotBegun,
+    TxnRolledBack,
+
+    // Migrations
+    MigrationChecksumMismatch {
+        version: i64,
+    },
+    MigrationUnknown {
+        version: i64,
+    },
+    MigrationOutOfOrder {
+        version: i64,
+    },
+
+    // Constraints
+    EmailAlreadyExists,
+    UniqueViolation {
+        table: String,
+        constraint: String,
+    },
+
+    // Entities
+    EntityNotFound {
+        entity: &'static str,
+        id: i64,
+    },
+    FieldsNotStruct,
+    ListQueryInvalid {
+        reason: String,
+    },
+    UserInactive {
+        id: i64,
+    },
+    CannotRemoveSelf,
+
+    // Rbac
+    PermissionDenied {
+        permission: String,
+    },
+
+    // Password policy
+    PwdPolicy {
+        issues: Vec<PwdIssue>,
+    },
+
+    // Refresh tokens
+    RefreshTokenReused,
+
+    // Password reset
+    PwdResetTokenInvalid,
+
+    // Email verification
+    EmailVerifyTokenInvalid,
+
+    // Invitations
+    InvitationRoleUnknown {
+        role: String,
+    },
+    InvitationTokenInvalid,
+    RegistrationClosed,
+
+    // TOTP
+    TotpAlreadyEnabled,
+    TotpNotEnrolled,
+    TotpInvalidCode,
+
+    // Login throttle
+    LoginLocked {
+        retry_after_sec: i64,
+    },
+
+    // API keys
+    ApiKeyScopeUnknown {
+        scope: String,
+    },
+    ApiKeyRequiresSession,
+
+    // Impersonation
+    CannotImpersonateSelf,
+    CannotImpersonatePrivileged {
+        id: i64,
+    },
+    NotAllowedWhileImpersonating,
+
+    // OIDC
+    OidcEmailMissing,
+    OidcEmailNotVerified,
+    OidcUserNotProvisioned {
+        email: String,
+    },
+
+    // Modules
+    #[from]
+    Pwd(pwd::Error),
+    #[from]
+    Notify(notify::Error),
+    #[from]
+    Totp(totp::Error),
+    #[from]
+    Ldap(ldap::Error),
 
     // Lib-utils
     #[from]
@@ -17,9 +125,56 @@ pub enum Error {
 
     // Externals
     #[from]
+    SerdeJson(#[serde_as(as = "DisplayFromStr")] serde_json::Error),
     Sqlx(#[serde_as(as = "DisplayFromStr")] sqlx::Error),
 }
 
+/// `UNIQUE` constraint failures become `UniqueViolation`, so the callers can
+/// match on them without parsing the driver message.
+impl From<sqlx::Error> for Error {
+    fn from(ex: sqlx::Error) -> Self {
+        // the transaction state errors of `Dbx`, through its executor
+        let ex = match ex {
+            sqlx::Error::Configuration(source) => match source.downcast::<Error>() {
+                Ok(err) => return *err,
+                Err(source) => sqlx::Error::Configuration(source),
+            },
+            ex => ex,
+        };
+
+        let Some(db_error) = ex.as_database_error() else {
+            return Self::Sqlx(ex);
+        };
+        if !db_error.is_unique_violation() {
+            return Self::Sqlx(ex);
+        }
+
+        // SQLite only reports `UNIQUE constraint failed: <table>.<column>`
+        let parsed = regex_captures!(
+            r"UNIQUE constraint failed: (\w+)\.(\w+)",
+            db_error.message()
+        );
+        let table = db_error
+            .table()
+            .or(parsed.map(|(_, table, _)| table))
+            .unwrap_or_default()
+            .to_string();
+        // Postgres names the constraint `<table>_<column>_key`
+        let constraint = db_error
+            .constraint()
+            .map(|name| {
+                name.strip_prefix(&format!("{table}_"))
+                    .and_then(|name| name.strip_suffix("_key"))
+                    .unwrap_or(name)
+            })
+            .or(parsed.map(|(_, _, column)| column))
+            .unwrap_or_default()
+            .to_string();
+
+        Self::UniqueViolation { table, constraint }
+    }
+}
+
 // region:      --- Error Boilerplate
 
 impl core::fmt::Display for Error {
@@ -31,3 +186,30 @@ impl core::fmt::Display for Error {
 impl std::error::Error for Error {}
 
 // endregion:   --- Error Boilerplate
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+    use crate::model::ModelManager;
+
+    #[tokio::test]
+    async fn test_unique_violation_from_sqlx() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        mm.seed_user("demo@mail.com").await?;
+
+        let res = mm.seed_user("demo@mail.com").await;
+        assert!(
+            matches!(&res, Err(super::Error::UniqueViolation { table, constraint })
+                if table == "user" && constraint == "email"),
+            "{res:?}"
+        );
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
@@ -0,0 +1,282 @@
+//! Impersonation, for support staff to see the app as a given user
+//!
+//! The impersonated user is stored on the session of the real user, so the
+//! session still expires and is revoked with the real user. Each start, stop
+//! and impersonated request is written to `impersonation_log`.
+
+use super::{
+    rbac::{check_permission, has_role, list_user_permissions, perms, ROLE_ADMIN},
+    user::get_user,
+    Error, ModelManager, Result,
+};
+use crate::ctx::Ctx;
+use lib_utils::time::now_utc_sec;
+use serde::Serialize;
+use sqlx::FromRow;
+use tracing::{debug, info};
+
+// region:        --- Types
+
+#[derive(FromRow, Serialize, Debug)]
+pub struct ImpersonationLogEntry {
+    pub id: i64,
+    pub real_user_id: i64,
+    pub user_id: i64,
+    /// `start`, `stop` or `request`.
+    pub action: String,
+    /// Method and path of the request.
+    pub detail: Option<String>,
+    pub created_at: i64,
+}
+
+// endregion:     --- Types
+
+/// Makes the session of the real user act as `user_id`, the real user needs
+/// `user.impersonate`. Admins, and users holding a permission the real user
+/// lacks, cannot be impersonated.
+pub async fn start_impersonation(mm: ModelManager, ctx: &Ctx, user_id: i64) -> Result<()> {
+    let session_id = ctx.session_id().ok_or(Error::ApiKeyRequiresSession)?;
+    let real_ctx = ctx.as_real_user();
+    check_permission(mm.clone(), &real_ctx, perms::USER_IMPERSONATE).await?;
+
+    if user_id == ctx.real_user_id() {
+        return Err(Error::CannotImpersonateSelf);
+    }
+    let user = get_user(mm.clone(), user_id).await?;
+    if !user.is_active {
+        return Err(Error::UserInactive { id: user_id });
+    }
+    if has_role(mm.clone(), user_id, ROLE_ADMIN).await? {
+        return Err(Error::CannotImpersonatePrivileged { id: user_id });
+    }
+    let real_permissions = list_user_permissions(mm.clone(), ctx.real_user_id()).await?;
+    let permissions = list_user_permissions(mm.clone(), user_id).await?;
+    if permissions.iter().any(|p| !real_permissions.contains(p)) {
+        return Err(Error::CannotImpersonatePrivileged { id: user_id });
+    }
+
+    let db = mm.db.clone();
+    sqlx::query("UPDATE session SET impersonated_user_id = $1 WHERE id = $2")
+        .bind(user_id)
+        .bind(session_id)
+        .execute(&db)
+        .await?;
+
+    let ctx = Ctx::new_impersonating(ctx.real_user_id(), user_id, session_id);
+    log_impersonation(mm, &ctx, "start", None).await
+}
+
+/// Back to the real user, does nothing when not impersonating.
+pub async fn stop_impersonation(mm: ModelManager, ctx: &Ctx) -> Result<()> {
+    let Some(session_id) = ctx.session_id().filter(|_| ctx.is_impersonating()) else {
+        return Ok(());
+    };
+
+    let db = mm.db.clone();
+    sqlx::query("UPDATE session SET impersonated_user_id = NULL WHERE id = $1")
+        .bind(session_id)
+        .execute(&db)
+        .await?;
+
+    log_impersonation(mm, ctx, "stop", None).await
+}
+
+/// For the changes the real user makes as itself only: credentials, TOTP and
+/// API keys.
+pub fn check_not_impersonating(ctx: &Ctx) -> Result<()> {
+    match ctx.is_impersonating() {
+        true => Err(Error::NotAllowedWhileImpersonating),
+        false => Ok(()),
+    }
+}
+
+/// Audit of a request made while impersonating, e.g. `GET /res/users`.
+pub async fn log_impersonated_request(mm: ModelManager, ctx: &Ctx, request: &str) -> Result<()> {
+    log_impersonation(mm, ctx, "request", Some(request)).await
+}
+
+async fn log_impersonation(
+    mm: ModelManager,
+    ctx: &Ctx,
+    action: &str,
+    detail: Option<&str>,
+) -> Result<()> {
+    info!(
+        "{:<12} - user {} as user {} - {action} {}",
+        "IMPERSONATE",
+        ctx.real_user_id(),
+        ctx.user_id(),
+        detail.unwrap_or_default()
+    );
+
+    let db = mm.db;
+    sqlx::query(
+        "INSERT INTO impersonation_log (real_user_id, user_id, session_id, action, detail, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(ctx.real_user_id())
+    .bind(ctx.user_id())
+    .bind(ctx.session_id())
+    .bind(action)
+    .bind(detail)
+    .bind(now_utc_sec())
+    .execute(&db)
+    .await?;
+
+    Ok(())
+}
+
+/// Latest entries first.
+pub async fn list_impersonation_log(mm: ModelManager) -> Result<Vec<ImpersonationLogEntry>> {
+    let db = mm.db;
+    let entries = sqlx::query_as::<_, ImpersonationLogEntry>(
+        "SELECT id, real_user_id, user_id, action, detail, created_at
+        FROM impersonation_log ORDER BY id DESC",
+    )
+    .fetch_all(&db)
+    .await?;
+
+    Ok(entries)
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+    use crate::model::api_key::{create_api_key, revoke_api_key, ApiKeyForCreate};
+    use crate::model::rbac::{
+        assign_admin_by_email, assign_role, create_permission, create_role, grant_permission,
+        has_permission,
+    };
+    use crate::model::session::{create_session, get_session};
+
+    #[tokio::test]
+    async fn test_impersonation_start_stop() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let admin_id = mm.seed_user("admin@mail.com").await?;
+        assign_admin_by_email(mm.clone(), "admin@mail.com").await?;
+        mm.seed_totp(admin_id).await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+        let session = create_session(mm.clone(), admin_id).await?;
+        let ctx = Ctx::new(admin_id, &session.id);
+
+        start_impersonation(mm.clone(), &ctx, user_id).await?;
+        let session = get_session(mm.clone(), &session.id)
+            .await?
+            .ok_or("session lost")?;
+        assert_eq!(session.impersonated_user_id, Some(user_id));
+
+        // the rights are the ones of the user
+        let ctx = Ctx::new_impersonating(admin_id, user_id, &session.id);
+        assert!(!has_permission(mm.clone(), &ctx, perms::USER_READ).await?);
+        log_impersonated_request(mm.clone(), &ctx, "GET /res/users").await?;
+
+        stop_impersonation(mm.clone(), &ctx).await?;
+        let session = get_session(mm.clone(), &session.id)
+            .await?
+            .ok_or("session lost")?;
+        assert!(session.impersonated_user_id.is_none());
+
+        let actions: Vec<String> = list_impersonation_log(mm)
+            .await?
+            .into_iter()
+            .map(|entry| entry.action)
+            .collect();
+        assert_eq!(actions, ["stop", "request", "start"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_impersonation_requires_permission() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+        let other_id = mm.seed_user("other@mail.com").await?;
+        let session = create_session(mm.clone(), user_id).await?;
+        let ctx = Ctx::new(user_id, &session.id);
+
+        let res = start_impersonation(mm.clone(), &ctx, other_id).await;
+        assert!(matches!(res, Err(super::Error::PermissionDenied { .. })));
+
+        // API keys have no session to carry the impersonation
+        let ctx = Ctx::new_for_api_key(user_id, vec![perms::USER_IMPERSONATE.to_string()]);
+        let res = start_impersonation(mm, &ctx, other_id).await;
+        assert!(matches!(res, Err(super::Error::ApiKeyRequiresSession)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_impersonation_privileged_target() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let support_id = mm.seed_user("support@mail.com").await?;
+        let support_role = create_role(mm.clone(), "support").await?;
+        let permission_id = create_permission(mm.clone(), perms::USER_IMPERSONATE).await?;
+        grant_permission(mm.clone(), support_role, permission_id).await?;
+        assign_role(mm.clone(), support_id, support_role).await?;
+        let session = create_session(mm.clone(), support_id).await?;
+        let ctx = Ctx::new(support_id, &session.id);
+
+        // admins, and users with a permission the real user lacks
+        let admin_id = mm.seed_user("admin@mail.com").await?;
+        assign_admin_by_email(mm.clone(), "admin@mail.com").await?;
+        let res = start_impersonation(mm.clone(), &ctx, admin_id).await;
+        assert!(
+            matches!(res, Err(super::Error::CannotImpersonatePrivileged { id }) if id == admin_id)
+        );
+
+        let reader_id = mm.seed_user("reader@mail.com").await?;
+        let reader_role = create_role(mm.clone(), "reader").await?;
+        let permission_id = create_permission(mm.clone(), perms::USER_READ).await?;
+        grant_permission(mm.clone(), reader_role, permission_id).await?;
+        assign_role(mm.clone(), reader_id, reader_role).await?;
+        let res = start_impersonation(mm.clone(), &ctx, reader_id).await;
+        assert!(matches!(
+            res,
+            Err(super::Error::CannotImpersonatePrivileged { .. })
+        ));
+
+        let user_id = mm.seed_user("demo@mail.com").await?;
+        start_impersonation(mm, &ctx, user_id).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_impersonation_no_credentials_change() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let admin_id = mm.seed_user("admin@mail.com").await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+        let session = create_session(mm.clone(), admin_id).await?;
+        let ctx = Ctx::new(admin_id, &session.id);
+        let key = create_api_key(mm.clone(), &ctx, key_for_create()).await?;
+
+        let ctx = Ctx::new_impersonating(admin_id, user_id, &session.id);
+        assert!(matches!(
+            check_not_impersonating(&ctx),
+            Err(super::Error::NotAllowedWhileImpersonating)
+        ));
+        let res = create_api_key(mm.clone(), &ctx, key_for_create()).await;
+        assert!(matches!(
+            res,
+            Err(super::Error::NotAllowedWhileImpersonating)
+        ));
+        let res = revoke_api_key(mm, &ctx, key.id).await;
+        assert!(matches!(
+            res,
+            Err(super::Error::NotAllowedWhileImpersonating)
+        ));
+        Ok(())
+    }
+
+    fn key_for_create() -> ApiKeyForCreate {
+        ApiKeyForCreate {
+            name: "ci".to_string(),
+            scopes: Vec::new(),
+            expires_in_sec: None,
+        }
+    }
+}
+
+// endregion: --- Tests
@@ -0,0 +1,288 @@
+//! Invitations, the way in when open registration is disabled
+//!
+//! An admin invites an email, optionally with a role to assign. The invitee
+//! gets a link with a single-use token (only its SHA-256 is stored), chooses a
+//! password and the account is created active and verified: the link proves
+//! the email.
+
+use super::{
+    rbac::{assign_role, get_role_by_name},
+    user::{check_pwd_policy, email_conflict},
+    Error, ModelManager, Result,
+};
+use crate::config;
+use crate::ctx::Ctx;
+use crate::notify::{Message, Notifier};
+use crate::pwd::hash_pwd;
+use crate::token::{generate_secret, hash_secret};
+use lib_utils::time::{now_utc_plus_sec, now_utc_sec};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tracing::debug;
+
+// region:        --- Types
+
+#[derive(FromRow, Serialize, Debug)]
+pub struct Invitation {
+    pub id: i64,
+    pub email: String,
+    /// Role assigned on acceptance.
+    pub role: Option<String>,
+    pub invited_by: Option<i64>,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub accepted_at: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct InvitationForCreate {
+    pub email: String,
+    pub role: Option<String>,
+}
+
+// endregion:     --- Types
+
+/// Creates the invitation and sends the link, the pending ones for the same email are dropped.
+pub async fn create_invitation(
+    mm: ModelManager,
+    notifier: &dyn Notifier,
+    ctx: &Ctx,
+    inv_c: InvitationForCreate,
+) -> Result<i64> {
+    let db = mm.db.clone();
+
+    let existing = sqlx::query_as::<_, (i64,)>("SELECT id FROM \"user\" WHERE email = $1")
+        .bind(&inv_c.email)
+        .fetch_optional(&db)
+        .await?;
+    if existing.is_some() {
+        return Err(Error::EmailAlreadyExists);
+    }
+
+    let role_id = match &inv_c.role {
+        Some(name) => Some(
+            get_role_by_name(mm, name)
+                .await?
+                .ok_or_else(|| Error::InvitationRoleUnknown { role: name.clone() })?
+                .id,
+        ),
+        None => None,
+    };
+
+    sqlx::query("DELETE FROM invitation WHERE email = $1 AND accepted_at IS NULL")
+        .bind(&inv_c.email)
+        .execute(&db)
+        .await?;
+
+    let token = generate_secret();
+    let (id,) = sqlx::query_as::<_, (i64,)>(
+        "INSERT INTO invitation (email, role_id, token_hash, invited_by, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+    )
+    .bind(&inv_c.email)
+    .bind(role_id)
+    .bind(hash_secret(&token))
+    .bind(ctx.user_id())
+    .bind(now_utc_sec())
+    .bind(now_utc_plus_sec(config().INVITATION_DURATION_SEC))
+    .fetch_one(&db)
+    .await?;
+
+    notifier.notify(&Message {
+        to: inv_c.email,
+        subject: "You are invited to the intranet".to_string(),
+        body: format!(
+            "Follow this link to choose your password and activate your account:\n{}/accept-invitation?token={token}",
+            config().WEB_URL
+        ),
+    })?;
+
+    Ok(id)
+}
+
+pub async fn list_invitations(mm: ModelManager) -> Result<Vec<Invitation>> {
+    let db = mm.db;
+    let invitations = sqlx::query_as::<_, Invitation>(
+        "SELECT i.id, i.email, r.name AS role, i.invited_by, i.created_at, i.expires_at, i.accepted_at
+        FROM invitation i LEFT JOIN role r ON r.id = i.role_id
+        ORDER BY i.id DESC",
+    )
+    .fetch_all(&db)
+    .await?;
+
+    Ok(invitations)
+}
+
+/// Deletes a pending invitation, its link stops working.
+pub async fn revoke_invitation(mm: ModelManager, id: i64) -> Result<()> {
+    let db = mm.db;
+    let res = sqlx::query("DELETE FROM invitation WHERE id = $1 AND accepted_at IS NULL")
+        .bind(id)
+        .execute(&db)
+        .await?;
+    if res.rows_affected() == 0 {
+        return Err(Error::EntityNotFound {
+            entity: "invitation",
+            id,
+        });
+    }
+
+    Ok(())
+}
+
+/// Creates the invited user with its password and role, returns the user id.
+pub async fn accept_invitation(mm: ModelManager, token: &str, pwd: &str) -> Result<i64> {
+    let db = mm.db.clone();
+    let now = now_utc_sec();
+
+    let (id, email, role_id) = sqlx::query_as::<_, (i64, String, Option<i64>)>(
+        "SELECT id, email, role_id FROM invitation
+        WHERE token_hash = $1 AND accepted_at IS NULL AND expires_at > $2",
+    )
+    .bind(hash_secret(token))
+    .bind(now)
+    .fetch_optional(&db)
+    .await?
+    .ok_or(Error::InvitationTokenInvalid)?;
+    check_pwd_policy(pwd, &email)?;
+    let pwd_hash = hash_pwd(pwd.to_string()).await?;
+
+    // the user, its role and the acceptance, or none of them
+    let mm = mm.new_with_txn();
+    mm.begin().await?;
+    let db = mm.db.clone();
+
+    // the unique email makes a second acceptance fail here
+    let (user_id,) = sqlx::query_as::<_, (i64,)>(
+        "INSERT INTO \"user\" (email, pwd, verified_at, created_at, updated_at)
+        VALUES ($1, $2, $3, $3, $3) RETURNING id",
+    )
+    .bind(&email)
+    .bind(pwd_hash)
+    .bind(now)
+    .fetch_one(&db)
+    .await
+    .map_err(email_conflict)?;
+
+    if let Some(role_id) = role_id {
+        assign_role(mm.clone(), user_id, role_id).await?;
+    }
+
+    sqlx::query("UPDATE invitation SET accepted_at = $1 WHERE id = $2")
+        .bind(now)
+        .bind(id)
+        .execute(&db)
+        .await?;
+    mm.commit().await?;
+    debug!("{:<12} - {email} accepted invitation {id}", "INVITATION");
+
+    Ok(user_id)
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+    use crate::model::rbac::{has_permission, perms, ROLE_ADMIN};
+    use crate::model::user::get_user_for_login;
+    use crate::notify::MemoryNotifier;
+
+    fn token_of(notifier: &MemoryNotifier) -> Result<String> {
+        let body = notifier.last_body().ok_or("no message sent")?;
+        let token = body.split("token=").nth(1).ok_or("no token in link")?;
+        Ok(token.trim().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_invitation_accept_ok() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let admin_id = mm.seed_user("admin@mail.com").await?;
+        let ctx = Ctx::new(admin_id, "session".to_string());
+        let notifier = MemoryNotifier::default();
+
+        let inv_c = InvitationForCreate {
+            email: "new@mail.com".to_string(),
+            role: Some(ROLE_ADMIN.to_string()),
+        };
+        create_invitation(mm.clone(), &notifier, &ctx, inv_c).await?;
+        let user_id =
+            accept_invitation(mm.clone(), &token_of(&notifier)?, "correct-Horse-battery").await?;
+
+        let user = get_user_for_login(mm.clone(), "new@mail.com")
+            .await?
+            .ok_or("user not created")?;
+        assert_eq!(user.id, user_id);
+        assert!(user.pwd.is_some());
+        assert!(user.verified_at.is_some() && user.is_active);
+        let ctx = Ctx::new(user_id, "session".to_string());
+        mm.seed_totp(user_id).await?;
+        assert!(has_permission(mm.clone(), &ctx, perms::USER_READ).await?);
+
+        let invitations = list_invitations(mm).await?;
+        assert_eq!(invitations[0].role.as_deref(), Some(ROLE_ADMIN));
+        assert!(invitations[0].accepted_at.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invitation_single_use() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let admin_id = mm.seed_user("admin@mail.com").await?;
+        let ctx = Ctx::new(admin_id, "session".to_string());
+        let notifier = MemoryNotifier::default();
+
+        let inv_c = InvitationForCreate {
+            email: "new@mail.com".to_string(),
+            role: None,
+        };
+        create_invitation(mm.clone(), &notifier, &ctx, inv_c).await?;
+        let token = token_of(&notifier)?;
+        accept_invitation(mm.clone(), &token, "correct-Horse-battery").await?;
+
+        let res = accept_invitation(mm, &token, "correct-Horse-battery").await;
+        assert!(matches!(res, Err(super::Error::InvitationTokenInvalid)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invitation_create_err() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let admin_id = mm.seed_user("admin@mail.com").await?;
+        let ctx = Ctx::new(admin_id, "session".to_string());
+        let notifier = MemoryNotifier::default();
+
+        let inv_c = InvitationForCreate {
+            email: "admin@mail.com".to_string(),
+            role: None,
+        };
+        let res = create_invitation(mm.clone(), &notifier, &ctx, inv_c).await;
+        assert!(matches!(res, Err(super::Error::EmailAlreadyExists)));
+
+        let inv_c = InvitationForCreate {
+            email: "new@mail.com".to_string(),
+            role: Some("unknown".to_string()),
+        };
+        let res = create_invitation(mm.clone(), &notifier, &ctx, inv_c).await;
+        assert!(matches!(
+            res,
+            Err(super::Error::InvitationRoleUnknown { .. })
+        ));
+
+        // a revoked link stops working
+        let inv_c = InvitationForCreate {
+            email: "new@mail.com".to_string(),
+            role: None,
+        };
+        let id = create_invitation(mm.clone(), &notifier, &ctx, inv_c).await?;
+        revoke_invitation(mm.clone(), id).await?;
+        let res = accept_invitation(mm, &token_of(&notifier)?, "correct-Horse-battery").await;
+        assert!(matches!(res, Err(super::Error::InvitationTokenInvalid)));
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
@@ -0,0 +1,330 @@
+//! Filters, ordering and pagination of the lists
+//!
+//! A filter is JSON, each column with a value to match or with operators:
+//!
+//! ```json
+//! {"email": {"$contains": "@acme.com"}, "is_active": true, "id": {"$gt": 100}}
+//! ```
+//!
+//! The conditions of an object must all match, an array of objects matches any
+//! of them. Operators: `$eq`, `$not`, `$in`, `$notIn`, `$contains`,
+//! `$notContains`, `$startsWith`, `$endsWith`, `$gt`, `$gte`, `$lt`, `$lte`
+//! and `$null` (`true` or `false`). Values are always bound, and only the
+//! `LIST_COLUMNS` of the entity can be filtered and ordered on.
+
+use super::base::push_value;
+use super::store::{DbBackend, LIKE, NOT_LIKE};
+use super::{Error, Result};
+use serde_json::Value;
+use sqlx::QueryBuilder;
+
+pub use lib_utils::list::ListOptions;
+
+/// Groups of conditions, a row matches when all the conditions of one group do.
+#[derive(Debug, Clone, Default)]
+pub struct ListFilter {
+    groups: Vec<Vec<Condition>>,
+}
+
+#[derive(Debug, Clone)]
+struct Condition {
+    column: String,
+    op: Op,
+    value: Value,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Eq,
+    Not,
+    In,
+    NotIn,
+    Contains,
+    NotContains,
+    StartsWith,
+    EndsWith,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Null,
+}
+
+impl ListFilter {
+    pub fn from_json(json: &str) -> Result<Self> {
+        let value = serde_json::from_str(json)
+            .map_err(|ex| invalid(format!("filters are not JSON: {ex}")))?;
+        Self::from_value(value)
+    }
+
+    pub fn from_value(value: Value) -> Result<Self> {
+        let groups = match value {
+            Value::Null => Vec::new(),
+            Value::Array(groups) => groups.into_iter().map(parse_group).collect::<Result<_>>()?,
+            group => vec![parse_group(group)?],
+        };
+
+        Ok(Self { groups })
+    }
+}
+
+fn parse_group(group: Value) -> Result<Vec<Condition>> {
+    let Value::Object(columns) = group else {
+        return Err(invalid("a filter is an object of columns".to_string()));
+    };
+
+    let mut conditions = Vec::new();
+    for (column, spec) in columns {
+        match spec {
+            // `{"$op": value, ...}`, else a value to match
+            Value::Object(ops) if ops.keys().all(|key| key.starts_with('$')) => {
+                for (op, value) in ops {
+                    let op = parse_op(&op)?;
+                    check_value(&column, op, &value)?;
+                    conditions.push(Condition {
+                        column: column.clone(),
+                        op,
+                        value,
+                    });
+                }
+            }
+            value => {
+                check_value(&column, Op::Eq, &value)?;
+                conditions.push(Condition {
+                    column,
+                    op: Op::Eq,
+                    value,
+                });
+            }
+        }
+    }
+
+    Ok(conditions)
+}
+
+fn parse_op(op: &str) -> Result<Op> {
+    let op = match op {
+        "$eq" => Op::Eq,
+        "$not" => Op::Not,
+        "$in" => Op::In,
+        "$notIn" => Op::NotIn,
+        "$contains" => Op::Contains,
+        "$notContains" => Op::NotContains,
+        "$startsWith" => Op::StartsWith,
+        "$endsWith" => Op::EndsWith,
+        "$gt" => Op::Gt,
+        "$gte" => Op::Gte,
+        "$lt" => Op::Lt,
+        "$lte" => Op::Lte,
+        "$null" => Op::Null,
+        other => return Err(invalid(format!("unknown operator `{other}`"))),
+    };
+
+    Ok(op)
+}
+
+fn check_value(column: &str, op: Op, value: &Value) -> Result<()> {
+    let valid = match op {
+        Op::Eq | Op::Not => !value.is_array() && !value.is_object(),
+        Op::In | Op::NotIn => value
+            .as_array()
+            .is_some_and(|values| values.iter().all(|v| !v.is_array() && !v.is_object())),
+        Op::Contains | Op::NotContains | Op::StartsWith | Op::EndsWith => value.is_string(),
+        Op::Gt | Op::Gte | Op::Lt | Op::Lte => value.is_number() || value.is_string(),
+        Op::Null => value.is_boolean(),
+    };
+
+    match valid {
+        true => Ok(()),
+        false => Err(invalid(format!("invalid value for `{column}`: {value}"))),
+    }
+}
+
+fn invalid(reason: String) -> Error {
+    Error::ListQueryInvalid { reason }
+}
+
+fn check_column(columns: &[&str], column: &str) -> Result<()> {
+    match columns.contains(&column) {
+        true => Ok(()),
+        false => Err(invalid(format!("unknown column `{column}`"))),
+    }
+}
+
+/// Appends the `WHERE`, `ORDER BY` (by id when not set), `LIMIT` and `OFFSET`
+/// clauses of the list.
+pub(super) fn push_list_clauses(
+    query: &mut QueryBuilder<DbBackend>,
+    columns: &[&str],
+    filter: Option<ListFilter>,
+    list_options: Option<ListOptions>,
+) -> Result<()> {
+    let groups: Vec<Vec<Condition>> = filter
+        .map(|filter| filter.groups)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|group| !group.is_empty())
+        .collect();
+
+    if !groups.is_empty() {
+        query.push(" WHERE ");
+        for (i, group) in groups.into_iter().enumerate() {
+            if i > 0 {
+                query.push(" OR ");
+            }
+            query.push("(");
+            for (j, condition) in group.into_iter().enumerate() {
+                if j > 0 {
+                    query.push(" AND ");
+                }
+                check_column(columns, &condition.column)?;
+                push_condition(query, condition);
+            }
+            query.push(")");
+        }
+    }
+
+    let list_options = list_options.unwrap_or_default();
+    let mut order_bys = Vec::new();
+    for (column, asc) in list_options.order_bys() {
+        check_column(columns, column)?;
+        order_bys.push(format!("{column} {}", if asc { "ASC" } else { "DESC" }));
+    }
+    if order_bys.is_empty() {
+        order_bys.push("id ASC".to_string());
+    }
+    query.push(format!(" ORDER BY {}", order_bys.join(", ")));
+
+    query
+        .push(" LIMIT ")
+        .push_bind(list_options.limit())
+        .push(" OFFSET ")
+        .push_bind(list_options.offset());
+
+    Ok(())
+}
+
+fn push_condition(query: &mut QueryBuilder<DbBackend>, condition: Condition) {
+    let Condition { column, op, value } = condition;
+
+    let (sql_op, value) = match (op, value) {
+        (Op::Eq, Value::Null) | (Op::Null, Value::Bool(true)) => {
+            query.push(format!("{column} IS NULL"));
+            return;
+        }
+        (Op::Not, Value::Null) | (Op::Null, _) => {
+            query.push(format!("{column} IS NOT NULL"));
+            return;
+        }
+        (Op::In | Op::NotIn, Value::Array(values)) if values.is_empty() => {
+            query.push(if matches!(op, Op::In) {
+                "0 = 1"
+            } else {
+                "1 = 1"
+            });
+            return;
+        }
+        (Op::In | Op::NotIn, Value::Array(values)) => {
+            let sql_op = if matches!(op, Op::In) { "IN" } else { "NOT IN" };
+            query.push(format!("{column} {sql_op} ("));
+            for (i, value) in values.into_iter().enumerate() {
+                if i > 0 {
+                    query.push(", ");
+                }
+                push_value(query, value);
+            }
+            query.push(")");
+            return;
+        }
+        (Op::Contains, Value::String(s)) => (LIKE, format!("%{}%", escape_like(&s)).into()),
+        (Op::NotContains, Value::String(s)) => (NOT_LIKE, format!("%{}%", escape_like(&s)).into()),
+        (Op::StartsWith, Value::String(s)) => (LIKE, format!("{}%", escape_like(&s)).into()),
+        (Op::EndsWith, Value::String(s)) => (LIKE, format!("%{}", escape_like(&s)).into()),
+        (Op::Not, value) => ("!=", value),
+        (Op::Gt, value) => (">", value),
+        (Op::Gte, value) => (">=", value),
+        (Op::Lt, value) => ("<", value),
+        (Op::Lte, value) => ("<=", value),
+        (_, value) => ("=", value),
+    };
+
+    query.push(format!("{column} {sql_op} "));
+    push_value(query, value);
+    if sql_op.ends_with("LIKE") {
+        query.push(" ESCAPE '\\'");
+    }
+}
+
+/// `%` and `_` match themselves.
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+
+    const COLUMNS: &[&str] = &["id", "email", "is_active"];
+
+    fn sql(filter: &str, list_options: ListOptions) -> Result<String> {
+        let mut query = QueryBuilder::<DbBackend>::new("SELECT * FROM user");
+        push_list_clauses(
+            &mut query,
+            COLUMNS,
+            Some(ListFilter::from_json(filter)?),
+            Some(list_options),
+        )?;
+        Ok(query.into_sql())
+    }
+
+    #[test]
+    fn test_list_filter_sql() -> Result<()> {
+        let sql = sql(
+            r#"[{"email": {"$contains": "50%_off"}, "is_active": true}, {"id": {"$in": [1, 2]}}]"#,
+            ListOptions {
+                order_bys: Some("!email".to_string()),
+                ..Default::default()
+            },
+        )?;
+        #[cfg(feature = "postgres")]
+        let expected = "SELECT * FROM user WHERE (email ILIKE $1 ESCAPE '\\' AND is_active = $2) \
+            OR (id IN ($3, $4)) ORDER BY email DESC LIMIT $5 OFFSET $6";
+        #[cfg(not(feature = "postgres"))]
+        let expected = "SELECT * FROM user WHERE (email LIKE ? ESCAPE '\\' AND is_active = ?) \
+            OR (id IN (?, ?)) ORDER BY email DESC LIMIT ? OFFSET ?";
+        assert_eq!(sql, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_filter_invalid() -> Result<()> {
+        let cases = [
+            r#"{"pwd": "x"}"#,
+            r#"{"email": {"$like": "x"}}"#,
+            r#"{"email": {"$in": "x"}}"#,
+            r#"{"id": {"$contains": 1}}"#,
+            r#""email""#,
+            "{",
+        ];
+        for filter in cases {
+            let res = sql(filter, ListOptions::default());
+            assert!(res.is_err(), "{filter}");
+        }
+
+        let order_by_pwd = ListOptions {
+            order_bys: Some("pwd".to_string()),
+            ..Default::default()
+        };
+        assert!(sql("{}", order_by_pwd).is_err());
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
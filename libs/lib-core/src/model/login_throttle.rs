@@ -0,0 +1,339 @@
+//! Login brute-force protection
+//!
+//! Failed attempts are counted per account (the typed email, known or not, and
+//! the DN it binds as with LDAP) and per client IP. Each attempt is counted before the credentials are checked,
+//! and released when they are right. Each failure blocks the next attempt with
+//! an exponential backoff, and `SERVICE_LOGIN_MAX_FAILURES` failures lock for
+//! `SERVICE_LOGIN_LOCKOUT_SEC`. Counters are forgotten after a lockout period
+//! without failure.
+//! The password reset requests are counted the same way, in their own scopes.
+
+use super::{auth_backend::AuthBackend, Error, ModelManager, Result};
+use crate::config;
+use crate::ldap::bind_dn;
+use lib_utils::time::now_utc_sec;
+use tracing::warn;
+
+const SCOPE_ACCOUNT: &str = "account";
+const SCOPE_IP: &str = "ip";
+const SCOPE_PWD_RESET: &str = "pwd-reset";
+const SCOPE_PWD_RESET_IP: &str = "pwd-reset-ip";
+
+/// Counts the attempt as a failure before the credentials are checked, so
+/// parallel guesses cannot all pass before the first failure is recorded.
+/// Fails with `Error::LoginLocked` while the account or the IP is blocked.
+pub async fn start_login_attempt(mm: ModelManager, email: &str, ip: Option<&str>) -> Result<()> {
+    let config = config();
+    let mut counters: Vec<(&str, String, i64)> = account_subjects(&config.AUTH_BACKENDS, email)
+        .into_iter()
+        .map(|subject| (SCOPE_ACCOUNT, subject, config.LOGIN_MAX_FAILURES))
+        .collect();
+    if let Some(ip) = ip {
+        counters.push((SCOPE_IP, ip.to_string(), config.LOGIN_MAX_FAILURES_PER_IP));
+    }
+
+    reserve_attempts(mm, &counters).await
+}
+
+/// Counts a password reset request, known email or not. Never released, each
+/// request delays the next one for the email and the IP, as a failed login.
+pub async fn start_pwd_reset_request(
+    mm: ModelManager,
+    email: &str,
+    ip: Option<&str>,
+) -> Result<()> {
+    let config = config();
+    let email = email.trim().to_lowercase();
+    let mut counters = vec![(SCOPE_PWD_RESET, email, config.LOGIN_MAX_FAILURES)];
+    if let Some(ip) = ip {
+        counters.push((
+            SCOPE_PWD_RESET_IP,
+            ip.to_string(),
+            config.LOGIN_MAX_FAILURES_PER_IP,
+        ));
+    }
+
+    reserve_attempts(mm, &counters).await
+}
+
+/// The credentials were right, the attempt is not a failure. The account
+/// counter is kept until `record_login_success`, e.g. for the TOTP step.
+pub async fn release_login_attempt(mm: ModelManager, email: &str, ip: Option<&str>) -> Result<()> {
+    for subject in account_subjects(&config().AUTH_BACKENDS, email) {
+        release_attempt(mm.clone(), SCOPE_ACCOUNT, &subject).await?;
+    }
+    if let Some(ip) = ip {
+        release_attempt(mm, SCOPE_IP, ip).await?;
+    }
+
+    Ok(())
+}
+
+/// Clears the account counter. Only the attempt is released from the IP one,
+/// a valid login on an account of the attacker must not reset it.
+pub async fn record_login_success(mm: ModelManager, email: &str, ip: Option<&str>) -> Result<()> {
+    if let Some(ip) = ip {
+        release_attempt(mm.clone(), SCOPE_IP, ip).await?;
+    }
+    unlock_account(mm, email).await
+}
+
+/// Admin operation, lifts the lockout of the account.
+pub async fn unlock_account(mm: ModelManager, email: &str) -> Result<()> {
+    let db = mm.db;
+    for subject in account_subjects(&config().AUTH_BACKENDS, email) {
+        sqlx::query("DELETE FROM login_throttle WHERE scope = $1 AND subject = $2")
+            .bind(SCOPE_ACCOUNT)
+            .bind(subject)
+            .execute(&db)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// All of the counters or none.
+async fn reserve_attempts(mm: ModelManager, counters: &[(&str, String, i64)]) -> Result<()> {
+    for (idx, (scope, subject, max)) in counters.iter().enumerate() {
+        if let Err(ex) = reserve_attempt(mm.clone(), scope, subject, *max).await {
+            for (scope, subject, _) in &counters[..idx] {
+                release_attempt(mm.clone(), scope, subject).await?;
+            }
+            return Err(ex);
+        }
+    }
+
+    Ok(())
+}
+
+async fn reserve_attempt(mm: ModelManager, scope: &str, subject: &str, max: i64) -> Result<()> {
+    let lockout_sec = config().LOGIN_LOCKOUT_SEC;
+    let now = now_utc_sec();
+
+    // counted and blocked for a second in one statement, the parallel attempts
+    // see the block, then the count sets its length
+    let db = mm.db;
+    let reserved = sqlx::query_as::<_, (i64,)>(
+        "INSERT INTO login_throttle (scope, subject, failures, last_failed_at, blocked_until)
+        VALUES ($1, $2, 1, $3, $3 + 1)
+        ON CONFLICT (scope, subject) DO UPDATE SET
+        failures = CASE WHEN login_throttle.last_failed_at + $4 > $3
+            THEN login_throttle.failures + 1 ELSE 1 END,
+        last_failed_at = $3, blocked_until = $3 + 1
+        WHERE login_throttle.blocked_until <= $3
+        RETURNING failures",
+    )
+    .bind(scope)
+    .bind(subject)
+    .bind(now)
+    .bind(lockout_sec)
+    .fetch_optional(&db)
+    .await?;
+
+    let Some((failures,)) = reserved else {
+        let (blocked_until,) = sqlx::query_as::<_, (i64,)>(
+            "SELECT blocked_until FROM login_throttle WHERE scope = $1 AND subject = $2",
+        )
+        .bind(scope)
+        .bind(subject)
+        .fetch_one(&db)
+        .await?;
+        return Err(Error::LoginLocked {
+            retry_after_sec: (blocked_until - now).max(1),
+        });
+    };
+
+    let block_sec = if failures >= max {
+        warn!(
+            "{:<12} - {scope} {subject} locked after {failures} failures",
+            "LOCKOUT"
+        );
+        lockout_sec
+    } else {
+        backoff_sec(failures).min(lockout_sec)
+    };
+    sqlx::query("UPDATE login_throttle SET blocked_until = $1 WHERE scope = $2 AND subject = $3")
+        .bind(now + block_sec)
+        .bind(scope)
+        .bind(subject)
+        .execute(&db)
+        .await?;
+
+    Ok(())
+}
+
+/// Takes back a reserved attempt and its block.
+async fn release_attempt(mm: ModelManager, scope: &str, subject: &str) -> Result<()> {
+    let db = mm.db;
+    sqlx::query(
+        "UPDATE login_throttle SET failures = CASE WHEN failures > 1 THEN failures - 1 ELSE 0 END,
+        blocked_until = $1 WHERE scope = $2 AND subject = $3",
+    )
+    .bind(now_utc_sec())
+    .bind(scope)
+    .bind(subject)
+    .execute(&db)
+    .await?;
+
+    Ok(())
+}
+
+/// 1s after the first failure, then doubles.
+fn backoff_sec(failures: i64) -> i64 {
+    2i64.saturating_pow((failures - 1).max(0) as u32)
+}
+
+/// The typed email, and the DN it binds as for each LDAP backend: with
+/// `{username}` in the template, `jdoe@a.com` and `jdoe@b.com` are the same
+/// directory account, and share its counter.
+fn account_subjects(backends: &[AuthBackend], email: &str) -> Vec<String> {
+    let email = email.trim().to_lowercase();
+    let mut subjects = vec![email.clone()];
+    for backend in backends {
+        if let AuthBackend::Ldap(ldap_config) = backend {
+            let dn = bind_dn(&ldap_config.bind_dn_template, &email);
+            if !subjects.contains(&dn) {
+                subjects.push(dn);
+            }
+        }
+    }
+    subjects
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+    use crate::ldap::tests::BIND_DN_TEMPLATE;
+    use crate::ldap::LdapConfig;
+
+    const IP: Option<&str> = Some("10.0.0.1");
+
+    #[tokio::test]
+    async fn test_login_backoff_and_lockout() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        start_login_attempt(mm.clone(), "demo@mail.com", IP).await?;
+
+        // counted until released, the next attempt waits
+        let res = start_login_attempt(mm.clone(), "Demo@mail.com", None).await;
+        assert!(matches!(
+            res,
+            Err(super::Error::LoginLocked { retry_after_sec }) if retry_after_sec <= 1
+        ));
+
+        for _ in 1..config().LOGIN_MAX_FAILURES {
+            expire_blocks(&mm).await?;
+            start_login_attempt(mm.clone(), "demo@mail.com", IP).await?;
+        }
+        let res = start_login_attempt(mm.clone(), "demo@mail.com", None).await;
+        assert!(matches!(
+            res,
+            Err(super::Error::LoginLocked { retry_after_sec })
+                if retry_after_sec > config().LOGIN_LOCKOUT_SEC - 5
+        ));
+
+        unlock_account(mm.clone(), "demo@mail.com").await?;
+        start_login_attempt(mm.clone(), "demo@mail.com", None).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_login_attempt_released() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+
+        start_login_attempt(mm.clone(), "demo@mail.com", IP).await?;
+        release_login_attempt(mm.clone(), "demo@mail.com", IP).await?;
+        start_login_attempt(mm.clone(), "demo@mail.com", IP).await?;
+        record_login_success(mm.clone(), "demo@mail.com", IP).await?;
+        start_login_attempt(mm, "demo@mail.com", IP).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_login_parallel_attempts() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+
+        // a burst of guesses, only one is checked
+        let attempts = (0..5).map(|_| start_login_attempt(mm.clone(), "demo@mail.com", IP));
+        let results = futures::future::join_all(attempts).await;
+        assert_eq!(results.iter().filter(|res| res.is_ok()).count(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_login_ip_kept_on_success() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+
+        start_login_attempt(mm.clone(), "victim@mail.com", IP).await?;
+        start_login_attempt(mm.clone(), "victim@mail.com", None)
+            .await
+            .err()
+            .ok_or("not blocked")?;
+        record_login_success(mm.clone(), "victim@mail.com", None).await?;
+
+        start_login_attempt(mm.clone(), "victim@mail.com", None).await?;
+        let res = start_login_attempt(mm.clone(), "other@mail.com", IP).await;
+        assert!(matches!(res, Err(super::Error::LoginLocked { .. })));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pwd_reset_request_throttled() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+
+        start_pwd_reset_request(mm.clone(), "demo@mail.com", IP).await?;
+        let res = start_pwd_reset_request(mm.clone(), "Demo@mail.com", None).await;
+        assert!(matches!(res, Err(super::Error::LoginLocked { .. })));
+        let res = start_pwd_reset_request(mm.clone(), "other@mail.com", IP).await;
+        assert!(matches!(res, Err(super::Error::LoginLocked { .. })));
+
+        // own scopes, the login is not delayed
+        start_login_attempt(mm, "demo@mail.com", IP).await?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_account_subjects_ldap_dn() {
+        let ldap = AuthBackend::Ldap(LdapConfig {
+            url: "ldap://127.0.0.1".to_string(),
+            bind_dn_template: BIND_DN_TEMPLATE.to_string(),
+            attr_email: "mail".to_string(),
+            attr_display_name: "displayName".to_string(),
+        });
+        let backends = [AuthBackend::Local, ldap];
+
+        let subjects = account_subjects(&backends, "JDoe@a.com");
+        assert_eq!(
+            subjects,
+            ["jdoe@a.com", "uid=jdoe,ou=people,dc=example,dc=org"]
+        );
+        // another domain, same directory account
+        assert_eq!(account_subjects(&backends, "jdoe@b.com")[1], subjects[1]);
+        assert_eq!(
+            account_subjects(&[AuthBackend::Local], "jdoe@a.com").len(),
+            1
+        );
+    }
+
+    /// As if the backoff delays were over.
+    async fn expire_blocks(mm: &ModelManager) -> Result<()> {
+        sqlx::query("UPDATE login_throttle SET blocked_until = 0")
+            .execute(&mm.db)
+            .await?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_backoff_sec() -> Result<()> {
+        assert_eq!(backoff_sec(1), 1);
+        assert_eq!(backoff_sec(4), 8);
+        assert_eq!(backoff_sec(100), i64::MAX);
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
@@ -0,0 +1,358 @@
+//! Versioned schema migrations
+//!
+//! The schema evolves through the ordered SQL files of `lib-core/migrations`,
+//! `NNNN_name.up.sql` with its `NNNN_name.down.sql`, registered in `MIGRATIONS`.
+//! Each store backend has its directory, `sqlite` and `postgres`, with the same
+//! migrations written in its dialect, the one of the build is embedded.
+//! The `_migrations` table records the versions applied with the checksum of
+//! their up script: an applied migration must not be edited, add a new one.
+//!
+//! Each migration runs in a transaction, unless its script starts with
+//! `-- migrate:no-transaction` for statements that cannot.
+//! The first one is the schema of the release before the migrations, it adopts
+//! the databases created by that release.
+
+use super::store::Db;
+use super::{Error, ModelManager, Result};
+use lib_utils::time::now_utc_sec;
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+const NO_TRANSACTION: &str = "-- migrate:no-transaction";
+
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    up: &'static str,
+    down: &'static str,
+}
+
+#[cfg(feature = "postgres")]
+macro_rules! migrations_dir {
+    () => {
+        "../../migrations/postgres/"
+    };
+}
+#[cfg(not(feature = "postgres"))]
+macro_rules! migrations_dir {
+    () => {
+        "../../migrations/sqlite/"
+    };
+}
+
+macro_rules! migration {
+    ($version:literal, $file:literal) => {
+        Migration {
+            version: $version,
+            name: $file,
+            up: include_str!(concat!(migrations_dir!(), $file, ".up.sql")),
+            down: include_str!(concat!(migrations_dir!(), $file, ".down.sql")),
+        }
+    };
+}
+
+/// In version order, append only.
+pub const MIGRATIONS: &[Migration] = &[
+    migration!(1, "0001_user"),
+    migration!(2, "0002_session"),
+    migration!(3, "0003_rbac"),
+    migration!(4, "0004_pwd_reset"),
+    migration!(5, "0005_email_verify"),
+    migration!(6, "0006_totp"),
+    migration!(7, "0007_login_throttle"),
+    migration!(8, "0008_api_key"),
+    migration!(9, "0009_user_oidc"),
+    migration!(10, "0010_user_ldap"),
+    migration!(11, "0011_user_profile"),
+    migration!(12, "0012_token_version"),
+    migration!(13, "0013_invitation"),
+    migration!(14, "0014_impersonation"),
+    migration!(15, "0015_refresh_token"),
+];
+
+impl Migration {
+    fn checksum(&self) -> String {
+        format!("{:x}", Sha256::digest(self.up.as_bytes()))
+    }
+
+    fn in_transaction(sql: &str) -> bool {
+        !sql.trim_start().starts_with(NO_TRANSACTION)
+    }
+}
+
+/// Applies the pending migrations, returns their versions. Fails before
+/// changing anything if an applied migration was edited, or is unknown to
+/// this build.
+pub async fn migrate(mm: ModelManager) -> Result<Vec<i64>> {
+    apply_migrations(mm.db.pool(), MIGRATIONS).await
+}
+
+/// Reverts the migrations above `version`, latest first, returns their versions.
+pub async fn rollback_to(mm: ModelManager, version: i64) -> Result<Vec<i64>> {
+    revert_migrations(mm.db.pool(), MIGRATIONS, version).await
+}
+
+/// Latest version applied, 0 on an empty database.
+pub async fn current_version(mm: ModelManager) -> Result<i64> {
+    let db = mm.db.pool();
+    create_migrations_table(db).await?;
+
+    let (version,) =
+        sqlx::query_as::<_, (i64,)>("SELECT COALESCE(MAX(version), 0) FROM _migrations")
+            .fetch_one(db)
+            .await?;
+
+    Ok(version)
+}
+
+async fn apply_migrations(db: &Db, migrations: &[Migration]) -> Result<Vec<i64>> {
+    create_migrations_table(db).await?;
+    let applied = check_applied(db, migrations).await?;
+
+    let last = applied.iter().max().copied().unwrap_or(0);
+    let pending: Vec<&Migration> = migrations
+        .iter()
+        .filter(|m| !applied.contains(&m.version))
+        .collect();
+    if let Some(m) = pending.iter().find(|m| m.version < last) {
+        return Err(Error::MigrationOutOfOrder { version: m.version });
+    }
+
+    let mut versions = Vec::new();
+    for m in pending {
+        let record = sqlx::query(
+            "INSERT INTO _migrations (version, name, checksum, applied_at) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(m.version)
+        .bind(m.name)
+        .bind(m.checksum())
+        .bind(now_utc_sec());
+
+        if Migration::in_transaction(m.up) {
+            let mut txn = db.begin().await?;
+            sqlx::raw_sql(m.up).execute(&mut *txn).await?;
+            record.execute(&mut *txn).await?;
+            txn.commit().await?;
+        } else {
+            sqlx::raw_sql(m.up).execute(db).await?;
+            record.execute(db).await?;
+        }
+
+        info!("{:<12} - Migration {} applied", "DATABASE", m.name);
+        versions.push(m.version);
+    }
+
+    Ok(versions)
+}
+
+async fn revert_migrations(db: &Db, migrations: &[Migration], version: i64) -> Result<Vec<i64>> {
+    create_migrations_table(db).await?;
+    let mut applied = check_applied(db, migrations).await?;
+    applied.retain(|v| *v > version);
+    applied.sort_unstable_by(|a, b| b.cmp(a));
+
+    for v in &applied {
+        let Some(m) = migrations.iter().find(|m| m.version == *v) else {
+            return Err(Error::MigrationUnknown { version: *v });
+        };
+        let record = sqlx::query("DELETE FROM _migrations WHERE version = $1").bind(m.version);
+
+        if Migration::in_transaction(m.down) {
+            let mut txn = db.begin().await?;
+            sqlx::raw_sql(m.down).execute(&mut *txn).await?;
+            record.execute(&mut *txn).await?;
+            txn.commit().await?;
+        } else {
+            sqlx::raw_sql(m.down).execute(db).await?;
+            record.execute(db).await?;
+        }
+
+        info!("{:<12} - Migration {} reverted", "DATABASE", m.name);
+    }
+
+    Ok(applied)
+}
+
+async fn create_migrations_table(db: &Db) -> Result<()> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS _migrations (
+    version BIGINT PRIMARY KEY,
+    name varchar(128) NOT NULL,
+    checksum varchar(64) NOT NULL,
+    applied_at BIGINT NOT NULL
+    )",
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Versions applied, each known and unchanged.
+async fn check_applied(db: &Db, migrations: &[Migration]) -> Result<Vec<i64>> {
+    let rows = sqlx::query_as::<_, (i64, String)>(
+        "SELECT version, checksum FROM _migrations ORDER BY version",
+    )
+    .fetch_all(db)
+    .await?;
+
+    for (version, checksum) in &rows {
+        let m = migrations
+            .iter()
+            .find(|m| m.version == *version)
+            .ok_or(Error::MigrationUnknown { version: *version })?;
+        if m.checksum() != *checksum {
+            return Err(Error::MigrationChecksumMismatch { version: *version });
+        }
+    }
+
+    Ok(rows.into_iter().map(|(version, _)| version).collect())
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+    use crate::model::store::{column_exists, new_test_db_pool};
+
+    const NOTE: Migration = Migration {
+        version: 1,
+        name: "0001_note",
+        up: "CREATE TABLE note (id INTEGER PRIMARY KEY, body TEXT);",
+        down: "DROP TABLE note;",
+    };
+    const NOTE_TITLE: Migration = Migration {
+        version: 2,
+        name: "0002_note_title",
+        up: "ALTER TABLE note ADD COLUMN title TEXT; CREATE INDEX note_title ON note(title);",
+        down: "DROP INDEX note_title; ALTER TABLE note DROP COLUMN title;",
+    };
+
+    async fn has_column(db: &Db, column: &str) -> Result<bool> {
+        Ok(column_exists(db, "note", column).await?)
+    }
+
+    #[tokio::test]
+    async fn test_migration_apply_and_rollback() -> Result<()> {
+        let db = new_test_db_pool().await?;
+
+        assert_eq!(apply_migrations(&db, &[NOTE]).await?, [1]);
+        assert_eq!(apply_migrations(&db, &[NOTE, NOTE_TITLE]).await?, [2]);
+        assert!(apply_migrations(&db, &[NOTE, NOTE_TITLE]).await?.is_empty());
+        assert!(has_column(&db, "title").await?);
+
+        assert_eq!(revert_migrations(&db, &[NOTE, NOTE_TITLE], 1).await?, [2]);
+        assert!(!has_column(&db, "title").await?);
+        assert!(has_column(&db, "body").await?);
+
+        assert_eq!(revert_migrations(&db, &[NOTE, NOTE_TITLE], 0).await?, [1]);
+        assert!(!has_column(&db, "body").await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_migration_failure_rolls_back() -> Result<()> {
+        let db = new_test_db_pool().await?;
+        let broken = Migration {
+            version: 2,
+            name: "0002_broken",
+            up: "ALTER TABLE note ADD COLUMN title TEXT; SELECT * FROM missing;",
+            down: "",
+        };
+
+        apply_migrations(&db, &[NOTE]).await?;
+        assert!(apply_migrations(&db, &[NOTE, broken]).await.is_err());
+        assert!(!has_column(&db, "title").await?);
+        assert_eq!(apply_migrations(&db, &[NOTE, NOTE_TITLE]).await?, [2]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_migration_checks() -> Result<()> {
+        let db = new_test_db_pool().await?;
+        apply_migrations(&db, &[NOTE, NOTE_TITLE]).await?;
+
+        let edited = Migration {
+            up: "CREATE TABLE note (id INTEGER PRIMARY KEY);",
+            ..NOTE
+        };
+        let res = apply_migrations(&db, &[edited, NOTE_TITLE]).await;
+        assert!(matches!(
+            res,
+            Err(super::Error::MigrationChecksumMismatch { version: 1 })
+        ));
+
+        let res = apply_migrations(&db, &[NOTE]).await;
+        assert!(matches!(
+            res,
+            Err(super::Error::MigrationUnknown { version: 2 })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_migrations_rollback_all() -> Result<()> {
+        let db = new_test_db_pool().await?;
+        let versions: Vec<i64> = MIGRATIONS.iter().map(|m| m.version).collect();
+
+        assert_eq!(apply_migrations(&db, MIGRATIONS).await?, versions);
+        let reverted = revert_migrations(&db, MIGRATIONS, 0).await?;
+        assert_eq!(reverted, versions.iter().rev().copied().collect::<Vec<_>>());
+        assert_eq!(apply_migrations(&db, MIGRATIONS).await?, versions);
+        Ok(())
+    }
+
+    /// The release before the migrations only had this table, with SQLite.
+    #[cfg(not(feature = "postgres"))]
+    #[tokio::test]
+    async fn test_migrations_adopt_released_schema() -> Result<()> {
+        let db = new_test_db_pool().await?;
+        sqlx::raw_sql(
+            "CREATE TABLE user (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            email varchar(128) NOT NULL UNIQUE,
+            pwd varchar(256)
+            );
+            INSERT INTO user (email, pwd) VALUES ('demo@mail.com', NULL);",
+        )
+        .execute(&db)
+        .await?;
+
+        apply_migrations(&db, MIGRATIONS).await?;
+        assert!(column_exists(&db, "user", "token_version").await?);
+        let (email, is_active) =
+            sqlx::query_as::<_, (String, bool)>("SELECT email, is_active FROM user")
+                .fetch_one(&db)
+                .await?;
+        assert_eq!(email, "demo@mail.com");
+        assert!(is_active);
+        Ok(())
+    }
+
+    #[test]
+    fn test_migration_files_registered() -> Result<()> {
+        let expected: Vec<String> = MIGRATIONS
+            .iter()
+            .flat_map(|m| [format!("{}.down.sql", m.name), format!("{}.up.sql", m.name)])
+            .collect();
+
+        // every backend has all the migrations
+        for backend in ["postgres", "sqlite"] {
+            let dir = format!("{}/migrations/{backend}", env!("CARGO_MANIFEST_DIR"));
+            let mut files: Vec<String> = std::fs::read_dir(dir)?
+                .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                .collect::<std::io::Result<_>>()?;
+            files.sort();
+            assert_eq!(files, expected, "{backend}");
+        }
+        assert!(MIGRATIONS.windows(2).all(|w| w[0].version < w[1].version));
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
@@ -1,21 +1,100 @@
 mod error;
 pub mod store;
+pub mod dbx;
+pub mod base;
+pub mod list;
+pub mod migration;
 pub mod user;
+pub mod session;
+pub mod refresh_token;
+pub mod rbac;
+pub mod pwd_reset;
+pub mod email_verify;
+pub mod user_totp;
+pub mod login_throttle;
+pub mod api_key;
+pub mod user_oidc;
+pub mod user_ldap;
+pub mod auth_backend;
+pub mod invitation;
+pub mod impersonation;
 pub mod app_state;
 
-use store::{new_db_pool, Db};
+use dbx::Dbx;
+use store::new_db_pool;
 
 pub use self::error::{Error, Result};
 
 #[derive(Debug,Clone)]
 pub struct ModelManager {
-    db: Db,
+    db: Dbx,
 }
 
 impl ModelManager {
     pub async fn new() -> Result<Self> {
-        let db = new_db_pool().await?;
+        let db = Dbx::new(new_db_pool().await?);
 
         Ok(ModelManager { db })
     }
+
+    /// Same database, the model functions given it (or its clones) run in its
+    /// transaction between `begin` and `commit`, see `model::dbx`.
+    pub fn new_with_txn(&self) -> ModelManager {
+        ModelManager {
+            db: self.db.new_with_txn(),
+        }
+    }
+
+    pub async fn begin(&self) -> Result<()> {
+        self.db.begin_txn().await
+    }
+
+    pub async fn commit(&self) -> Result<()> {
+        self.db.commit_txn().await
+    }
+
+    pub async fn rollback(&self) -> Result<()> {
+        self.db.rollback_txn().await
+    }
+}
+
+#[cfg(test)]
+impl ModelManager {
+    /// Fresh test database with all the migrations applied.
+    pub(crate) async fn new_for_test() -> Result<Self> {
+        let mm = ModelManager {
+            db: Dbx::new(store::new_test_db_pool().await?),
+        };
+        migration::migrate(mm.clone()).await?;
+        rbac::seed_rbac(mm.clone()).await?;
+
+        Ok(mm)
+    }
+
+    /// Inserts a verified user without the password hashing cost.
+    pub(crate) async fn seed_user(&self, email: &str) -> Result<i64> {
+        let (id,) = sqlx::query_as::<_, (i64,)>(
+            "INSERT INTO \"user\" (email, verified_at, created_at, updated_at) VALUES ($1, $2, $2, $2)
+            RETURNING id",
+        )
+            .bind(email)
+            .bind(lib_utils::time::now_utc_sec())
+            .fetch_one(&self.db)
+            .await?;
+
+        Ok(id)
+    }
+
+    /// Marks the TOTP of the user as enabled, the secret is not usable.
+    pub(crate) async fn seed_totp(&self, user_id: i64) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO user_totp (user_id, secret_enc, enabled_at) VALUES ($1, 'seed', $2)",
+        )
+        .bind(user_id)
+        .bind(lib_utils::time::now_utc_sec())
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
 }
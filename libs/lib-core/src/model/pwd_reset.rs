@@ -0,0 +1,195 @@
+//! Password reset with single-use tokens
+//!
+//! Only the SHA-256 of the token is stored, the token itself is only sent to
+//! the user through the `Notifier`.
+
+use super::{
+    login_throttle::start_pwd_reset_request,
+    session::revoke_user_sessions,
+    user::{check_pwd_policy, get_user_for_login, update_pwd},
+    Error, ModelManager, Result,
+};
+use crate::config;
+use crate::notify::{Message, Notifier};
+use crate::token::{generate_secret, hash_secret};
+use lib_utils::time::{now_utc_plus_sec, now_utc_sec};
+use tracing::debug;
+
+/// Issues a new reset token for the user, the previous pending ones are dropped.
+pub async fn create_pwd_reset(mm: ModelManager, user_id: i64) -> Result<String> {
+    let token = generate_secret();
+
+    let db = mm.db;
+    sqlx::query("DELETE FROM pwd_reset WHERE user_id = $1 AND used_at IS NULL")
+        .bind(user_id)
+        .execute(&db)
+        .await?;
+    sqlx::query(
+        "INSERT INTO pwd_reset (user_id, token_hash, created_at, expires_at) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(user_id)
+    .bind(hash_secret(&token))
+    .bind(now_utc_sec())
+    .bind(now_utc_plus_sec(config().PWD_RESET_DURATION_SEC))
+    .execute(&db)
+    .await?;
+
+    Ok(token)
+}
+
+/// Marks the token as used and returns its user, fails if unknown, used or expired.
+pub async fn consume_pwd_reset(mm: ModelManager, token: &str) -> Result<i64> {
+    let db = mm.db;
+    let (user_id,) = sqlx::query_as::<_, (i64,)>(
+        "UPDATE pwd_reset SET used_at = $1
+        WHERE token_hash = $2 AND used_at IS NULL AND expires_at > $1
+        RETURNING user_id",
+    )
+    .bind(now_utc_sec())
+    .bind(hash_secret(token))
+    .fetch_optional(&db)
+    .await?
+    .ok_or(Error::PwdResetTokenInvalid)?;
+
+    Ok(user_id)
+}
+
+/// Email of the user of a valid token, the token stays unused.
+async fn pending_pwd_reset_email(mm: ModelManager, token: &str) -> Result<String> {
+    let db = mm.db;
+    let (email,) = sqlx::query_as::<_, (String,)>(
+        "SELECT u.email FROM pwd_reset r JOIN \"user\" u ON u.id = r.user_id
+        WHERE r.token_hash = $1 AND r.used_at IS NULL AND r.expires_at > $2",
+    )
+    .bind(hash_secret(token))
+    .bind(now_utc_sec())
+    .fetch_optional(&db)
+    .await?
+    .ok_or(Error::PwdResetTokenInvalid)?;
+
+    Ok(email)
+}
+
+// region:        --- Flow
+
+/// Sends a reset link if the email is known. Silent otherwise, to not disclose
+/// which emails are registered. Fails with `LoginLocked` when the email or the
+/// IP requested one too recently, see `login_throttle`.
+pub async fn request_pwd_reset(
+    mm: ModelManager,
+    notifier: &dyn Notifier,
+    email: &str,
+    ip: Option<&str>,
+) -> Result<()> {
+    start_pwd_reset_request(mm.clone(), email, ip).await?;
+
+    let Some(user) = get_user_for_login(mm.clone(), email).await? else {
+        debug!("{:<12} - unknown email {email}", "PWD_RESET");
+        return Ok(());
+    };
+
+    let token = create_pwd_reset(mm, user.id).await?;
+    notifier.notify(&Message {
+        to: user.email,
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Follow this link to choose a new password:\n{}/reset-password?token={token}",
+            config().WEB_URL
+        ),
+    })?;
+
+    Ok(())
+}
+
+/// Sets the new password and closes every open session of the user. The
+/// password is checked before the token is used, a rejected one can be retried.
+/// The token is used in the transaction of the update, kept if it fails.
+pub async fn complete_pwd_reset(mm: ModelManager, token: &str, pwd: &str) -> Result<()> {
+    let email = pending_pwd_reset_email(mm.clone(), token).await?;
+    check_pwd_policy(pwd, &email)?;
+
+    let mm = mm.new_with_txn();
+    mm.begin().await?;
+    let user_id = consume_pwd_reset(mm.clone(), token).await?;
+    update_pwd(mm.clone(), user_id, pwd).await?;
+    revoke_user_sessions(mm.clone(), user_id).await?;
+    mm.commit().await?;
+
+    Ok(())
+}
+
+// endregion:     --- Flow
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+    use crate::notify::MemoryNotifier;
+    use lib_utils::pwd_policy::PwdIssue;
+
+    #[tokio::test]
+    async fn test_pwd_reset_single_use() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+
+        let token = create_pwd_reset(mm.clone(), user_id).await?;
+        assert_eq!(consume_pwd_reset(mm.clone(), &token).await?, user_id);
+
+        let res = consume_pwd_reset(mm.clone(), &token).await;
+        assert!(matches!(res, Err(super::Error::PwdResetTokenInvalid)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pwd_reset_only_last_token() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+
+        let first = create_pwd_reset(mm.clone(), user_id).await?;
+        let last = create_pwd_reset(mm.clone(), user_id).await?;
+
+        let res = consume_pwd_reset(mm.clone(), &first).await;
+        assert!(matches!(res, Err(super::Error::PwdResetTokenInvalid)));
+        assert_eq!(consume_pwd_reset(mm.clone(), &last).await?, user_id);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_request_pwd_reset_notify() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        mm.seed_user("demo@mail.com").await?;
+        let notifier = MemoryNotifier::default();
+
+        request_pwd_reset(mm.clone(), &notifier, "unknown@mail.com", None).await?;
+        assert!(notifier.last_body().is_none());
+
+        request_pwd_reset(mm.clone(), &notifier, "demo@mail.com", None).await?;
+        let body = notifier.last_body().ok_or("no message sent")?;
+        let token = body.split("token=").nth(1).ok_or("no token in link")?;
+        consume_pwd_reset(mm, token.trim()).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_complete_pwd_reset_policy() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+        let token = create_pwd_reset(mm.clone(), user_id).await?;
+
+        let res = complete_pwd_reset(mm.clone(), &token, "demo@mail.com-Pwd1").await;
+        assert!(matches!(
+            res,
+            Err(super::Error::PwdPolicy { issues }) if issues == [PwdIssue::ContainsEmail]
+        ));
+
+        // the token was not used by the rejected password
+        complete_pwd_reset(mm, &token, "correct-Horse-battery").await?;
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
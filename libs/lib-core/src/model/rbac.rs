@@ -0,0 +1,300 @@
+//! Role-based access control
+//!
+//! Users get roles, roles get permissions. Permissions are plain names like
+//! `user.read`, checked against the `Ctx` of the request.
+
+use super::{Error, ModelManager, Result};
+use crate::ctx::Ctx;
+use lib_utils::time::now_utc_sec;
+use serde::Serialize;
+use sqlx::FromRow;
+use tracing::debug;
+
+// region:        --- Roles & Permissions
+
+pub const ROLE_ADMIN: &str = "admin";
+
+pub mod perms {
+    pub const USER_READ: &str = "user.read";
+    pub const USER_CREATE: &str = "user.create";
+    pub const USER_UPDATE: &str = "user.update";
+    pub const USER_DELETE: &str = "user.delete";
+    pub const USER_TOTP_RESET: &str = "user.totp.reset";
+    pub const USER_UNLOCK: &str = "user.unlock";
+    pub const USER_INVITE: &str = "user.invite";
+    pub const USER_IMPERSONATE: &str = "user.impersonate";
+
+    /// Every permission known by the app, all granted to the admin role.
+    pub const ALL: &[&str] = &[
+        USER_READ,
+        USER_CREATE,
+        USER_UPDATE,
+        USER_DELETE,
+        USER_TOTP_RESET,
+        USER_UNLOCK,
+        USER_INVITE,
+        USER_IMPERSONATE,
+    ];
+}
+
+// endregion:     --- Roles & Permissions
+
+// region:        --- Types
+
+#[derive(FromRow, Serialize, Debug)]
+pub struct Role {
+    pub id: i64,
+    pub name: String,
+}
+
+// endregion:     --- Types
+
+/// Creates the admin role with every permission of `perms::ALL`.
+pub async fn seed_rbac(mm: ModelManager) -> Result<()> {
+    let admin_id = create_role(mm.clone(), ROLE_ADMIN).await?;
+    for name in perms::ALL {
+        let permission_id = create_permission(mm.clone(), name).await?;
+        grant_permission(mm.clone(), admin_id, permission_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Gives the admin role to an existing user, used to bootstrap the first admin.
+/// The configured email is trusted, so it is also marked as verified.
+pub async fn assign_admin_by_email(mm: ModelManager, email: &str) -> Result<bool> {
+    let db = mm.db.clone();
+    let user = sqlx::query_as::<_, (i64,)>("SELECT id FROM \"user\" WHERE email = $1")
+        .bind(email)
+        .fetch_optional(&db)
+        .await?;
+    let Some((user_id,)) = user else {
+        return Ok(false);
+    };
+
+    sqlx::query("UPDATE \"user\" SET verified_at = COALESCE(verified_at, $1) WHERE id = $2")
+        .bind(now_utc_sec())
+        .bind(user_id)
+        .execute(&db)
+        .await?;
+
+    let admin_id = create_role(mm.clone(), ROLE_ADMIN).await?;
+    assign_role(mm, user_id, admin_id).await?;
+
+    Ok(true)
+}
+
+// region:        --- Roles
+
+/// Returns the role id, creating the role if it does not exist.
+pub async fn create_role(mm: ModelManager, name: &str) -> Result<i64> {
+    let db = mm.db;
+    sqlx::query("INSERT INTO role (name) VALUES ($1) ON CONFLICT DO NOTHING")
+        .bind(name)
+        .execute(&db)
+        .await?;
+
+    let (id,) = sqlx::query_as::<_, (i64,)>("SELECT id FROM role WHERE name = $1")
+        .bind(name)
+        .fetch_one(&db)
+        .await?;
+
+    Ok(id)
+}
+
+pub async fn get_role_by_name(mm: ModelManager, name: &str) -> Result<Option<Role>> {
+    let db = mm.db;
+    let role = sqlx::query_as::<_, Role>("SELECT id, name FROM role WHERE name = $1")
+        .bind(name)
+        .fetch_optional(&db)
+        .await?;
+
+    Ok(role)
+}
+
+pub async fn list_roles(mm: ModelManager) -> Result<Vec<Role>> {
+    let db = mm.db;
+    let roles = sqlx::query_as::<_, Role>("SELECT id, name FROM role")
+        .fetch_all(&db)
+        .await?;
+
+    Ok(roles)
+}
+
+pub async fn assign_role(mm: ModelManager, user_id: i64, role_id: i64) -> Result<()> {
+    let db = mm.db;
+    sqlx::query("INSERT INTO user_role (user_id, role_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+        .bind(user_id)
+        .bind(role_id)
+        .execute(&db)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn has_role(mm: ModelManager, user_id: i64, role: &str) -> Result<bool> {
+    let db = mm.db;
+    let (count,) = sqlx::query_as::<_, (i64,)>(
+        "SELECT COUNT(*) FROM user_role ur JOIN role r ON r.id = ur.role_id
+        WHERE ur.user_id = $1 AND r.name = $2",
+    )
+    .bind(user_id)
+    .bind(role)
+    .fetch_one(&db)
+    .await?;
+
+    Ok(count > 0)
+}
+
+pub async fn unassign_role(mm: ModelManager, user_id: i64, role_id: i64) -> Result<()> {
+    let db = mm.db;
+    sqlx::query("DELETE FROM user_role WHERE user_id = $1 AND role_id = $2")
+        .bind(user_id)
+        .bind(role_id)
+        .execute(&db)
+        .await?;
+
+    Ok(())
+}
+
+// endregion:     --- Roles
+
+// region:        --- Permissions
+
+/// Returns the permission id, creating the permission if it does not exist.
+pub async fn create_permission(mm: ModelManager, name: &str) -> Result<i64> {
+    let db = mm.db;
+    sqlx::query("INSERT INTO permission (name) VALUES ($1) ON CONFLICT DO NOTHING")
+        .bind(name)
+        .execute(&db)
+        .await?;
+
+    let (id,) = sqlx::query_as::<_, (i64,)>("SELECT id FROM permission WHERE name = $1")
+        .bind(name)
+        .fetch_one(&db)
+        .await?;
+
+    Ok(id)
+}
+
+pub async fn grant_permission(mm: ModelManager, role_id: i64, permission_id: i64) -> Result<()> {
+    let db = mm.db;
+    sqlx::query(
+        "INSERT INTO role_permission (role_id, permission_id) VALUES ($1, $2)
+        ON CONFLICT DO NOTHING",
+    )
+    .bind(role_id)
+    .bind(permission_id)
+    .execute(&db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn list_user_permissions(mm: ModelManager, user_id: i64) -> Result<Vec<String>> {
+    let db = mm.db;
+    let permissions = sqlx::query_as::<_, (String,)>(
+        "SELECT DISTINCT p.name FROM permission p
+        JOIN role_permission rp ON rp.permission_id = p.id
+        JOIN user_role ur ON ur.role_id = rp.role_id
+        WHERE ur.user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_all(&db)
+    .await?;
+
+    Ok(permissions.into_iter().map(|(name,)| name).collect())
+}
+
+/// Unverified users hold no permission, whatever their roles, and the admin role
+/// grants none until the user enabled TOTP. API keys are also limited to their
+/// scopes.
+pub async fn has_permission(mm: ModelManager, ctx: &Ctx, permission: &str) -> Result<bool> {
+    if !ctx.in_scope(permission) {
+        return Ok(false);
+    }
+
+    let db = mm.db;
+    let (count,) = sqlx::query_as::<_, (i64,)>(
+        "SELECT COUNT(*) FROM permission p
+        JOIN role_permission rp ON rp.permission_id = p.id
+        JOIN role r ON r.id = rp.role_id
+        JOIN user_role ur ON ur.role_id = rp.role_id
+        JOIN \"user\" u ON u.id = ur.user_id AND u.verified_at IS NOT NULL AND u.is_active
+        LEFT JOIN user_totp t ON t.user_id = u.id
+        WHERE ur.user_id = $1 AND p.name = $2 AND (r.name <> $3 OR t.enabled_at IS NOT NULL)",
+    )
+    .bind(ctx.user_id())
+    .bind(permission)
+    .bind(ROLE_ADMIN)
+    .fetch_one(&db)
+    .await?;
+
+    Ok(count > 0)
+}
+
+/// Same as `has_permission` but fails with `Error::PermissionDenied`.
+pub async fn check_permission(mm: ModelManager, ctx: &Ctx, permission: &str) -> Result<()> {
+    if has_permission(mm, ctx, permission).await? {
+        Ok(())
+    } else {
+        Err(Error::PermissionDenied {
+            permission: permission.to_string(),
+        })
+    }
+}
+
+// endregion:     --- Permissions
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check_permission() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+        let ctx = Ctx::new(user_id, "session");
+
+        // no role, no permission
+        let res = check_permission(mm.clone(), &ctx, perms::USER_READ).await;
+        assert!(matches!(res, Err(super::Error::PermissionDenied { .. })));
+
+        // admin has every permission, once TOTP is enabled
+        let admin = get_role_by_name(mm.clone(), ROLE_ADMIN)
+            .await?
+            .ok_or("admin role not seeded")?;
+        assign_role(mm.clone(), user_id, admin.id).await?;
+        assert!(!has_permission(mm.clone(), &ctx, perms::USER_READ).await?);
+        mm.seed_totp(user_id).await?;
+        check_permission(mm.clone(), &ctx, perms::USER_READ).await?;
+        assert_eq!(
+            list_user_permissions(mm.clone(), user_id).await?.len(),
+            perms::ALL.len()
+        );
+
+        // unknown permission
+        assert!(!has_permission(mm.clone(), &ctx, "unknown").await?);
+
+        // back to no role
+        unassign_role(mm.clone(), user_id, admin.id).await?;
+        assert!(!has_permission(mm, &ctx, perms::USER_READ).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_role_idempotent() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+
+        let id = create_role(mm.clone(), "support").await?;
+        assert_eq!(create_role(mm.clone(), "support").await?, id);
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
@@ -0,0 +1,276 @@
+//! "Remember me" refresh tokens
+//!
+//! A refresh token reopens a session once the short session cookie is gone.
+//! Only its SHA-256 is stored, with a label of the device it was issued to.
+//! Each use rotates it: the token is marked as used and a new one of the same
+//! family is issued. A used token presented again means it was copied, so the
+//! whole family is revoked and the device has to sign in again. Unless it comes
+//! within a few seconds, as the concurrent requests of a page sending the same
+//! token: each of them gets its own next token.
+
+use super::{Error, ModelManager, Result};
+use crate::config;
+use crate::token::{generate_secret, hash_secret};
+use lib_utils::time::{now_utc_plus_sec, now_utc_sec};
+use sqlx::FromRow;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Longer labels (user agents) are cut.
+const DEVICE_MAX_LEN: usize = 128;
+/// A token used again within this delay is a concurrent request, not a copy.
+const REUSE_GRACE_SEC: i64 = 10;
+
+// region:        --- Types
+
+/// New token of the family, to send back to the device.
+#[derive(Debug)]
+pub struct RefreshTokenRotated {
+    pub user_id: i64,
+    pub token: String,
+}
+
+#[derive(FromRow)]
+struct RefreshTokenRecord {
+    id: i64,
+    family_id: String,
+    user_id: i64,
+    device: String,
+    expires_at: i64,
+    used_at: Option<i64>,
+    revoked_at: Option<i64>,
+}
+
+// endregion:     --- Types
+
+/// Starts a new family for the device, returns the token.
+pub async fn create_refresh_token(mm: ModelManager, user_id: i64, device: &str) -> Result<String> {
+    let device: String = device.chars().take(DEVICE_MAX_LEN).collect();
+    insert_refresh_token(mm, &Uuid::new_v4().to_string(), user_id, &device).await
+}
+
+async fn insert_refresh_token(
+    mm: ModelManager,
+    family_id: &str,
+    user_id: i64,
+    device: &str,
+) -> Result<String> {
+    let token = generate_secret();
+
+    let db = mm.db;
+    sqlx::query(
+        "INSERT INTO refresh_token (family_id, user_id, token_hash, device, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(family_id)
+    .bind(user_id)
+    .bind(hash_secret(&token))
+    .bind(device)
+    .bind(now_utc_sec())
+    .bind(now_utc_plus_sec(config().REFRESH_TOKEN_DURATION_SEC))
+    .execute(&db)
+    .await?;
+
+    Ok(token)
+}
+
+/// Exchanges the token for the next one of its family, `None` if unknown,
+/// expired, revoked or of an inactive user. Fails with `RefreshTokenReused`,
+/// after revoking the family, if the token was exchanged before the grace delay.
+pub async fn rotate_refresh_token(
+    mm: ModelManager,
+    token: &str,
+) -> Result<Option<RefreshTokenRotated>> {
+    let db = mm.db.clone();
+    let now = now_utc_sec();
+
+    let record = sqlx::query_as::<_, RefreshTokenRecord>(
+        "SELECT id, family_id, user_id, device, expires_at, used_at, revoked_at
+        FROM refresh_token WHERE token_hash = $1",
+    )
+    .bind(hash_secret(token))
+    .fetch_optional(&db)
+    .await?;
+    let Some(record) = record.filter(|r| r.revoked_at.is_none() && r.expires_at > now) else {
+        return Ok(None);
+    };
+
+    // the update also catches a concurrent use of the same token
+    let marked =
+        sqlx::query("UPDATE refresh_token SET used_at = $1 WHERE id = $2 AND used_at IS NULL")
+            .bind(now)
+            .bind(record.id)
+            .execute(&db)
+            .await?
+            .rows_affected();
+    // not seen used by the select, it was just now by a concurrent request
+    let used_at = record.used_at.unwrap_or(now);
+    if marked == 0 && now - used_at > REUSE_GRACE_SEC {
+        revoke_family(mm, &record.family_id).await?;
+        warn!(
+            "{:<12} - reused token of user {}, family {} revoked",
+            "REFRESH", record.user_id, record.family_id
+        );
+        return Err(Error::RefreshTokenReused);
+    }
+
+    let (is_active,) = sqlx::query_as::<_, (bool,)>("SELECT is_active FROM \"user\" WHERE id = $1")
+        .bind(record.user_id)
+        .fetch_one(&db)
+        .await?;
+    if !is_active {
+        return Ok(None);
+    }
+
+    let token = insert_refresh_token(mm, &record.family_id, record.user_id, &record.device).await?;
+
+    Ok(Some(RefreshTokenRotated {
+        user_id: record.user_id,
+        token,
+    }))
+}
+
+/// Revokes the family of the token, on sign out of the device.
+pub async fn revoke_refresh_token(mm: ModelManager, token: &str) -> Result<()> {
+    let db = mm.db.clone();
+    let family =
+        sqlx::query_as::<_, (String,)>("SELECT family_id FROM refresh_token WHERE token_hash = $1")
+            .bind(hash_secret(token))
+            .fetch_optional(&db)
+            .await?;
+
+    match family {
+        Some((family_id,)) => revoke_family(mm, &family_id).await,
+        None => Ok(()),
+    }
+}
+
+async fn revoke_family(mm: ModelManager, family_id: &str) -> Result<()> {
+    let db = mm.db;
+    sqlx::query(
+        "UPDATE refresh_token SET revoked_at = COALESCE(revoked_at, $1) WHERE family_id = $2",
+    )
+    .bind(now_utc_sec())
+    .bind(family_id)
+    .execute(&db)
+    .await?;
+
+    Ok(())
+}
+
+/// Every device of the user has to sign in again.
+pub async fn delete_user_refresh_tokens(mm: ModelManager, user_id: i64) -> Result<u64> {
+    let db = mm.db;
+    let res = sqlx::query("DELETE FROM refresh_token WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&db)
+        .await?;
+
+    Ok(res.rows_affected())
+}
+
+/// Returns the number of tokens deleted, used ones are kept until they expire
+/// to detect their reuse.
+pub async fn delete_expired_refresh_tokens(mm: ModelManager) -> Result<u64> {
+    let db = mm.db;
+    let res = sqlx::query("DELETE FROM refresh_token WHERE expires_at <= $1")
+        .bind(now_utc_sec())
+        .execute(&db)
+        .await?;
+
+    Ok(res.rows_affected())
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_refresh_token_rotation() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+
+        let first = create_refresh_token(mm.clone(), user_id, "Firefox on Linux").await?;
+        let rotated = rotate_refresh_token(mm.clone(), &first)
+            .await?
+            .ok_or("not rotated")?;
+        assert_eq!(rotated.user_id, user_id);
+        assert_ne!(rotated.token, first);
+
+        let second = rotate_refresh_token(mm.clone(), &rotated.token)
+            .await?
+            .ok_or("not rotated")?;
+        assert!(rotate_refresh_token(mm, "unknown").await?.is_none());
+        assert_ne!(second.token, rotated.token);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_reuse_revokes_family() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+        let first = create_refresh_token(mm.clone(), user_id, "laptop").await?;
+        let other = create_refresh_token(mm.clone(), user_id, "phone").await?;
+
+        let rotated = rotate_refresh_token(mm.clone(), &first)
+            .await?
+            .ok_or("not rotated")?;
+
+        // the copied token is replayed, after the grace delay
+        sqlx::query("UPDATE refresh_token SET used_at = used_at - $1")
+            .bind(REUSE_GRACE_SEC + 1)
+            .execute(&mm.db)
+            .await?;
+        let res = rotate_refresh_token(mm.clone(), &first).await;
+        assert!(matches!(res, Err(super::Error::RefreshTokenReused)));
+        assert!(rotate_refresh_token(mm.clone(), &rotated.token)
+            .await?
+            .is_none());
+
+        // other devices are other families
+        assert!(rotate_refresh_token(mm, &other).await?.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_concurrent_rotation() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+        let first = create_refresh_token(mm.clone(), user_id, "laptop").await?;
+
+        // two requests of the same page, each gets a token of the family
+        let (res_a, res_b) = tokio::join!(
+            rotate_refresh_token(mm.clone(), &first),
+            rotate_refresh_token(mm.clone(), &first)
+        );
+        let token_a = res_a?.ok_or("not rotated")?.token;
+        let token_b = res_b?.ok_or("not rotated")?.token;
+        assert_ne!(token_a, token_b);
+
+        assert!(rotate_refresh_token(mm.clone(), &token_a).await?.is_some());
+        assert!(rotate_refresh_token(mm, &token_b).await?.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_revoke() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+
+        let token = create_refresh_token(mm.clone(), user_id, "laptop").await?;
+        revoke_refresh_token(mm.clone(), &token).await?;
+        assert!(rotate_refresh_token(mm.clone(), &token).await?.is_none());
+
+        let token = create_refresh_token(mm.clone(), user_id, "laptop").await?;
+        assert_eq!(delete_user_refresh_tokens(mm.clone(), user_id).await?, 2);
+        assert!(rotate_refresh_token(mm, &token).await?.is_none());
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
@@ -0,0 +1,252 @@
+use std::time::Duration;
+
+use super::{
+    refresh_token::{delete_expired_refresh_tokens, delete_user_refresh_tokens},
+    ModelManager, Result,
+};
+use crate::config;
+use lib_utils::time::{now_utc_plus_sec, now_utc_sec};
+use serde::Serialize;
+use sqlx::FromRow;
+use tracing::{debug, error};
+use uuid::Uuid;
+
+// region:        --- Types
+
+#[derive(FromRow, Serialize, Debug, Clone)]
+pub struct Session {
+    pub id: String,
+    pub user_id: i64,
+    pub created_at: i64,
+    pub expires_at: i64,
+    /// User seen through this session, set by `start_impersonation`.
+    pub impersonated_user_id: Option<i64>,
+}
+
+/// Sessions are only valid while their `token_version` is the one of the user,
+/// bumped by `revoke_user_sessions` to sign out everywhere.
+const SESSION_VALID: &str = "expires_at > $2
+    AND token_version = (SELECT token_version FROM \"user\" WHERE \"user\".id = session.user_id)";
+
+// endregion:     --- Types
+
+pub async fn create_session(mm: ModelManager, user_id: i64) -> Result<Session> {
+    let session = Session {
+        id: Uuid::new_v4().to_string(),
+        user_id,
+        created_at: now_utc_sec(),
+        expires_at: now_utc_plus_sec(config().SESSION_DURATION_SEC),
+        impersonated_user_id: None,
+    };
+
+    let db = mm.db;
+    sqlx::query(
+        "INSERT INTO session (id, user_id, created_at, expires_at, token_version)
+        SELECT $1, $2, $3, $4, token_version FROM \"user\" WHERE id = $2",
+    )
+    .bind(&session.id)
+    .bind(session.user_id)
+    .bind(session.created_at)
+    .bind(session.expires_at)
+    .execute(&db)
+    .await?;
+
+    Ok(session)
+}
+
+/// Returns the session only if it is not expired.
+pub async fn get_session(mm: ModelManager, id: &str) -> Result<Option<Session>> {
+    let db = mm.db;
+    let session = sqlx::query_as::<_, Session>(&format!(
+        "SELECT id, user_id, created_at, expires_at, impersonated_user_id FROM session
+        WHERE id = $1 AND {SESSION_VALID}"
+    ))
+    .bind(id)
+    .bind(now_utc_sec())
+    .fetch_optional(&db)
+    .await?;
+
+    Ok(session)
+}
+
+/// Extends a valid session for another `SESSION_DURATION_SEC`.
+pub async fn touch_session(mm: ModelManager, id: &str) -> Result<Option<Session>> {
+    let db = mm.db;
+    let session = sqlx::query_as::<_, Session>(&format!(
+        "UPDATE session SET expires_at = $3 WHERE id = $1 AND {SESSION_VALID}
+        RETURNING id, user_id, created_at, expires_at, impersonated_user_id"
+    ))
+    .bind(id)
+    .bind(now_utc_sec())
+    .bind(now_utc_plus_sec(config().SESSION_DURATION_SEC))
+    .fetch_optional(&db)
+    .await?;
+
+    Ok(session)
+}
+
+/// Marks the session as expired, it is removed by the next cleanup.
+pub async fn expire_session(mm: ModelManager, id: &str) -> Result<()> {
+    let db = mm.db;
+    sqlx::query("UPDATE session SET expires_at = $1 WHERE id = $2")
+        .bind(now_utc_sec())
+        .bind(id)
+        .execute(&db)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn delete_session(mm: ModelManager, id: &str) -> Result<()> {
+    let db = mm.db;
+    sqlx::query("DELETE FROM session WHERE id = $1")
+        .bind(id)
+        .execute(&db)
+        .await?;
+
+    Ok(())
+}
+
+/// Closes every session of the user, returns the number of sessions deleted.
+pub async fn delete_user_sessions(mm: ModelManager, user_id: i64) -> Result<u64> {
+    let db = mm.db;
+    let res = sqlx::query("DELETE FROM session WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&db)
+        .await?;
+
+    Ok(res.rows_affected())
+}
+
+/// Signs the user out everywhere: the sessions and "remember me" tokens are
+/// deleted and the token version bumped, so a session created concurrently is
+/// rejected too. Returns the number of sessions deleted.
+pub async fn revoke_user_sessions(mm: ModelManager, user_id: i64) -> Result<u64> {
+    let db = mm.db.clone();
+    sqlx::query("UPDATE \"user\" SET token_version = token_version + 1 WHERE id = $1")
+        .bind(user_id)
+        .execute(&db)
+        .await?;
+    delete_user_refresh_tokens(mm.clone(), user_id).await?;
+
+    delete_user_sessions(mm, user_id).await
+}
+
+/// Returns the number of sessions deleted.
+pub async fn delete_expired_sessions(mm: ModelManager) -> Result<u64> {
+    let db = mm.db;
+    let res = sqlx::query("DELETE FROM session WHERE expires_at <= $1")
+        .bind(now_utc_sec())
+        .execute(&db)
+        .await?;
+
+    Ok(res.rows_affected())
+}
+
+/// Deletes expired sessions and refresh tokens every `SESSION_CLEANUP_INTERVAL_SEC`.
+pub fn spawn_sessions_cleanup(mm: ModelManager) {
+    let period = Duration::from_secs(config().SESSION_CLEANUP_INTERVAL_SEC);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            match delete_expired_sessions(mm.clone()).await {
+                Ok(0) => (),
+                Ok(count) => debug!("{:<12} - {count} expired sessions deleted", "SESSION"),
+                Err(ex) => error!("{:<12} - cleanup failed: {ex}", "SESSION"),
+            }
+            match delete_expired_refresh_tokens(mm.clone()).await {
+                Ok(0) => (),
+                Ok(count) => debug!("{:<12} - {count} expired tokens deleted", "REFRESH"),
+                Err(ex) => error!("{:<12} - cleanup failed: {ex}", "REFRESH"),
+            }
+        }
+    });
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_session_lifecycle() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+
+        // create & lookup
+        let session = create_session(mm.clone(), user_id).await?;
+        let found = get_session(mm.clone(), &session.id).await?;
+        assert_eq!(found.map(|s| s.user_id), Some(user_id));
+
+        // touch
+        let touched = touch_session(mm.clone(), &session.id).await?;
+        assert!(touched.is_some_and(|s| s.expires_at >= session.expires_at));
+
+        // expire
+        expire_session(mm.clone(), &session.id).await?;
+        assert!(get_session(mm.clone(), &session.id).await?.is_none());
+        assert!(touch_session(mm.clone(), &session.id).await?.is_none());
+
+        // cleanup
+        assert_eq!(delete_expired_sessions(mm.clone()).await?, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_session_delete() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+
+        let session = create_session(mm.clone(), user_id).await?;
+        delete_session(mm.clone(), &session.id).await?;
+
+        assert!(get_session(mm.clone(), &session.id).await?.is_none());
+        assert_eq!(delete_expired_sessions(mm).await?, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_session_revoke_user_sessions() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+        let other_id = mm.seed_user("other@mail.com").await?;
+        let laptop = create_session(mm.clone(), user_id).await?;
+        let phone = create_session(mm.clone(), user_id).await?;
+        let other = create_session(mm.clone(), other_id).await?;
+
+        assert_eq!(revoke_user_sessions(mm.clone(), user_id).await?, 2);
+        assert!(touch_session(mm.clone(), &laptop.id).await?.is_none());
+        assert!(touch_session(mm.clone(), &phone.id).await?.is_none());
+        assert!(touch_session(mm.clone(), &other.id).await?.is_some());
+
+        // sessions opened after the revocation are valid
+        let session = create_session(mm.clone(), user_id).await?;
+        assert!(get_session(mm, &session.id).await?.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_session_stale_token_version() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+        let session = create_session(mm.clone(), user_id).await?;
+
+        // version bumped without the delete, e.g. a session created during the revocation
+        sqlx::query("UPDATE \"user\" SET token_version = token_version + 1 WHERE id = $1")
+            .bind(user_id)
+            .execute(&mm.db)
+            .await?;
+
+        assert!(get_session(mm.clone(), &session.id).await?.is_none());
+        assert!(touch_session(mm, &session.id).await?.is_none());
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
@@ -1,17 +1,54 @@
-use std::{thread, time::Duration};
-
-use super::{ModelManager, Result};
-use serde::{Deserialize, Serialize};
+use super::base::{self, DbBmc};
+use super::list::{ListFilter, ListOptions};
+use super::{
+    email_verify::send_verification, session::revoke_user_sessions, Error, ModelManager, Result,
+};
+use crate::ctx::Ctx;
+use crate::notify::Notifier;
+use crate::pwd::hash_pwd;
+use lib_utils::pwd_policy::check_pwd;
+use lib_utils::time::now_utc_sec;
+use serde::{Deserialize, Serialize, Serializer};
 use sqlx::FromRow;
 use tracing::debug;
 
+pub struct UserBmc;
+
+impl DbBmc for UserBmc {
+    const TABLE: &'static str = "user";
+    const TIMESTAMPED: bool = true;
+    const LIST_COLUMNS: &'static [&'static str] = &[
+        "id",
+        "email",
+        "display_name",
+        "is_active",
+        "created_at",
+        "updated_at",
+    ];
+}
+
 // region:        --- Types
 
 #[derive(FromRow, Serialize, Debug)]
 pub struct User {
     pub id: i64,
     pub email: String,
-    pub pwd: String,
+    pub display_name: Option<String>,
+    /// An inactive user cannot sign in, its sessions are closed on deactivation.
+    pub is_active: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// User with its password hash, never serialized.
+#[derive(FromRow, Debug)]
+pub struct UserForLogin {
+    pub id: i64,
+    pub email: String,
+    pub pwd: Option<String>,
+    pub verified_at: Option<i64>,
+    pub totp_enabled: bool,
+    pub is_active: bool,
 }
 
 #[derive(Deserialize)]
@@ -20,45 +57,341 @@ pub struct UserForCreate {
     pub pwd: String,
 }
 
+/// Row inserted, with the password hashed.
+#[derive(Serialize)]
+struct UserForInsert {
+    email: String,
+    pwd: String,
+}
+
+/// Fields to change, `None` keeps the current value.
+#[derive(Deserialize, Serialize, Default, Debug)]
+pub struct UserForUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// An empty string clears the display name.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "empty_as_null"
+    )]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_active: Option<bool>,
+}
+
+fn empty_as_null<S: Serializer>(
+    value: &Option<String>,
+    serializer: S,
+) -> core::result::Result<S::Ok, S::Error> {
+    match value.as_deref() {
+        None | Some("") => serializer.serialize_none(),
+        Some(value) => serializer.serialize_str(value),
+    }
+}
+
 // endregion:     --- Types
 
-pub async fn create_user_table(mm: ModelManager) -> Result<()> {
+/// Fails with `PwdPolicy` when a new password breaks the policy. Not applied to
+/// the passwords already set, they are checked when changed.
+pub(super) fn check_pwd_policy(pwd: &str, email: &str) -> Result<()> {
+    let issues = check_pwd(pwd, email);
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::PwdPolicy { issues })
+    }
+}
+
+pub async fn create_user(mm: ModelManager, email: &str, pwd: &str) -> Result<i64> {
+    check_pwd_policy(pwd, email)?;
+
+    let user_i = UserForInsert {
+        email: email.to_string(),
+        pwd: hash_pwd(pwd.to_string()).await?,
+    };
+
+    base::create::<UserBmc, _>(mm, user_i)
+        .await
+        .map_err(email_conflict)
+}
+
+pub async fn get_user(mm: ModelManager, id: i64) -> Result<User> {
+    base::get::<UserBmc, _>(mm, id).await
+}
+
+const SELECT_USER: &str =
+    "SELECT id, email, display_name, is_active, created_at, updated_at FROM \"user\"";
+
+pub async fn first_by_email(mm: ModelManager, email: &str) -> Result<Option<User>> {
     let db = mm.db;
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS user (
-    id INTEGER PRIMARY KEY AUTOINCREMENT,
-    email varchar(128) NOT NULL UNIQUE,
-    pwd varchar(256)
-    )",
-    )
-    .execute(&db)
-    .await?;
-
-    debug!("{:<12} - User table initiated", "DATABASE");
+    let user = sqlx::query_as::<_, User>(&format!("{SELECT_USER} WHERE email = $1"))
+        .bind(email)
+        .fetch_optional(&db)
+        .await?;
+
+    Ok(user)
+}
+
+pub async fn list_users(
+    mm: ModelManager,
+    filter: Option<ListFilter>,
+    list_options: Option<ListOptions>,
+) -> Result<Vec<User>> {
+    base::list::<UserBmc, _>(mm, filter, list_options).await
+}
+
+/// A new email is unverified, the verification link is sent to it.
+pub async fn update_user(
+    mm: ModelManager,
+    notifier: &dyn Notifier,
+    id: i64,
+    user_u: UserForUpdate,
+) -> Result<()> {
+    let deactivated = user_u.is_active == Some(false);
+    let new_email = match &user_u.email {
+        Some(email) if *email != get_user(mm.clone(), id).await?.email => Some(email.clone()),
+        _ => None,
+    };
+    base::update::<UserBmc, _>(mm.clone(), id, user_u)
+        .await
+        .map_err(email_conflict)?;
+
+    if let Some(email) = new_email {
+        sqlx::query("UPDATE \"user\" SET verified_at = NULL WHERE id = $1")
+            .bind(id)
+            .execute(&mm.db)
+            .await?;
+        send_verification(notifier, id, &email)?;
+    }
+
+    if deactivated {
+        let count = revoke_user_sessions(mm, id).await?;
+        debug!(
+            "{:<12} - user {id} deactivated, {count} sessions closed",
+            "USER"
+        );
+    }
 
     Ok(())
 }
 
-pub async fn create_user(mm: ModelManager, email: &str, pwd: &str) -> Result<i64> {
-    // wait 3s to simulate an error
-    thread::sleep(Duration::from_millis(3000));
+/// Deletes the user, its sessions, roles, keys and links go with it.
+pub async fn delete_user(mm: ModelManager, id: i64) -> Result<()> {
+    base::delete::<UserBmc>(mm, id).await
+}
 
+/// Neither deleted nor deactivated by its own request, the real user would
+/// lose the access it acts with, e.g. the last admin.
+pub fn check_not_self(ctx: &Ctx, id: i64) -> Result<()> {
+    match ctx.real_user_id() == id {
+        true => Err(Error::CannotRemoveSelf),
+        false => Ok(()),
+    }
+}
+
+/// `EmailAlreadyExists` when the email of the insert or update is taken.
+pub(super) fn email_conflict(ex: impl Into<Error>) -> Error {
+    match ex.into() {
+        Error::UniqueViolation { table, constraint }
+            if table == "user" && constraint.contains("email") =>
+        {
+            Error::EmailAlreadyExists
+        }
+        other => other,
+    }
+}
+
+const SELECT_USER_FOR_LOGIN: &str = "SELECT u.id, u.email, u.pwd, u.verified_at,
+    t.enabled_at IS NOT NULL AS totp_enabled, u.is_active
+    FROM \"user\" u LEFT JOIN user_totp t ON t.user_id = u.id";
+
+pub async fn get_user_for_login(mm: ModelManager, email: &str) -> Result<Option<UserForLogin>> {
     let db = mm.db;
-    let res = sqlx::query("INSERT INTO user (email, pwd) VALUES (?1, ?2)")
-        .bind(email)
+    let user =
+        sqlx::query_as::<_, UserForLogin>(&format!("{SELECT_USER_FOR_LOGIN} WHERE u.email = $1"))
+            .bind(email)
+            .fetch_optional(&db)
+            .await?;
+
+    Ok(user)
+}
+
+/// Same as `get_user_for_login`, for the login steps after the password one.
+pub async fn get_user_for_login_by_id(mm: ModelManager, id: i64) -> Result<Option<UserForLogin>> {
+    let db = mm.db;
+    let user =
+        sqlx::query_as::<_, UserForLogin>(&format!("{SELECT_USER_FOR_LOGIN} WHERE u.id = $1"))
+            .bind(id)
+            .fetch_optional(&db)
+            .await?;
+
+    Ok(user)
+}
+
+pub async fn update_pwd(mm: ModelManager, id: i64, pwd: &str) -> Result<()> {
+    let pwd = hash_pwd(pwd.to_string()).await?;
+
+    let db = mm.db;
+    sqlx::query("UPDATE \"user\" SET pwd = $1, updated_at = $2 WHERE id = $3")
         .bind(pwd)
+        .bind(now_utc_sec())
+        .bind(id)
         .execute(&db)
         .await?;
 
-    Ok(res.last_insert_rowid())
+    Ok(())
 }
 
-pub async fn list_users(mm: ModelManager) -> Result<Vec<User>> {
-    let db = mm.db;
-    debug!("HELLO");
-    let users = sqlx::query_as::<_, User>("SELECT id, email, pwd FROM user")
-        .fetch_all(&db)
-        .await?;
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
 
-    Ok(users)
+    use super::*;
+    use crate::model::session::{create_session, get_session};
+    use crate::notify::{LogNotifier, MemoryNotifier};
+
+    #[tokio::test]
+    async fn test_user_get_and_first_by_email_ok() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let id = mm.seed_user("demo@mail.com").await?;
+
+        let user = get_user(mm.clone(), id).await?;
+        assert_eq!(user.email, "demo@mail.com");
+        assert!(user.is_active);
+        assert!(user.created_at > 0);
+
+        let user = first_by_email(mm.clone(), "demo@mail.com").await?;
+        assert_eq!(user.map(|user| user.id), Some(id));
+        assert!(first_by_email(mm, "other@mail.com").await?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_user_list_filtered() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        for email in [
+            "ann@acme.com",
+            "bob@acme.com",
+            "cy@other.com",
+            "dee@acme.com",
+        ] {
+            mm.seed_user(email).await?;
+        }
+        let user_u = UserForUpdate {
+            is_active: Some(false),
+            ..Default::default()
+        };
+        update_user(mm.clone(), &LogNotifier, 2, user_u).await?;
+
+        let filter =
+            ListFilter::from_json(r#"{"email": {"$endsWith": "@acme.com"}, "is_active": true}"#)?;
+        let list_options = ListOptions {
+            limit: Some(1),
+            offset: Some(1),
+            order_bys: Some("!email".to_string()),
+        };
+        let users = list_users(mm.clone(), Some(filter), Some(list_options)).await?;
+        let emails: Vec<&str> = users.iter().map(|u| u.email.as_str()).collect();
+        assert_eq!(emails, ["ann@acme.com"]);
+
+        let filter = ListFilter::from_json(r#"{"pwd": {"$null": false}}"#)?;
+        let res = list_users(mm, Some(filter), None).await;
+        assert!(matches!(res, Err(super::Error::ListQueryInvalid { .. })));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_user_update_ok() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let id = mm.seed_user("demo@mail.com").await?;
+        let session = create_session(mm.clone(), id).await?;
+
+        let user_u = UserForUpdate {
+            display_name: Some("Demo".to_string()),
+            is_active: Some(false),
+            ..Default::default()
+        };
+        update_user(mm.clone(), &LogNotifier, id, user_u).await?;
+
+        let user = get_user(mm.clone(), id).await?;
+        assert_eq!(user.email, "demo@mail.com");
+        assert_eq!(user.display_name.as_deref(), Some("Demo"));
+        assert!(!user.is_active);
+        assert!(get_session(mm.clone(), &session.id).await?.is_none());
+
+        // empty string clears
+        let user_u = UserForUpdate {
+            display_name: Some(String::new()),
+            ..Default::default()
+        };
+        update_user(mm.clone(), &LogNotifier, id, user_u).await?;
+        assert!(get_user(mm, id).await?.display_name.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_user_update_email_unverified() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let id = mm.seed_user("demo@mail.com").await?;
+        let notifier = MemoryNotifier::default();
+
+        let user_u = UserForUpdate {
+            email: Some("new@mail.com".to_string()),
+            ..Default::default()
+        };
+        update_user(mm.clone(), &notifier, id, user_u).await?;
+
+        let user = get_user_for_login_by_id(mm, id)
+            .await?
+            .ok_or("user not found")?;
+        assert_eq!(user.email, "new@mail.com");
+        assert!(user.verified_at.is_none());
+        let body = notifier.last_body().ok_or("no message sent")?;
+        assert!(body.contains("/verify-email?token="));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_not_self() {
+        assert!(matches!(
+            check_not_self(&Ctx::new(1, "session"), 1),
+            Err(super::Error::CannotRemoveSelf)
+        ));
+        // seen as the other user, still the real one removed
+        let ctx = Ctx::new_impersonating(1, 2, "session");
+        assert!(check_not_self(&ctx, 1).is_err());
+        assert!(check_not_self(&ctx, 2).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_user_update_delete_err() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let id = mm.seed_user("demo@mail.com").await?;
+        mm.seed_user("other@mail.com").await?;
+
+        let user_u = UserForUpdate {
+            email: Some("other@mail.com".to_string()),
+            ..Default::default()
+        };
+        let res = update_user(mm.clone(), &LogNotifier, id, user_u).await;
+        assert!(matches!(res, Err(super::Error::EmailAlreadyExists)));
+
+        delete_user(mm.clone(), id).await?;
+        let res = get_user(mm.clone(), id).await;
+        assert!(matches!(
+            res,
+            Err(super::Error::EntityNotFound { entity: "user", .. })
+        ));
+        let res = delete_user(mm, id).await;
+        assert!(matches!(
+            res,
+            Err(super::Error::EntityNotFound { entity: "user", .. })
+        ));
+        Ok(())
+    }
 }
+
+// endregion: --- Tests
@@ -0,0 +1,102 @@
+//! Users of the LDAP directory
+//!
+//! A directory entry is linked to a `user` by its DN on the first bind, to the
+//! user with the same email if that one has no password and no role (e.g.
+//! created for an invitation), otherwise to a new user without password when
+//! provisioning is allowed. Each successful bind copies the email and display
+//! name of the entry, never onto a user with a local password.
+
+use super::{user::email_conflict, ModelManager, Result};
+use crate::ldap::LdapUser;
+use lib_utils::time::now_utc_sec;
+use tracing::{debug, warn};
+
+/// Returns the user of the entry, after copying its email and display name.
+/// `None` for an entry of no user when `provision` is false.
+pub async fn sync_ldap_user(
+    mm: ModelManager,
+    entry: &LdapUser,
+    provision: bool,
+) -> Result<Option<i64>> {
+    let db = mm.db;
+    let now = now_utc_sec();
+
+    let linked = sqlx::query_as::<_, (i64,)>("SELECT user_id FROM user_ldap WHERE dn = $1")
+        .bind(&entry.dn)
+        .fetch_optional(&db)
+        .await?;
+    let existing = match linked {
+        Some(linked) => Some(linked),
+        None => {
+            let local = sqlx::query_as::<_, (i64, bool)>(
+                "SELECT id, pwd IS NULL AND NOT EXISTS
+                (SELECT 1 FROM user_role WHERE user_role.user_id = \"user\".id)
+                FROM \"user\" WHERE email = $1",
+            )
+            .bind(&entry.email)
+            .fetch_optional(&db)
+            .await?;
+            match local {
+                Some((user_id, true)) => Some((user_id,)),
+                // the same mail in the directory is no proof of owning the account
+                Some((user_id, false)) => {
+                    warn!(
+                        "{:<12} - {} not linked to the local user {user_id}",
+                        "LDAP", entry.dn
+                    );
+                    return Ok(None);
+                }
+                None => None,
+            }
+        }
+    };
+
+    // the directory vouches for the email of its users only
+    let user_id = match existing {
+        Some((user_id,)) => {
+            sqlx::query(
+                "UPDATE \"user\" SET email = $1, display_name = $2,
+                verified_at = COALESCE(verified_at, $3), updated_at = $3
+                WHERE id = $4 AND pwd IS NULL",
+            )
+            .bind(&entry.email)
+            .bind(&entry.display_name)
+            .bind(now)
+            .bind(user_id)
+            .execute(&db)
+            .await
+            .map_err(email_conflict)?;
+            user_id
+        }
+        None if provision => {
+            let (user_id,) = sqlx::query_as::<_, (i64,)>(
+                "INSERT INTO \"user\" (email, display_name, verified_at, created_at, updated_at)
+                VALUES ($1, $2, $3, $3, $3) RETURNING id",
+            )
+            .bind(&entry.email)
+            .bind(&entry.display_name)
+            .bind(now)
+            .fetch_one(&db)
+            .await
+            .map_err(email_conflict)?;
+            user_id
+        }
+        None => {
+            debug!("{:<12} - {} not provisioned", "LDAP", entry.dn);
+            return Ok(None);
+        }
+    };
+
+    sqlx::query(
+        "INSERT INTO user_ldap (dn, user_id, synced_at) VALUES ($1, $2, $3)
+        ON CONFLICT(dn) DO UPDATE SET synced_at = excluded.synced_at",
+    )
+    .bind(&entry.dn)
+    .bind(user_id)
+    .bind(now)
+    .execute(&db)
+    .await?;
+    debug!("{:<12} - {} synced to user {user_id}", "LDAP", entry.dn);
+
+    Ok(Some(user_id))
+}
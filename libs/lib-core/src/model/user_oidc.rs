@@ -0,0 +1,223 @@
+//! Users signed in with an OpenID Connect provider
+//!
+//! An identity is the `(issuer, subject)` pair of the ID token. The first
+//! sign-in links it to the user with the same email, or provisions a new
+//! user without password. Both need an email verified by the provider.
+//! The provider may not check a second factor, the users with TOTP enabled
+//! still go through the TOTP step of the login form.
+
+use super::{
+    session::{create_session, Session},
+    user::get_user,
+    user_totp::is_totp_enabled,
+    Error, ModelManager, Result,
+};
+use crate::oidc::IdClaims;
+use lib_utils::time::now_utc_sec;
+use tracing::debug;
+
+/// Outcome of an SSO sign-in.
+#[derive(Debug)]
+pub enum OidcSignIn {
+    Session(Session),
+    /// The session is opened once the TOTP code is checked.
+    TotpRequired {
+        user_id: i64,
+    },
+}
+
+/// Signs in the user of the identity, see `link_or_provision_oidc_user`.
+pub async fn sign_in_oidc_user(
+    mm: ModelManager,
+    claims: &IdClaims,
+    provision: bool,
+) -> Result<OidcSignIn> {
+    let user_id = link_or_provision_oidc_user(mm.clone(), claims, provision).await?;
+    if !get_user(mm.clone(), user_id).await?.is_active {
+        return Err(Error::UserInactive { id: user_id });
+    }
+    if is_totp_enabled(mm.clone(), user_id).await? {
+        return Ok(OidcSignIn::TotpRequired { user_id });
+    }
+
+    Ok(OidcSignIn::Session(create_session(mm, user_id).await?))
+}
+
+/// Returns the user of the identity, linking or provisioning it on first sign-in.
+/// The callers only `provision` when the registration is open.
+pub async fn link_or_provision_oidc_user(
+    mm: ModelManager,
+    claims: &IdClaims,
+    provision: bool,
+) -> Result<i64> {
+    let db = mm.db;
+
+    let linked = sqlx::query_as::<_, (i64,)>(
+        "SELECT user_id FROM user_oidc WHERE issuer = $1 AND subject = $2",
+    )
+    .bind(&claims.iss)
+    .bind(&claims.sub)
+    .fetch_optional(&db)
+    .await?;
+    if let Some((user_id,)) = linked {
+        return Ok(user_id);
+    }
+
+    let email = claims.email.as_deref().ok_or(Error::OidcEmailMissing)?;
+    if !claims.email_verified {
+        return Err(Error::OidcEmailNotVerified);
+    }
+
+    let existing = sqlx::query_as::<_, (i64,)>("SELECT id FROM \"user\" WHERE email = $1")
+        .bind(email)
+        .fetch_optional(&db)
+        .await?;
+    let user_id = match existing {
+        Some((user_id,)) => {
+            // the provider vouches for the email
+            sqlx::query(
+                "UPDATE \"user\" SET verified_at = COALESCE(verified_at, $1) WHERE id = $2",
+            )
+            .bind(now_utc_sec())
+            .bind(user_id)
+            .execute(&db)
+            .await?;
+            user_id
+        }
+        None if provision => {
+            let (user_id,) = sqlx::query_as::<_, (i64,)>(
+                "INSERT INTO \"user\" (email, display_name, verified_at, created_at, updated_at)
+                VALUES ($1, $2, $3, $3, $3) RETURNING id",
+            )
+            .bind(email)
+            .bind(&claims.name)
+            .bind(now_utc_sec())
+            .fetch_one(&db)
+            .await?;
+            user_id
+        }
+        None => {
+            return Err(Error::OidcUserNotProvisioned {
+                email: email.to_string(),
+            })
+        }
+    };
+
+    sqlx::query(
+        "INSERT INTO user_oidc (issuer, subject, user_id, linked_at) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(&claims.iss)
+    .bind(&claims.sub)
+    .bind(user_id)
+    .bind(now_utc_sec())
+    .execute(&db)
+    .await?;
+    debug!(
+        "{:<12} - OIDC identity {} linked to user {user_id}",
+        "OIDC", claims.sub
+    );
+
+    Ok(user_id)
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+
+    fn claims(sub: &str, email: &str, email_verified: bool) -> IdClaims {
+        IdClaims {
+            iss: "https://sso.example.com".to_string(),
+            sub: sub.to_string(),
+            email: Some(email.to_string()),
+            email_verified,
+            name: None,
+            nonce: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oidc_link_existing_user_ok() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+
+        let claims = claims("sub-1", "demo@mail.com", true);
+        assert_eq!(
+            link_or_provision_oidc_user(mm.clone(), &claims, false).await?,
+            user_id
+        );
+        // linked by subject from now on, even if the email changes at the provider
+        let renamed = IdClaims {
+            email: Some("renamed@mail.com".to_string()),
+            ..claims
+        };
+        assert_eq!(
+            link_or_provision_oidc_user(mm, &renamed, false).await?,
+            user_id
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_oidc_provision_ok() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+
+        let user_id =
+            link_or_provision_oidc_user(mm.clone(), &claims("sub-1", "new@mail.com", true), true)
+                .await?;
+
+        let user = crate::model::user::get_user_for_login(mm, "new@mail.com")
+            .await?
+            .ok_or("user not provisioned")?;
+        assert_eq!(user.id, user_id);
+        assert!(user.pwd.is_none());
+        assert!(user.verified_at.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_oidc_sign_in_totp_required() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+        let claims = claims("sub-1", "demo@mail.com", true);
+
+        let signed_in = sign_in_oidc_user(mm.clone(), &claims, false).await?;
+        assert!(matches!(signed_in, OidcSignIn::Session(session) if session.user_id == user_id));
+
+        // no session before the second factor
+        mm.seed_totp(user_id).await?;
+        let signed_in = sign_in_oidc_user(mm, &claims, false).await?;
+        assert!(matches!(
+            signed_in,
+            OidcSignIn::TotpRequired { user_id: id } if id == user_id
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_oidc_link_err() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        mm.seed_user("demo@mail.com").await?;
+
+        // unverified email at the provider
+        let res =
+            link_or_provision_oidc_user(mm.clone(), &claims("sub-1", "demo@mail.com", false), true)
+                .await;
+        assert!(matches!(res, Err(super::Error::OidcEmailNotVerified)));
+
+        // unknown user without provisioning
+        let res =
+            link_or_provision_oidc_user(mm, &claims("sub-2", "other@mail.com", true), false).await;
+        assert!(matches!(
+            res,
+            Err(super::Error::OidcUserNotProvisioned { .. })
+        ));
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
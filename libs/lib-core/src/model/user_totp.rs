@@ -0,0 +1,288 @@
+//! TOTP second factor of the users
+//!
+//! Enrollment stores a pending secret, enabled once the user proves the
+//! authenticator app works with a first code. Enabling also issues the
+//! single-use recovery codes, stored hashed like the reset tokens.
+
+use super::{Error, ModelManager, Result};
+use crate::token::hash_secret;
+use crate::totp::{self, decrypt_secret, encrypt_secret, provisioning_uri, qr_svg, verify_code};
+use lib_utils::time::now_utc_sec;
+use rand::RngCore;
+use serde::Serialize;
+use tracing::debug;
+
+pub const RECOVERY_CODES_COUNT: usize = 10;
+
+// region:        --- Types
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TotpEnrollment {
+    pub uri: String,
+    pub qr_svg: String,
+}
+
+// endregion:     --- Types
+
+// region:        --- Enrollment
+
+/// Stores a new pending secret, replacing any previous pending one.
+pub async fn start_totp_enrollment(
+    mm: ModelManager,
+    user_id: i64,
+    email: &str,
+) -> Result<TotpEnrollment> {
+    if is_totp_enabled(mm.clone(), user_id).await? {
+        return Err(Error::TotpAlreadyEnabled);
+    }
+
+    let secret = totp::generate_secret();
+    let db = mm.db;
+    sqlx::query(
+        "INSERT INTO user_totp (user_id, secret_enc, enabled_at, last_step)
+        VALUES ($1, $2, NULL, NULL)
+        ON CONFLICT (user_id) DO UPDATE SET secret_enc = excluded.secret_enc,
+        enabled_at = NULL, last_step = NULL",
+    )
+    .bind(user_id)
+    .bind(encrypt_secret(&secret)?)
+    .execute(&db)
+    .await?;
+
+    let uri = provisioning_uri(&secret, email);
+    let qr_svg = qr_svg(&uri)?;
+
+    Ok(TotpEnrollment { uri, qr_svg })
+}
+
+/// Enables the pending secret if `code` matches, returns the recovery codes to
+/// show once to the user.
+pub async fn enable_totp(mm: ModelManager, user_id: i64, code: &str) -> Result<Vec<String>> {
+    let (secret_enc, enabled_at) = get_user_totp(mm.clone(), user_id)
+        .await?
+        .ok_or(Error::TotpNotEnrolled)?;
+    if enabled_at.is_some() {
+        return Err(Error::TotpAlreadyEnabled);
+    }
+
+    let secret = decrypt_secret(&secret_enc)?;
+    let step = verify_code(&secret, code, now_utc_sec())?.ok_or(Error::TotpInvalidCode)?;
+
+    let db = mm.db.clone();
+    sqlx::query("UPDATE user_totp SET enabled_at = $1, last_step = $2 WHERE user_id = $3")
+        .bind(now_utc_sec())
+        .bind(step)
+        .bind(user_id)
+        .execute(&db)
+        .await?;
+
+    create_recovery_codes(mm, user_id).await
+}
+
+pub async fn is_totp_enabled(mm: ModelManager, user_id: i64) -> Result<bool> {
+    let enabled = get_user_totp(mm, user_id)
+        .await?
+        .is_some_and(|(_, enabled_at)| enabled_at.is_some());
+
+    Ok(enabled)
+}
+
+/// Removes the second factor of the user, who can enroll again.
+pub async fn reset_totp(mm: ModelManager, user_id: i64) -> Result<()> {
+    let db = mm.db;
+    sqlx::query("DELETE FROM user_totp WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&db)
+        .await?;
+    sqlx::query("DELETE FROM recovery_code WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&db)
+        .await?;
+
+    debug!("{:<12} - 2FA reset for user {user_id}", "TOTP");
+
+    Ok(())
+}
+
+// endregion:     --- Enrollment
+
+// region:        --- Verification
+
+/// Accepts a TOTP code, never twice the same step, or an unused recovery code.
+pub async fn verify_totp(mm: ModelManager, user_id: i64, code: &str) -> Result<()> {
+    let Some((secret_enc, Some(_))) = get_user_totp(mm.clone(), user_id).await? else {
+        return Err(Error::TotpNotEnrolled);
+    };
+
+    let secret = decrypt_secret(&secret_enc)?;
+    if let Some(step) = verify_code(&secret, code, now_utc_sec())? {
+        let db = mm.db;
+        let res = sqlx::query(
+            "UPDATE user_totp SET last_step = $1
+            WHERE user_id = $2 AND (last_step IS NULL OR last_step < $1)",
+        )
+        .bind(step)
+        .bind(user_id)
+        .execute(&db)
+        .await?;
+
+        return match res.rows_affected() {
+            0 => Err(Error::TotpInvalidCode),
+            _ => Ok(()),
+        };
+    }
+
+    consume_recovery_code(mm, user_id, code).await
+}
+
+// endregion:     --- Verification
+
+// region:        --- Recovery codes
+
+async fn create_recovery_codes(mm: ModelManager, user_id: i64) -> Result<Vec<String>> {
+    let db = mm.db;
+    sqlx::query("DELETE FROM recovery_code WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&db)
+        .await?;
+
+    let mut codes = Vec::with_capacity(RECOVERY_CODES_COUNT);
+    for _ in 0..RECOVERY_CODES_COUNT {
+        let code = generate_recovery_code();
+        sqlx::query("INSERT INTO recovery_code (user_id, code_hash) VALUES ($1, $2)")
+            .bind(user_id)
+            .bind(hash_secret(&normalize_recovery_code(&code)))
+            .execute(&db)
+            .await?;
+        codes.push(code);
+    }
+
+    Ok(codes)
+}
+
+async fn consume_recovery_code(mm: ModelManager, user_id: i64, code: &str) -> Result<()> {
+    let db = mm.db;
+    let res = sqlx::query(
+        "UPDATE recovery_code SET used_at = $1
+        WHERE user_id = $2 AND code_hash = $3 AND used_at IS NULL",
+    )
+    .bind(now_utc_sec())
+    .bind(user_id)
+    .bind(hash_secret(&normalize_recovery_code(code)))
+    .execute(&db)
+    .await?;
+
+    match res.rows_affected() {
+        0 => Err(Error::TotpInvalidCode),
+        _ => {
+            debug!("{:<12} - recovery code used by user {user_id}", "TOTP");
+            Ok(())
+        }
+    }
+}
+
+/// `xxxx-xxxx`, lowercase base32 to avoid ambiguous characters.
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 5];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let code = base32::encode(base32::Alphabet::Rfc4648 { padding: false }, &bytes).to_lowercase();
+
+    format!("{}-{}", &code[..4], &code[4..])
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+// endregion:     --- Recovery codes
+
+async fn get_user_totp(mm: ModelManager, user_id: i64) -> Result<Option<(String, Option<i64>)>> {
+    let db = mm.db;
+    let totp = sqlx::query_as::<_, (String, Option<i64>)>(
+        "SELECT secret_enc, enabled_at FROM user_totp WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(&db)
+    .await?;
+
+    Ok(totp)
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+    use crate::totp::{code_at_step, step_at};
+
+    /// Current code of the user, read back from the provisioning URI.
+    fn current_code(enrollment: &TotpEnrollment) -> Result<String> {
+        let secret_b32 = enrollment
+            .uri
+            .split("secret=")
+            .nth(1)
+            .and_then(|s| s.split('&').next())
+            .ok_or("no secret in uri")?;
+        let secret = base32::decode(base32::Alphabet::Rfc4648 { padding: false }, secret_b32)
+            .ok_or("secret not base32")?;
+
+        Ok(code_at_step(&secret, step_at(now_utc_sec()))?)
+    }
+
+    #[tokio::test]
+    async fn test_totp_enroll_verify() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+
+        let enrollment = start_totp_enrollment(mm.clone(), user_id, "demo@mail.com").await?;
+        assert!(!is_totp_enabled(mm.clone(), user_id).await?);
+        let res = enable_totp(mm.clone(), user_id, "000000x").await;
+        assert!(matches!(res, Err(super::Error::TotpInvalidCode)));
+
+        let code = current_code(&enrollment)?;
+        let recovery_codes = enable_totp(mm.clone(), user_id, &code).await?;
+        assert_eq!(recovery_codes.len(), RECOVERY_CODES_COUNT);
+        assert!(is_totp_enabled(mm.clone(), user_id).await?);
+
+        // the enrollment code cannot be replayed
+        let res = verify_totp(mm.clone(), user_id, &code).await;
+        assert!(matches!(res, Err(super::Error::TotpInvalidCode)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_totp_recovery_code_single_use() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+        let enrollment = start_totp_enrollment(mm.clone(), user_id, "demo@mail.com").await?;
+        let recovery_codes = enable_totp(mm.clone(), user_id, &current_code(&enrollment)?).await?;
+
+        let code = recovery_codes[0].to_uppercase();
+        verify_totp(mm.clone(), user_id, &code).await?;
+        let res = verify_totp(mm.clone(), user_id, &code).await;
+        assert!(matches!(res, Err(super::Error::TotpInvalidCode)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_totp_reset() -> Result<()> {
+        let mm = ModelManager::new_for_test().await?;
+        let user_id = mm.seed_user("demo@mail.com").await?;
+        let enrollment = start_totp_enrollment(mm.clone(), user_id, "demo@mail.com").await?;
+        let recovery_codes = enable_totp(mm.clone(), user_id, &current_code(&enrollment)?).await?;
+
+        reset_totp(mm.clone(), user_id).await?;
+        assert!(!is_totp_enabled(mm.clone(), user_id).await?);
+        let res = verify_totp(mm.clone(), user_id, &recovery_codes[0]).await;
+        assert!(matches!(res, Err(super::Error::TotpNotEnrolled)));
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
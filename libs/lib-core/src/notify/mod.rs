@@ -0,0 +1,121 @@
+//! Outgoing messages to users (password reset, verification links, ...)
+//!
+//! Delivery is behind the `Notifier` trait, the dev implementations only
+//! write the messages to the log or to a file.
+
+mod error;
+
+pub use self::error::{Error, Result};
+
+use crate::config;
+use lib_utils::files::create_file;
+use std::fmt::Debug;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::info;
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+pub trait Notifier: Debug + Send + Sync {
+    fn notify(&self, message: &Message) -> Result<()>;
+}
+
+/// `FileNotifier` when `SERVICE_NOTIFY_FILE` is set, `LogNotifier` otherwise.
+pub fn new_notifier() -> Arc<dyn Notifier> {
+    match &config().NOTIFY_FILE {
+        Some(path) => Arc::new(FileNotifier::new(path)),
+        None => Arc::new(LogNotifier),
+    }
+}
+
+// region:        --- LogNotifier
+
+#[derive(Debug)]
+pub struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn notify(&self, message: &Message) -> Result<()> {
+        info!(
+            "{:<12} - to: {} - {}\n{}",
+            "NOTIFY", message.to, message.subject, message.body
+        );
+
+        Ok(())
+    }
+}
+
+// endregion:     --- LogNotifier
+
+// region:        --- FileNotifier
+
+/// Appends every message to a file, one block per message.
+#[derive(Debug)]
+pub struct FileNotifier {
+    path: PathBuf,
+}
+
+impl FileNotifier {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Notifier for FileNotifier {
+    fn notify(&self, message: &Message) -> Result<()> {
+        create_file(&self.path).map_err(|ex| Error::CannotWriteFile(ex.to_string()))?;
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(|ex| Error::CannotWriteFile(ex.to_string()))?;
+        writeln!(
+            file,
+            "To: {}\nSubject: {}\n\n{}\n---",
+            message.to, message.subject, message.body
+        )
+        .map_err(|ex| Error::CannotWriteFile(ex.to_string()))?;
+
+        Ok(())
+    }
+}
+
+// endregion:     --- FileNotifier
+
+// region:        --- MemoryNotifier
+
+/// Keeps the messages in memory, for tests.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MemoryNotifier {
+    pub messages: std::sync::Mutex<Vec<Message>>,
+}
+
+#[cfg(test)]
+impl MemoryNotifier {
+    pub fn last_body(&self) -> Option<String> {
+        self.messages
+            .lock()
+            .ok()
+            .and_then(|messages| messages.last().map(|m| m.body.clone()))
+    }
+}
+
+#[cfg(test)]
+impl Notifier for MemoryNotifier {
+    fn notify(&self, message: &Message) -> Result<()> {
+        if let Ok(mut messages) = self.messages.lock() {
+            messages.push(message.clone());
+        }
+
+        Ok(())
+    }
+}
+
+// endregion:     --- MemoryNotifier
@@ -0,0 +1,50 @@
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[serde_as]
+#[derive(Debug, Serialize)]
+pub enum Error {
+    NotConfigured,
+
+    // -- Discovery
+    DiscoveryIssuerMismatch { expected: String, found: String },
+
+    // -- Callback
+    StateNotMatching,
+    TokenResponseWithoutIdToken,
+
+    // -- ID token
+    IdTokenKeyNotFound { kid: Option<String> },
+    IdTokenAlgNotSupported,
+    IdTokenNonceNotMatching,
+
+    // -- Externals
+    Http(#[serde_as(as = "DisplayFromStr")] reqwest::Error),
+    Jwt(#[serde_as(as = "DisplayFromStr")] jsonwebtoken::errors::Error),
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(ex: reqwest::Error) -> Self {
+        Self::Http(ex)
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for Error {
+    fn from(ex: jsonwebtoken::errors::Error) -> Self {
+        Self::Jwt(ex)
+    }
+}
+
+// region:    --- Error Boilerplate
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
+        write!(fmt, "{self:?}")
+    }
+}
+
+impl std::error::Error for Error {}
+
+// endregion: --- Error Boilerplate
@@ -0,0 +1,508 @@
+//! OpenID Connect relying party
+//!
+//! Authorization code flow with PKCE. The provider is found by discovery
+//! (`<issuer>/.well-known/openid-configuration`), and the ID token is checked
+//! against its JWKS: signature, issuer, audience, expiration and nonce.
+//! SSO is enabled when `SERVICE_OIDC_ISSUER_URL` is set.
+
+mod error;
+
+pub use self::error::{Error, Result};
+
+use crate::config;
+use crate::token::generate_secret;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use lib_utils::b64::b64u_encode;
+use lib_utils::envs::{get_env, get_env_parse};
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+const SUPPORTED_ALGS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::ES256,
+    Algorithm::ES384,
+];
+
+// region:        --- Config
+
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    pub issuer_url: String,
+    pub client_id: String,
+    /// `None` for a public client, PKCE only.
+    pub client_secret: Option<String>,
+    pub redirect_url: String,
+    /// Creates the `user` row of unknown staff, otherwise only existing emails can sign in.
+    pub provision: bool,
+}
+
+impl OidcConfig {
+    /// `None` when `SERVICE_OIDC_ISSUER_URL` is not set.
+    pub fn load_from_env(web_url: &str) -> lib_utils::Result<Option<Self>> {
+        let Ok(issuer_url) = get_env("SERVICE_OIDC_ISSUER_URL") else {
+            return Ok(None);
+        };
+
+        Ok(Some(OidcConfig {
+            issuer_url,
+            client_id: get_env("SERVICE_OIDC_CLIENT_ID")?,
+            client_secret: get_env("SERVICE_OIDC_CLIENT_SECRET").ok(),
+            redirect_url: get_env("SERVICE_OIDC_REDIRECT_URL")
+                .unwrap_or_else(|_| format!("{web_url}/auth/oidc/callback")),
+            provision: match get_env("SERVICE_OIDC_PROVISION") {
+                Ok(_) => get_env_parse("SERVICE_OIDC_PROVISION")?,
+                Err(_) => true,
+            },
+        }))
+    }
+}
+
+// endregion:     --- Config
+
+// region:        --- Types
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// Where to send the browser, and what to keep until the callback.
+#[derive(Debug, Clone)]
+pub struct AuthRequest {
+    pub url: String,
+    pub flow: FlowState,
+}
+
+/// Secrets of one login attempt, kept in a signed cookie during the redirect.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowState {
+    pub state: String,
+    pub nonce: String,
+    pub pkce_verifier: String,
+}
+
+impl FlowState {
+    fn new() -> Self {
+        Self {
+            state: generate_secret(),
+            nonce: generate_secret(),
+            pkce_verifier: generate_secret(),
+        }
+    }
+
+    /// `<state>.<nonce>.<pkce_verifier>`, all b64u so the dot is a safe separator.
+    pub fn encode(&self) -> String {
+        format!("{}.{}.{}", self.state, self.nonce, self.pkce_verifier)
+    }
+
+    pub fn decode(s: &str) -> Option<Self> {
+        let mut parts = s.split('.');
+        let flow = Self {
+            state: parts.next()?.to_string(),
+            nonce: parts.next()?.to_string(),
+            pkce_verifier: parts.next()?.to_string(),
+        };
+
+        parts.next().is_none().then_some(flow)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct IdClaims {
+    pub iss: String,
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub name: Option<String>,
+    pub nonce: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+// endregion:     --- Types
+
+#[derive(Debug, Clone)]
+pub struct OidcClient {
+    config: OidcConfig,
+    metadata: ProviderMetadata,
+    http: reqwest::Client,
+}
+
+/// Whether SSO is configured for this deployment.
+pub fn is_enabled() -> bool {
+    config().OIDC.is_some()
+}
+
+impl OidcClient {
+    /// Client of the configured provider, discovered on each call.
+    pub async fn from_config() -> Result<Self> {
+        let oidc_config = config().OIDC.clone().ok_or(Error::NotConfigured)?;
+
+        Self::discover(oidc_config).await
+    }
+
+    pub async fn discover(config: OidcConfig) -> Result<Self> {
+        let http = reqwest::Client::new();
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            config.issuer_url.trim_end_matches('/')
+        );
+        let metadata: ProviderMetadata = http
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if metadata.issuer.trim_end_matches('/') != config.issuer_url.trim_end_matches('/') {
+            return Err(Error::DiscoveryIssuerMismatch {
+                expected: config.issuer_url,
+                found: metadata.issuer,
+            });
+        }
+
+        Ok(Self {
+            config,
+            metadata,
+            http,
+        })
+    }
+
+    pub fn config(&self) -> &OidcConfig {
+        &self.config
+    }
+
+    pub fn authorization_request(&self) -> Result<AuthRequest> {
+        let flow = FlowState::new();
+        let url = Url::parse_with_params(
+            &self.metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &self.config.client_id),
+                ("redirect_uri", &self.config.redirect_url),
+                ("scope", "openid email profile"),
+                ("state", &flow.state),
+                ("nonce", &flow.nonce),
+                ("code_challenge", &pkce_challenge(&flow.pkce_verifier)),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|_| Error::NotConfigured)?;
+
+        Ok(AuthRequest {
+            url: url.to_string(),
+            flow,
+        })
+    }
+
+    /// Exchanges the callback code and returns the validated ID token claims.
+    pub async fn exchange_code(
+        &self,
+        flow: &FlowState,
+        state: &str,
+        code: &str,
+    ) -> Result<IdClaims> {
+        if flow.state != state {
+            return Err(Error::StateNotMatching);
+        }
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.config.redirect_url),
+            ("client_id", &self.config.client_id),
+            ("code_verifier", &flow.pkce_verifier),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret));
+        }
+
+        let res: TokenResponse = self
+            .http
+            .post(&self.metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let id_token = res.id_token.ok_or(Error::TokenResponseWithoutIdToken)?;
+
+        self.validate_id_token(&id_token, &flow.nonce).await
+    }
+
+    async fn validate_id_token(&self, id_token: &str, nonce: &str) -> Result<IdClaims> {
+        let header = decode_header(id_token)?;
+        if !SUPPORTED_ALGS.contains(&header.alg) {
+            return Err(Error::IdTokenAlgNotSupported);
+        }
+
+        let jwks: JwkSet = self
+            .http
+            .get(&self.metadata.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let jwk = match &header.kid {
+            Some(kid) => jwks.find(kid),
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None,
+        }
+        .ok_or(Error::IdTokenKeyNotFound {
+            kid: header.kid.clone(),
+        })?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.metadata.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        let claims =
+            decode::<IdClaims>(id_token, &DecodingKey::from_jwk(jwk)?, &validation)?.claims;
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(Error::IdTokenNonceNotMatching);
+        }
+
+        Ok(claims)
+    }
+}
+
+fn pkce_challenge(verifier: &str) -> String {
+    b64u_encode(Sha256::digest(verifier.as_bytes()))
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+pub(crate) mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+    use axum::extract::State;
+    use axum::routing::{get, post};
+    use axum::{Form, Json, Router};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use lib_utils::time::now_utc_plus_sec;
+    use serde::Serialize;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    const RSA_KEY_PEM: &str = include_str!("test_rsa_key.pem");
+    const RSA_KEY_N: &str = "y-tOleMqbF8lWwUWvApO2XZrekJvCs6X7Z0fpsdzSphDA-JtFBLdKDvggzFV3PU566MxhlhIFthPuxBDz2FWaziar8cZZIFMuqvjPQIx5G8W_KuNIMYqsp3WD2nDrnxXoh7s-PMSuCcQ0nPsbOPvi-Wca1IQQRyT4zu1QmnHoNcVE6WC_0R3oAHacvKLo33xLF9rKj2wdpJN29NNVLqwRYFcgAiGrQn5m3-87eazrJj3ilwwrIDCT2CVWV5pf6YEIVZCcBWPwKYcI31ptuaTnH-oLIQ9oy120JpS2UdmRUdJHmWGsGlYuqYXX1Ds8BbDb5gA5djo8q5SQ6F0m17u_Q";
+    const KID: &str = "test-key";
+    const CLIENT_ID: &str = "intranet";
+
+    // region:        --- Mock provider
+
+    /// Pending authorization of the mock provider, what the user approved.
+    #[derive(Clone)]
+    struct Grant {
+        code_challenge: String,
+        nonce: String,
+        claims: MockClaims,
+    }
+
+    #[derive(Serialize, Clone)]
+    pub(crate) struct MockClaims {
+        pub sub: String,
+        pub email: String,
+        pub email_verified: bool,
+        pub name: String,
+    }
+
+    #[derive(Clone)]
+    pub(crate) struct MockProvider {
+        pub issuer: String,
+        grants: Arc<Mutex<HashMap<String, Grant>>>,
+        /// Audience of the issued ID tokens, to test the validation.
+        audience: Arc<Mutex<String>>,
+    }
+
+    impl MockProvider {
+        /// Starts the provider on a random local port.
+        pub async fn start() -> Result<Self> {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+            let provider = MockProvider {
+                issuer: format!("http://{}", listener.local_addr()?),
+                grants: Default::default(),
+                audience: Arc::new(Mutex::new(CLIENT_ID.to_string())),
+            };
+
+            let routes = Router::new()
+                .route("/.well-known/openid-configuration", get(discovery_handler))
+                .route("/jwks", get(jwks_handler))
+                .route("/token", post(token_handler))
+                .with_state(provider.clone());
+            tokio::spawn(async move { axum::serve(listener, routes).await });
+
+            Ok(provider)
+        }
+
+        pub fn config(&self) -> OidcConfig {
+            OidcConfig {
+                issuer_url: self.issuer.clone(),
+                client_id: CLIENT_ID.to_string(),
+                client_secret: None,
+                redirect_url: "http://localhost:8080/auth/oidc/callback".to_string(),
+                provision: true,
+            }
+        }
+
+        /// What the provider does when the user signs in at the authorization URL.
+        pub fn approve(&self, auth_url: &str, claims: MockClaims) -> Result<String> {
+            let url = Url::parse(auth_url)?;
+            let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+            let code = generate_secret();
+            self.grants.lock().map_err(|_| "poisoned")?.insert(
+                code.clone(),
+                Grant {
+                    code_challenge: params.get("code_challenge").ok_or("no challenge")?.clone(),
+                    nonce: params.get("nonce").ok_or("no nonce")?.clone(),
+                    claims,
+                },
+            );
+
+            Ok(code)
+        }
+    }
+
+    async fn discovery_handler(State(provider): State<MockProvider>) -> Json<serde_json::Value> {
+        let issuer = &provider.issuer;
+        Json(serde_json::json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{issuer}/authorize"),
+            "token_endpoint": format!("{issuer}/token"),
+            "jwks_uri": format!("{issuer}/jwks"),
+        }))
+    }
+
+    async fn jwks_handler() -> Json<serde_json::Value> {
+        Json(serde_json::json!({
+            "keys": [{ "kty": "RSA", "kid": KID, "alg": "RS256", "use": "sig", "n": RSA_KEY_N, "e": "AQAB" }]
+        }))
+    }
+
+    async fn token_handler(
+        State(provider): State<MockProvider>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> core::result::Result<Json<serde_json::Value>, axum::http::StatusCode> {
+        let bad_request = axum::http::StatusCode::BAD_REQUEST;
+        let code = form.get("code").ok_or(bad_request)?;
+        let grant = provider
+            .grants
+            .lock()
+            .map_err(|_| bad_request)?
+            .remove(code)
+            .ok_or(bad_request)?;
+        let verifier = form.get("code_verifier").ok_or(bad_request)?;
+        if pkce_challenge(verifier) != grant.code_challenge {
+            return Err(bad_request);
+        }
+
+        let mut claims = serde_json::to_value(grant.claims).map_err(|_| bad_request)?;
+        claims["iss"] = provider.issuer.clone().into();
+        claims["aud"] = provider
+            .audience
+            .lock()
+            .map_err(|_| bad_request)?
+            .clone()
+            .into();
+        claims["exp"] = now_utc_plus_sec(300).into();
+        claims["nonce"] = grant.nonce.into();
+
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(KID.to_string());
+        let key = EncodingKey::from_rsa_pem(RSA_KEY_PEM.as_bytes()).map_err(|_| bad_request)?;
+        let id_token = encode(&header, &claims, &key).map_err(|_| bad_request)?;
+
+        Ok(Json(
+            serde_json::json!({ "id_token": id_token, "token_type": "Bearer" }),
+        ))
+    }
+
+    pub(crate) fn mock_claims(email: &str) -> MockClaims {
+        MockClaims {
+            sub: format!("sub-{email}"),
+            email: email.to_string(),
+            email_verified: true,
+            name: "Demo User".to_string(),
+        }
+    }
+
+    // endregion:     --- Mock provider
+
+    #[tokio::test]
+    async fn test_oidc_code_flow_ok() -> Result<()> {
+        let provider = MockProvider::start().await?;
+        let client = OidcClient::discover(provider.config()).await?;
+
+        let auth = client.authorization_request()?;
+        assert!(auth.url.contains("code_challenge_method=S256"));
+        let code = provider.approve(&auth.url, mock_claims("demo@mail.com"))?;
+
+        let claims = client
+            .exchange_code(&auth.flow, &auth.flow.state, &code)
+            .await?;
+        assert_eq!(claims.sub, "sub-demo@mail.com");
+        assert_eq!(claims.email.as_deref(), Some("demo@mail.com"));
+        assert!(claims.email_verified);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_oidc_code_flow_err() -> Result<()> {
+        let provider = MockProvider::start().await?;
+        let client = OidcClient::discover(provider.config()).await?;
+
+        // state from another login attempt
+        let auth = client.authorization_request()?;
+        let code = provider.approve(&auth.url, mock_claims("demo@mail.com"))?;
+        let res = client.exchange_code(&auth.flow, "other-state", &code).await;
+        assert!(matches!(res, Err(super::Error::StateNotMatching)));
+
+        // PKCE verifier of another login attempt
+        let other = client.authorization_request()?;
+        let res = client
+            .exchange_code(&other.flow, &other.flow.state, &code)
+            .await;
+        assert!(matches!(res, Err(super::Error::Http(_))));
+
+        // ID token for another client
+        *provider.audience.lock().map_err(|_| "poisoned")? = "other-client".to_string();
+        let auth = client.authorization_request()?;
+        let code = provider.approve(&auth.url, mock_claims("demo@mail.com"))?;
+        let res = client
+            .exchange_code(&auth.flow, &auth.flow.state, &code)
+            .await;
+        assert!(matches!(res, Err(super::Error::Jwt(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_flow_state_encode_decode() -> Result<()> {
+        let flow = FlowState::new();
+
+        assert_eq!(FlowState::decode(&flow.encode()), Some(flow));
+        assert_eq!(FlowState::decode("a.b"), None);
+        assert_eq!(FlowState::decode("a.b.c.d"), None);
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
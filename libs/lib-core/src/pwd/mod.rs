@@ -0,0 +1,158 @@
+//! Password hashing
+//!
+//! Hashes are stored as `#<scheme>#<hash>` so the algorithm can change later
+//! without invalidating the passwords already in the database.
+
+mod error;
+mod scheme_01;
+
+pub use self::error::{Error, Result};
+
+use lazy_regex::regex_captures;
+
+pub const DEFAULT_SCHEME: &str = "01";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SchemeStatus {
+    /// The password hash uses the latest scheme and parameters.
+    Ok,
+    /// The password is valid but should be hashed again with the default scheme.
+    Outdated,
+}
+
+// region:        --- Public functions
+
+/// Hash the password with the default scheme, returns `#01#<hash>`.
+pub async fn hash_pwd(pwd: String) -> Result<String> {
+    tokio::task::spawn_blocking(move || hash_for_scheme(DEFAULT_SCHEME, &pwd))
+        .await
+        .map_err(|_| Error::FailSpawnBlockForHash)?
+}
+
+/// Validate the password against a stored `#<scheme>#<hash>`.
+pub async fn validate_pwd(pwd: String, pwd_ref: String) -> Result<SchemeStatus> {
+    let PwdParts {
+        scheme_name,
+        hashed,
+    } = pwd_ref.parse()?;
+
+    tokio::task::spawn_blocking(move || {
+        validate_for_scheme(&scheme_name, &pwd, &hashed)?;
+
+        if scheme_name != DEFAULT_SCHEME || is_outdated_for_scheme(&scheme_name, &hashed)? {
+            Ok(SchemeStatus::Outdated)
+        } else {
+            Ok(SchemeStatus::Ok)
+        }
+    })
+    .await
+    .map_err(|_| Error::FailSpawnBlockForValidate)?
+}
+
+// endregion:     --- Public functions
+
+// region:        --- Schemes
+
+fn hash_for_scheme(scheme_name: &str, pwd: &str) -> Result<String> {
+    let hashed = match scheme_name {
+        "01" => scheme_01::hash(pwd)?,
+        other => return Err(Error::SchemeNotFound(other.to_string())),
+    };
+
+    Ok(format!("#{scheme_name}#{hashed}"))
+}
+
+fn validate_for_scheme(scheme_name: &str, pwd: &str, hashed: &str) -> Result<()> {
+    match scheme_name {
+        "01" => scheme_01::validate(pwd, hashed),
+        other => Err(Error::SchemeNotFound(other.to_string())),
+    }
+}
+
+fn is_outdated_for_scheme(scheme_name: &str, hashed: &str) -> Result<bool> {
+    match scheme_name {
+        "01" => scheme_01::is_outdated(hashed),
+        other => Err(Error::SchemeNotFound(other.to_string())),
+    }
+}
+
+struct PwdParts {
+    scheme_name: String,
+    hashed: String,
+}
+
+impl std::str::FromStr for PwdParts {
+    type Err = Error;
+
+    fn from_str(pwd_with_scheme: &str) -> Result<Self> {
+        regex_captures!(r#"^#(\w+)#(.*)"#, pwd_with_scheme)
+            .map(|(_, scheme, hashed)| Self {
+                scheme_name: scheme.to_string(),
+                hashed: hashed.to_string(),
+            })
+            .ok_or(Error::PwdWithSchemeFailedParse)
+    }
+}
+
+// endregion:     --- Schemes
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hash_and_validate_ok() -> Result<()> {
+        let hashed = hash_pwd("welcome".to_string()).await?;
+
+        assert!(hashed.starts_with("#01#$argon2id$"), "scheme prefix");
+        assert_eq!(
+            validate_pwd("welcome".to_string(), hashed).await?,
+            SchemeStatus::Ok
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_wrong_pwd() -> Result<()> {
+        let hashed = hash_pwd("welcome".to_string()).await?;
+
+        let res = validate_pwd("not-welcome".to_string(), hashed).await;
+        assert!(matches!(res, Err(super::Error::PwdNotMatching)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_unknown_scheme() -> Result<()> {
+        let res = validate_pwd("welcome".to_string(), "#99#whatever".to_string()).await;
+        assert!(matches!(res, Err(super::Error::SchemeNotFound(_))));
+
+        let res = validate_pwd("welcome".to_string(), "welcome".to_string()).await;
+        assert!(matches!(res, Err(super::Error::PwdWithSchemeFailedParse)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_outdated_params() -> Result<()> {
+        use argon2::password_hash::{rand_core::OsRng, PasswordHasher, SaltString};
+        use argon2::{Algorithm, Argon2, Params, Version};
+
+        // hash with a lower memory cost than the current default
+        let params = Params::new(8 * 1024, 2, 1, None)?;
+        let argon = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+        let salt = SaltString::generate(&mut OsRng);
+        let hashed = argon.hash_password(b"welcome", &salt)?.to_string();
+
+        assert_eq!(
+            validate_pwd("welcome".to_string(), format!("#01#{hashed}")).await?,
+            SchemeStatus::Outdated
+        );
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
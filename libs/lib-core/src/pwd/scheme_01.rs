@@ -0,0 +1,34 @@
+//! Scheme `01`: Argon2id with the crate default parameters, stored as a PHC string.
+
+use super::{Error, Result};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Argon2, Params};
+
+pub fn hash(pwd: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(pwd.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|ex| Error::PwdHash(ex.to_string()))
+}
+
+pub fn validate(pwd: &str, pwd_hash: &str) -> Result<()> {
+    let parsed = PasswordHash::new(pwd_hash).map_err(|ex| Error::PwdHashParse(ex.to_string()))?;
+    Argon2::default()
+        .verify_password(pwd.as_bytes(), &parsed)
+        .map_err(|_| Error::PwdNotMatching)
+}
+
+/// Returns true when the stored hash was produced with other parameters
+/// than the current ones (e.g. before a cost increase).
+pub fn is_outdated(pwd_hash: &str) -> Result<bool> {
+    let parsed = PasswordHash::new(pwd_hash).map_err(|ex| Error::PwdHashParse(ex.to_string()))?;
+    let params = Params::try_from(&parsed).map_err(|ex| Error::PwdHashParse(ex.to_string()))?;
+    let current = Params::default();
+
+    Ok(parsed.algorithm != argon2::Algorithm::default().ident()
+        || params.m_cost() != current.m_cost()
+        || params.t_cost() != current.t_cost()
+        || params.p_cost() != current.p_cost())
+}
@@ -0,0 +1,312 @@
+//! Signed and expiring tokens
+//!
+//! Format: `<ident_b64u>.<exp_b64u>.<sign_b64u>`, where `exp` is a unix
+//! timestamp in seconds and `sign` a HMAC-SHA512 of the two first parts and of
+//! the token purpose, so a token is only valid for the purpose it was issued for.
+
+mod error;
+
+pub use self::error::{Error, Result};
+
+use crate::config;
+use hmac::{Hmac, Mac};
+use lib_utils::b64::{b64u_decode, b64u_decode_to_string, b64u_encode};
+use lib_utils::time::{now_utc_plus_sec, now_utc_sec};
+use rand::RngCore;
+use sha2::{Digest, Sha256, Sha512};
+use std::fmt::Display;
+use std::str::FromStr;
+
+// region:        --- Token Type
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub ident: String,
+    pub exp: i64,
+    pub sign_b64u: String,
+}
+
+impl FromStr for Token {
+    type Err = Error;
+
+    fn from_str(token_str: &str) -> Result<Self> {
+        let splits: Vec<&str> = token_str.split('.').collect();
+        let [ident_b64u, exp_b64u, sign_b64u] = splits[..] else {
+            return Err(Error::InvalidFormat);
+        };
+
+        let ident = b64u_decode_to_string(ident_b64u).map_err(|_| Error::CannotDecodeIdent)?;
+        let exp = b64u_decode_to_string(exp_b64u)
+            .ok()
+            .and_then(|exp| exp.parse::<i64>().ok())
+            .ok_or(Error::CannotDecodeExp)?;
+
+        Ok(Self {
+            ident,
+            exp,
+            sign_b64u: sign_b64u.to_string(),
+        })
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}",
+            b64u_encode(&self.ident),
+            b64u_encode(self.exp.to_string()),
+            self.sign_b64u
+        )
+    }
+}
+
+// endregion:     --- Token Type
+
+// region:        --- Purposes
+
+const PURPOSE_WEB: &str = "web";
+const PURPOSE_EMAIL: &str = "email";
+const PURPOSE_LOGIN_2FA: &str = "login-2fa";
+const PURPOSE_OIDC_FLOW: &str = "oidc-flow";
+
+// endregion:     --- Purposes
+
+// region:        --- Web Token
+
+pub fn generate_web_token(ident: &str) -> Result<Token> {
+    let config = &config();
+    _generate_token(
+        PURPOSE_WEB,
+        ident,
+        config.TOKEN_DURATION_SEC,
+        &config.TOKEN_KEY,
+    )
+}
+
+pub fn validate_web_token(token: &Token) -> Result<()> {
+    _validate_token(PURPOSE_WEB, token, &config().TOKEN_KEY)
+}
+
+// endregion:     --- Web Token
+
+// region:        --- Email Token
+
+/// Token of the email verification links, longer lived than the web token.
+pub fn generate_email_token(ident: &str) -> Result<Token> {
+    let config = &config();
+    _generate_token(
+        PURPOSE_EMAIL,
+        ident,
+        config.EMAIL_VERIFY_DURATION_SEC,
+        &config.TOKEN_KEY,
+    )
+}
+
+pub fn validate_email_token(token: &Token) -> Result<()> {
+    _validate_token(PURPOSE_EMAIL, token, &config().TOKEN_KEY)
+}
+
+// endregion:     --- Email Token
+
+// region:        --- Login 2FA Token
+
+/// Time left to enter the second factor once the password is checked.
+pub const LOGIN_2FA_DURATION_SEC: i64 = 300;
+
+pub fn generate_login_2fa_token(ident: &str) -> Result<Token> {
+    _generate_token(
+        PURPOSE_LOGIN_2FA,
+        ident,
+        LOGIN_2FA_DURATION_SEC,
+        &config().TOKEN_KEY,
+    )
+}
+
+pub fn validate_login_2fa_token(token: &Token) -> Result<()> {
+    _validate_token(PURPOSE_LOGIN_2FA, token, &config().TOKEN_KEY)
+}
+
+// endregion:     --- Login 2FA Token
+
+// region:        --- OIDC Flow Token
+
+/// Time left to sign in at the identity provider.
+pub const OIDC_FLOW_DURATION_SEC: i64 = 600;
+
+pub fn generate_oidc_flow_token(ident: &str) -> Result<Token> {
+    _generate_token(
+        PURPOSE_OIDC_FLOW,
+        ident,
+        OIDC_FLOW_DURATION_SEC,
+        &config().TOKEN_KEY,
+    )
+}
+
+pub fn validate_oidc_flow_token(token: &Token) -> Result<()> {
+    _validate_token(PURPOSE_OIDC_FLOW, token, &config().TOKEN_KEY)
+}
+
+// endregion:     --- OIDC Flow Token
+
+// region:        --- Secret
+
+/// Random 256 bits secret, b64u encoded, for the single-use tokens sent to users.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    b64u_encode(bytes)
+}
+
+/// SHA-256 of a secret, the only form stored in DB.
+pub fn hash_secret(secret: &str) -> String {
+    b64u_encode(Sha256::digest(secret.as_bytes()))
+}
+
+// endregion:     --- Secret
+
+// region:        --- Private
+
+fn _generate_token(purpose: &str, ident: &str, duration_sec: i64, key: &[u8]) -> Result<Token> {
+    let ident = ident.to_string();
+    let exp = now_utc_plus_sec(duration_sec);
+    let sign_b64u = b64u_encode(
+        _token_sign(purpose, &ident, exp, key)?
+            .finalize()
+            .into_bytes(),
+    );
+
+    Ok(Token {
+        ident,
+        exp,
+        sign_b64u,
+    })
+}
+
+fn _validate_token(purpose: &str, origin_token: &Token, key: &[u8]) -> Result<()> {
+    // -- Validate signature (constant time comparison)
+    let sign = b64u_decode(&origin_token.sign_b64u).map_err(|_| Error::SignatureNotMatching)?;
+    _token_sign(purpose, &origin_token.ident, origin_token.exp, key)?
+        .verify_slice(&sign)
+        .map_err(|_| Error::SignatureNotMatching)?;
+
+    // -- Validate expiration
+    if origin_token.exp <= now_utc_sec() {
+        return Err(Error::Expired);
+    }
+
+    Ok(())
+}
+
+fn _token_sign(purpose: &str, ident: &str, exp: i64, key: &[u8]) -> Result<Hmac<Sha512>> {
+    let content = format!(
+        "{purpose}.{}.{}",
+        b64u_encode(ident),
+        b64u_encode(exp.to_string())
+    );
+
+    let mut hmac_sha512 =
+        Hmac::<Sha512>::new_from_slice(key).map_err(|_| Error::HmacFailNewFromSlice)?;
+    hmac_sha512.update(content.as_bytes());
+
+    Ok(hmac_sha512)
+}
+
+// endregion:     --- Private
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+
+    const KEY: &[u8] = b"a-test-key-long-enough-for-hmac";
+
+    #[test]
+    fn test_token_display_parse_ok() -> Result<()> {
+        let token = _generate_token(PURPOSE_WEB, "some-ident", 60, KEY)?;
+
+        let parsed: Token = token.to_string().parse()?;
+        assert_eq!(parsed, token);
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_parse_err() -> Result<()> {
+        assert!(matches!(
+            "only.two".parse::<Token>(),
+            Err(super::Error::InvalidFormat)
+        ));
+        assert!(matches!(
+            format!("{}.###.sign", b64u_encode("ident")).parse::<Token>(),
+            Err(super::Error::CannotDecodeExp)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_secret_hash() -> Result<()> {
+        let secret = generate_secret();
+
+        assert_ne!(secret, generate_secret());
+        assert_eq!(hash_secret(&secret), hash_secret(&secret));
+        assert_ne!(hash_secret(&secret), secret);
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_ok() -> Result<()> {
+        let token = _generate_token(PURPOSE_WEB, "some-ident", 60, KEY)?;
+
+        _validate_token(PURPOSE_WEB, &token, KEY)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_err_expired() -> Result<()> {
+        let token = _generate_token(PURPOSE_WEB, "some-ident", -1, KEY)?;
+
+        assert!(matches!(
+            _validate_token(PURPOSE_WEB, &token, KEY),
+            Err(super::Error::Expired)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_err_tampered() -> Result<()> {
+        let mut token = _generate_token(PURPOSE_WEB, "some-ident", 60, KEY)?;
+        token.ident = "other-ident".to_string();
+
+        assert!(matches!(
+            _validate_token(PURPOSE_WEB, &token, KEY),
+            Err(super::Error::SignatureNotMatching)
+        ));
+        assert!(matches!(
+            _validate_token(
+                PURPOSE_WEB,
+                &_generate_token(PURPOSE_WEB, "some-ident", 60, KEY)?,
+                b"another-key"
+            ),
+            Err(super::Error::SignatureNotMatching)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_err_other_purpose() -> Result<()> {
+        let token = _generate_token(PURPOSE_EMAIL, "some-ident", 60, KEY)?;
+
+        _validate_token(PURPOSE_EMAIL, &token, KEY)?;
+        assert!(matches!(
+            _validate_token(PURPOSE_WEB, &token, KEY),
+            Err(super::Error::SignatureNotMatching)
+        ));
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
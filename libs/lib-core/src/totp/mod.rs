@@ -0,0 +1,216 @@
+//! Time-based one-time passwords (RFC 6238)
+//!
+//! SHA-1, 6 digits and 30 seconds steps, the defaults every authenticator app
+//! understands. Secrets are stored AES-256-GCM encrypted with `SERVICE_TOTP_KEY`
+//! as `<nonce_b64u>.<ciphertext_b64u>`.
+
+mod error;
+
+pub use self::error::{Error, Result};
+
+use crate::config;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use hmac::{Hmac, Mac};
+use lib_utils::b64::{b64u_decode, b64u_encode};
+use qrcode::render::svg;
+use qrcode::QrCode;
+use rand::RngCore;
+use sha1::Sha1;
+use subtle::ConstantTimeEq;
+
+pub const STEP_SEC: i64 = 30;
+pub const DIGITS: u32 = 6;
+/// Steps accepted before and after the current one, for clock drift.
+const SKEW_STEPS: i64 = 1;
+
+// region:        --- Secret
+
+/// Random 160 bits secret, the size recommended for HMAC-SHA1.
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; 20];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+pub fn encrypt_secret(secret: &[u8]) -> Result<String> {
+    _encrypt_secret(secret, &config().TOTP_KEY)
+}
+
+pub fn decrypt_secret(secret_enc: &str) -> Result<Vec<u8>> {
+    _decrypt_secret(secret_enc, &config().TOTP_KEY)
+}
+
+// endregion:     --- Secret
+
+// region:        --- Provisioning
+
+/// `otpauth://` URI to import the secret in an authenticator app.
+pub fn provisioning_uri(secret: &[u8], account: &str) -> String {
+    let issuer = encode_uri_component(&config().TOTP_ISSUER);
+    let secret = base32::encode(base32::Alphabet::Rfc4648 { padding: false }, secret);
+
+    format!(
+        "otpauth://totp/{issuer}:{}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={STEP_SEC}",
+        encode_uri_component(account)
+    )
+}
+
+/// The provisioning URI as a QR code, in a standalone SVG document.
+pub fn qr_svg(uri: &str) -> Result<String> {
+    let code = QrCode::new(uri.as_bytes()).map_err(|ex| Error::QrCode(ex.to_string()))?;
+
+    Ok(code.render::<svg::Color>().min_dimensions(200, 200).build())
+}
+
+fn encode_uri_component(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+// endregion:     --- Provisioning
+
+// region:        --- Code
+
+/// Step of a unix timestamp, the moving factor of the code.
+pub fn step_at(unix_sec: i64) -> i64 {
+    unix_sec.div_euclid(STEP_SEC)
+}
+
+pub fn code_at_step(secret: &[u8], step: i64) -> Result<String> {
+    let mut hmac =
+        <Hmac<Sha1> as Mac>::new_from_slice(secret).map_err(|_| Error::HmacFailNewFromSlice)?;
+    hmac.update(&step.to_be_bytes());
+    let hash = hmac.finalize().into_bytes();
+
+    // dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let bin = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    Ok(format!(
+        "{:0width$}",
+        bin % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    ))
+}
+
+/// Returns the matching step, within the allowed skew, if the code is valid.
+pub fn verify_code(secret: &[u8], code: &str, unix_sec: i64) -> Result<Option<i64>> {
+    let code = code.trim();
+    let current = step_at(unix_sec);
+
+    for step in (current - SKEW_STEPS)..=(current + SKEW_STEPS) {
+        // constant time, the comparison time tells nothing of the code
+        let expected = code_at_step(secret, step)?;
+        if bool::from(expected.as_bytes().ct_eq(code.as_bytes())) {
+            return Ok(Some(step));
+        }
+    }
+
+    Ok(None)
+}
+
+// endregion:     --- Code
+
+// region:        --- Private
+
+fn _encrypt_secret(secret: &[u8], key: &[u8]) -> Result<String> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| Error::KeyFailNewFromSlice)?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let content = cipher
+        .encrypt(&nonce, secret)
+        .map_err(|_| Error::SecretFailEncrypt)?;
+
+    Ok(format!("{}.{}", b64u_encode(nonce), b64u_encode(content)))
+}
+
+fn _decrypt_secret(secret_enc: &str, key: &[u8]) -> Result<Vec<u8>> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| Error::KeyFailNewFromSlice)?;
+    let (nonce_b64u, content_b64u) = secret_enc.split_once('.').ok_or(Error::SecretFailDecrypt)?;
+    let nonce = b64u_decode(nonce_b64u).map_err(|_| Error::SecretFailDecrypt)?;
+    let content = b64u_decode(content_b64u).map_err(|_| Error::SecretFailDecrypt)?;
+    if nonce.len() != 12 {
+        return Err(Error::SecretFailDecrypt);
+    }
+
+    cipher
+        .decrypt(Nonce::from_slice(&nonce), content.as_ref())
+        .map_err(|_| Error::SecretFailDecrypt)
+}
+
+// endregion:     --- Private
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+
+    const KEY: &[u8; 32] = b"an-aes-256-key-of-32-bytes-long!";
+
+    #[test]
+    fn test_code_rfc6238_vectors() -> Result<()> {
+        // RFC 6238, appendix B, SHA-1 (last 6 digits of the 8 digits values)
+        let secret = b"12345678901234567890";
+
+        assert_eq!(code_at_step(secret, step_at(59))?, "287082");
+        assert_eq!(code_at_step(secret, step_at(1111111109))?, "081804");
+        assert_eq!(code_at_step(secret, step_at(1234567890))?, "005924");
+        assert_eq!(code_at_step(secret, step_at(2000000000))?, "279037");
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_code_skew() -> Result<()> {
+        let secret = generate_secret();
+        let now = 1_700_000_000;
+        let previous = code_at_step(&secret, step_at(now) - 1)?;
+        let too_old = code_at_step(&secret, step_at(now) - 2)?;
+
+        assert_eq!(
+            verify_code(&secret, &previous, now)?,
+            Some(step_at(now) - 1)
+        );
+        assert_eq!(verify_code(&secret, &too_old, now)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_secret_encrypt_decrypt() -> Result<()> {
+        let secret = generate_secret();
+
+        let secret_enc = _encrypt_secret(&secret, KEY)?;
+        assert_eq!(_decrypt_secret(&secret_enc, KEY)?, secret);
+        assert!(matches!(
+            _decrypt_secret(&secret_enc, b"another-aes-256-key-of-32-bytes!"),
+            Err(super::Error::SecretFailDecrypt)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_provisioning_uri() -> Result<()> {
+        let uri = provisioning_uri(b"12345678901234567890", "demo@mail.com");
+
+        assert!(uri.starts_with("otpauth://totp/"));
+        assert!(uri.contains(":demo@mail.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&"));
+        assert!(qr_svg(&uri)?.contains("<svg"));
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
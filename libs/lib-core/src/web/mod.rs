@@ -0,0 +1,141 @@
+use crate::config;
+use crate::oidc::FlowState;
+use crate::token::{
+    generate_login_2fa_token, generate_oidc_flow_token, generate_web_token,
+    validate_login_2fa_token, validate_oidc_flow_token, Result, Token, LOGIN_2FA_DURATION_SEC,
+    OIDC_FLOW_DURATION_SEC,
+};
+use tower_cookies::cookie::time::Duration;
+use tower_cookies::cookie::SameSite;
+use tower_cookies::{Cookie, Cookies};
+
+pub const AUTH_TOKEN: &str = "auth-token";
+pub const REFRESH_TOKEN: &str = "refresh-token";
+pub const LOGIN_2FA: &str = "login-2fa";
+const LOGIN_2FA_IDENT_PREFIX: &str = "login-2fa";
+pub const OIDC_FLOW: &str = "oidc-flow";
+const OIDC_FLOW_IDENT_PREFIX: &str = "oidc-flow";
+
+/// Sets a fresh token for the session as an `HttpOnly` cookie.
+pub fn set_token_cookie(cookies: &Cookies, session_id: &str) -> Result<()> {
+    let token = generate_web_token(session_id)?;
+
+    let mut cookie = Cookie::new(AUTH_TOKEN, token.to_string());
+    cookie.set_http_only(true);
+    cookie.set_same_site(SameSite::Lax);
+    cookie.set_path("/");
+
+    cookies.add(cookie);
+
+    Ok(())
+}
+
+pub fn remove_token_cookie(cookies: &Cookies) {
+    let mut cookie = Cookie::from(AUTH_TOKEN);
+    cookie.set_path("/");
+
+    cookies.remove(cookie);
+}
+
+// region:        --- Refresh Token
+
+/// Keeps the "remember me" token, sent back to reopen a session.
+pub fn set_refresh_cookie(cookies: &Cookies, token: &str) {
+    let mut cookie = Cookie::new(REFRESH_TOKEN, token.to_string());
+    cookie.set_http_only(true);
+    cookie.set_same_site(SameSite::Lax);
+    cookie.set_path("/");
+    cookie.set_max_age(Duration::seconds(config().REFRESH_TOKEN_DURATION_SEC));
+
+    cookies.add(cookie);
+}
+
+pub fn get_refresh_token(cookies: &Cookies) -> Option<String> {
+    cookies.get(REFRESH_TOKEN).map(|c| c.value().to_string())
+}
+
+pub fn remove_refresh_cookie(cookies: &Cookies) {
+    let mut cookie = Cookie::from(REFRESH_TOKEN);
+    cookie.set_path("/");
+
+    cookies.remove(cookie);
+}
+
+// endregion:     --- Refresh Token
+
+// region:        --- Login 2FA
+
+/// Remembers the user whose password was checked, until the second factor is.
+pub fn set_login_2fa_cookie(cookies: &Cookies, user_id: i64) -> Result<()> {
+    let token = generate_login_2fa_token(&format!("{LOGIN_2FA_IDENT_PREFIX}:{user_id}"))?;
+
+    let mut cookie = Cookie::new(LOGIN_2FA, token.to_string());
+    cookie.set_http_only(true);
+    cookie.set_same_site(SameSite::Strict);
+    cookie.set_path("/");
+    cookie.set_max_age(Duration::seconds(LOGIN_2FA_DURATION_SEC));
+
+    cookies.add(cookie);
+
+    Ok(())
+}
+
+/// User of a valid login 2FA cookie, if any.
+pub fn get_login_2fa_user_id(cookies: &Cookies) -> Option<i64> {
+    let token: Token = cookies.get(LOGIN_2FA)?.value().parse().ok()?;
+    validate_login_2fa_token(&token).ok()?;
+
+    token
+        .ident
+        .strip_prefix(LOGIN_2FA_IDENT_PREFIX)?
+        .strip_prefix(':')?
+        .parse()
+        .ok()
+}
+
+pub fn remove_login_2fa_cookie(cookies: &Cookies) {
+    let mut cookie = Cookie::from(LOGIN_2FA);
+    cookie.set_path("/");
+
+    cookies.remove(cookie);
+}
+
+// endregion:     --- Login 2FA
+
+// region:        --- OIDC Flow
+
+/// Keeps the state, nonce and PKCE verifier during the redirect to the identity provider.
+pub fn set_oidc_flow_cookie(cookies: &Cookies, flow: &FlowState) -> Result<()> {
+    let token = generate_oidc_flow_token(&format!("{OIDC_FLOW_IDENT_PREFIX}:{}", flow.encode()))?;
+
+    let mut cookie = Cookie::new(OIDC_FLOW, token.to_string());
+    cookie.set_http_only(true);
+    // Lax, the callback is a top-level navigation from the provider site
+    cookie.set_same_site(SameSite::Lax);
+    cookie.set_path("/");
+    cookie.set_max_age(Duration::seconds(OIDC_FLOW_DURATION_SEC));
+
+    cookies.add(cookie);
+
+    Ok(())
+}
+
+/// Flow of a valid OIDC flow cookie, if any. The cookie is removed, a flow is single-use.
+pub fn take_oidc_flow_cookie(cookies: &Cookies) -> Option<FlowState> {
+    let cookie = cookies.get(OIDC_FLOW)?;
+    let mut removal = Cookie::from(OIDC_FLOW);
+    removal.set_path("/");
+    cookies.remove(removal);
+
+    let token: Token = cookie.value().parse().ok()?;
+    validate_oidc_flow_token(&token).ok()?;
+
+    FlowState::decode(
+        token
+            .ident
+            .strip_prefix(OIDC_FLOW_IDENT_PREFIX)?
+            .strip_prefix(':')?,
+    )
+}
+
+// endregion:     --- OIDC Flow
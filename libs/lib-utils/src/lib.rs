@@ -1,6 +1,9 @@
 pub mod b64;
 pub mod envs;
 pub mod files;
+pub mod list;
+pub mod pwd_policy;
+pub mod time;
 
 mod error;
 
@@ -0,0 +1,77 @@
+//! Pagination and ordering of the lists, shared by the browser and the server
+//!
+//! The filters go along as JSON, see `lib_core::model::list` for their syntax.
+
+use serde::{Deserialize, Serialize};
+
+pub const LIST_LIMIT_DEFAULT: i64 = 100;
+pub const LIST_LIMIT_MAX: i64 = 1000;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListOptions {
+    /// `LIST_LIMIT_DEFAULT` when not set, at most `LIST_LIMIT_MAX`.
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    /// Comma separated columns, `!` first for a descending one, e.g.
+    /// `"!created_at,email"`.
+    pub order_bys: Option<String>,
+}
+
+impl ListOptions {
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(LIST_LIMIT_DEFAULT)
+            .clamp(0, LIST_LIMIT_MAX)
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset.unwrap_or(0).max(0)
+    }
+
+    /// Columns with `true` for an ascending order.
+    pub fn order_bys(&self) -> Vec<(&str, bool)> {
+        self.order_bys
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|column| !column.is_empty())
+            .map(|column| match column.strip_prefix('!') {
+                Some(column) => (column, false),
+                None => (column, true),
+            })
+            .collect()
+    }
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+
+    #[test]
+    fn test_list_options() -> Result<()> {
+        let options = ListOptions {
+            limit: Some(5000),
+            offset: Some(-3),
+            order_bys: Some("!created_at, email,".to_string()),
+        };
+        assert_eq!(options.limit(), LIST_LIMIT_MAX);
+        assert_eq!(options.offset(), 0);
+        assert_eq!(
+            options.order_bys(),
+            [("created_at", false), ("email", true)]
+        );
+
+        let options = ListOptions::default();
+        assert_eq!(options.limit(), LIST_LIMIT_DEFAULT);
+        assert!(options.order_bys().is_empty());
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
@@ -0,0 +1,146 @@
+//! Password policy, shared by the browser (live feedback) and the server
+//!
+//! No dependency on the server side, so it builds for WASM too. The blocklist
+//! is `data/common-passwords.txt`, embedded at compile time.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+pub const PWD_MIN_LEN: usize = 12;
+/// Among lowercase, uppercase, digit and other.
+pub const PWD_MIN_CHAR_CLASSES: usize = 3;
+
+const COMMON_PASSWORDS: &str = include_str!("../data/common-passwords.txt");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PwdIssue {
+    TooShort,
+    TooFewCharClasses,
+    Common,
+    ContainsEmail,
+}
+
+impl PwdIssue {
+    /// The rules, in the order they are shown.
+    pub const ALL: [PwdIssue; 4] = [
+        Self::TooShort,
+        Self::TooFewCharClasses,
+        Self::Common,
+        Self::ContainsEmail,
+    ];
+}
+
+impl core::fmt::Display for PwdIssue {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::TooShort => write!(fmt, "At least {PWD_MIN_LEN} characters"),
+            Self::TooFewCharClasses => write!(
+                fmt,
+                "At least {PWD_MIN_CHAR_CLASSES} of lowercase, uppercase, digits and symbols"
+            ),
+            Self::Common => write!(fmt, "Not a common password"),
+            Self::ContainsEmail => write!(fmt, "Not containing your email"),
+        }
+    }
+}
+
+/// Every rule the password breaks, empty when it is accepted.
+pub fn check_pwd(pwd: &str, email: &str) -> Vec<PwdIssue> {
+    let mut issues = Vec::new();
+
+    if pwd.chars().count() < PWD_MIN_LEN {
+        issues.push(PwdIssue::TooShort);
+    }
+    if char_classes(pwd) < PWD_MIN_CHAR_CLASSES {
+        issues.push(PwdIssue::TooFewCharClasses);
+    }
+
+    let pwd = pwd.to_lowercase();
+    if common_passwords().contains(&pwd.as_str()) {
+        issues.push(PwdIssue::Common);
+    }
+    if contains_email(&pwd, &email.to_lowercase()) {
+        issues.push(PwdIssue::ContainsEmail);
+    }
+
+    issues
+}
+
+/// The issues as validation messages of the `pwd` field.
+pub fn pwd_field_errors(issues: &[PwdIssue]) -> BTreeMap<String, Vec<String>> {
+    BTreeMap::from([(
+        "pwd".to_string(),
+        issues.iter().map(ToString::to_string).collect(),
+    )])
+}
+
+fn char_classes(pwd: &str) -> usize {
+    let has = |class: fn(char) -> bool| pwd.chars().any(class);
+    [
+        has(char::is_lowercase),
+        has(char::is_uppercase),
+        has(|c| c.is_ascii_digit()),
+        has(|c| !c.is_alphanumeric()),
+    ]
+    .into_iter()
+    .filter(|has_class| *has_class)
+    .count()
+}
+
+/// The email, or its local part when long enough to matter.
+fn contains_email(pwd: &str, email: &str) -> bool {
+    let local = email.split_once('@').map_or(email, |(local, _)| local);
+    (!email.is_empty() && pwd.contains(email)) || (local.len() >= 3 && pwd.contains(local))
+}
+
+fn common_passwords() -> &'static Vec<&'static str> {
+    static INSTANCE: OnceLock<Vec<&'static str>> = OnceLock::new();
+
+    INSTANCE.get_or_init(|| {
+        COMMON_PASSWORDS
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect()
+    })
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    type Error = Box<dyn std::error::Error>;
+    type Result<T> = core::result::Result<T, Error>; // For tests.
+
+    use super::*;
+
+    #[test]
+    fn test_check_pwd_ok() -> Result<()> {
+        assert!(check_pwd("correct-Horse-battery", "demo@mail.com").is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_pwd_issues() -> Result<()> {
+        assert_eq!(
+            check_pwd("welcome", "demo@mail.com"),
+            [
+                PwdIssue::TooShort,
+                PwdIssue::TooFewCharClasses,
+                PwdIssue::Common
+            ]
+        );
+        assert_eq!(
+            check_pwd("Password123!", "demo@mail.com"),
+            [PwdIssue::Common]
+        );
+        assert_eq!(
+            check_pwd("my-JDoe-pwd-2026", "jdoe@corp.com"),
+            [PwdIssue::ContainsEmail]
+        );
+        Ok(())
+    }
+}
+
+// endregion: --- Tests
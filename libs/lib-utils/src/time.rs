@@ -0,0 +1,13 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds since the unix epoch, used for all the timestamps stored in DB.
+pub fn now_utc_sec() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+pub fn now_utc_plus_sec(sec: i64) -> i64 {
+    now_utc_sec() + sec
+}
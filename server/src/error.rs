@@ -5,6 +5,9 @@ pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Debug, From)]
 pub enum Error {
+    // -- Command line
+    CliUsage(&'static str),
+
     // -- Modules
     #[from]
     Web(web::Error),
@@ -12,6 +15,8 @@ pub enum Error {
     // -- Externals
     #[from]
     Core(lib_core::Error),
+    #[from]
+    Model(lib_core::model::Error),
 }
 
 // region:    --- Error Boilerplate
@@ -14,10 +14,12 @@ use axum::{
 use dotenv::dotenv;
 use leptos::{provide_context, LeptosOptions};
 use leptos_axum::handle_server_fns_with_context;
-use lib_core::model::{app_state::AppState, user::create_user_table, ModelManager};
+use lib_core::model::{app_state::AppState, migration::rollback_to, ModelManager};
+use std::net::SocketAddr;
+use tower_cookies::CookieManagerLayer;
 use tracing::{debug, info};
 use tracing_subscriber::EnvFilter;
-use web::middleware::{response_map::response_map_mw, stamp::req_stamp};
+use web::middleware::{auth::mw_ctx_resolve, response_map::response_map_mw};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -31,6 +33,19 @@ async fn main() -> Result<()> {
         .with_target(false)
         .init();
 
+    // `server db-rollback <version>` reverts the schema, without serving
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|command| command == "db-rollback") {
+        let version = match &args[1..] {
+            [version] => version.parse().ok(),
+            _ => None,
+        }
+        .ok_or(Error::CliUsage("server db-rollback <version>"))?;
+        let reverted = rollback_to(ModelManager::new().await?, version).await?;
+        info!("{:<12} - reverted {reverted:?}", "DATABASE");
+        return Ok(());
+    }
+
     // get leptos config
     let (leptos_options, addr) = web::routes_leptos::get_leptos_config().await?;
 
@@ -41,9 +56,15 @@ async fn main() -> Result<()> {
 
     let routes_all = Router::new()
         .merge(web::routes_leptos::routes(app_state.clone()))
-        .merge(web::routes_api::routes(app_state.mm.clone()))
+        .merge(web::routes_api::routes(app_state.clone()))
+        .merge(web::routes_oidc::routes(app_state.clone()))
+        .merge(web::routes_logout::routes(app_state.clone()))
         .layer(middleware::map_response(response_map_mw))
-        .layer(middleware::map_request(req_stamp));
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            mw_ctx_resolve,
+        ))
+        .layer(CookieManagerLayer::new());
 
     // endregion:     --- Axum router
 
@@ -56,9 +77,13 @@ async fn main() -> Result<()> {
         "LISTENING ON",
         addr.port()
     );
-    axum::serve(listener, routes_all.into_make_service())
-        .await
-        .unwrap();
+    // client address, for the login throttle
+    axum::serve(
+        listener,
+        routes_all.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 
     // endregion:     --- Start server
 
@@ -1,8 +1,12 @@
+use crate::web::middleware::auth::CtxExtError;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use derive_more::From;
+use lib_core::{model, oidc};
+use lib_utils::pwd_policy::pwd_field_errors;
 use serde::Serialize;
 use serde_with::{serde_as, DisplayFromStr};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use tracing::debug;
 
@@ -15,8 +19,22 @@ pub enum Error {
     BuildAxumRequest(String),
     GetLeptosConfig(String),
 
+    // -- CtxExtError
+    #[from]
+    CtxExt(CtxExtError),
+
+    // -- OIDC
+    OidcCallbackDenied {
+        error: String,
+    },
+    OidcFlowNotValid,
+
     #[from]
     Model(lib_core::model::Error),
+    #[from]
+    Oidc(lib_core::oidc::Error),
+    #[from]
+    Token(lib_core::token::Error),
 }
 
 // region:    --- Error Boilerplate
@@ -56,6 +74,36 @@ impl IntoResponse for Error {
 #[serde(tag = "message", content = "detail")]
 #[allow(non_camel_case_types)]
 pub enum ClientError {
+    NO_AUTH,
+    PERMISSION_DENIED,
+    ACCOUNT_LOCKED {
+        retry_after_sec: i64,
+    },
+    ENTITY_NOT_FOUND,
+    ACCOUNT_DISABLED,
+    SSO_NOT_CONFIGURED,
+    SSO_FAILED,
+    SSO_USER_NOT_PROVISIONED,
+    INVALID_SCOPE {
+        scope: String,
+    },
+    INVALID_ROLE {
+        role: String,
+    },
+    INVALID_LIST_QUERY {
+        reason: String,
+    },
+    /// Messages by field name.
+    INVALID_FIELDS {
+        fields: BTreeMap<String, Vec<String>>,
+    },
+    REGISTRATION_CLOSED,
+    CANNOT_IMPERSONATE_SELF,
+    CANNOT_IMPERSONATE_PRIVILEGED,
+    NOT_ALLOWED_WHILE_IMPERSONATING,
+    CANNOT_REMOVE_SELF,
+    EMAIL_ALREADY_EXISTS,
+    ALREADY_EXISTS,
     SERVICE_ERROR,
 }
 
@@ -65,6 +113,89 @@ impl Error {
 
         #[allow(unreachable_patterns)]
         match self {
+            // -- Auth
+            CtxExt(_) => (StatusCode::UNAUTHORIZED, ClientError::NO_AUTH),
+            Model(model::Error::PermissionDenied { .. } | model::Error::ApiKeyRequiresSession) => {
+                (StatusCode::FORBIDDEN, ClientError::PERMISSION_DENIED)
+            }
+            Model(model::Error::LoginLocked { retry_after_sec }) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                ClientError::ACCOUNT_LOCKED {
+                    retry_after_sec: *retry_after_sec,
+                },
+            ),
+
+            Model(model::Error::UserInactive { .. }) => {
+                (StatusCode::FORBIDDEN, ClientError::ACCOUNT_DISABLED)
+            }
+            Model(model::Error::RegistrationClosed) => {
+                (StatusCode::FORBIDDEN, ClientError::REGISTRATION_CLOSED)
+            }
+            Model(model::Error::CannotImpersonateSelf) => (
+                StatusCode::BAD_REQUEST,
+                ClientError::CANNOT_IMPERSONATE_SELF,
+            ),
+            Model(model::Error::CannotImpersonatePrivileged { .. }) => (
+                StatusCode::FORBIDDEN,
+                ClientError::CANNOT_IMPERSONATE_PRIVILEGED,
+            ),
+            Model(model::Error::NotAllowedWhileImpersonating) => (
+                StatusCode::FORBIDDEN,
+                ClientError::NOT_ALLOWED_WHILE_IMPERSONATING,
+            ),
+            Model(model::Error::CannotRemoveSelf) => {
+                (StatusCode::BAD_REQUEST, ClientError::CANNOT_REMOVE_SELF)
+            }
+
+            // -- SSO
+            Oidc(oidc::Error::NotConfigured) => {
+                (StatusCode::NOT_FOUND, ClientError::SSO_NOT_CONFIGURED)
+            }
+            Model(model::Error::OidcUserNotProvisioned { .. }) => {
+                (StatusCode::FORBIDDEN, ClientError::SSO_USER_NOT_PROVISIONED)
+            }
+            OidcCallbackDenied { .. }
+            | OidcFlowNotValid
+            | Oidc(_)
+            | Model(model::Error::OidcEmailMissing | model::Error::OidcEmailNotVerified) => {
+                (StatusCode::UNAUTHORIZED, ClientError::SSO_FAILED)
+            }
+
+            // -- Model
+            Model(model::Error::EmailAlreadyExists) => {
+                (StatusCode::CONFLICT, ClientError::EMAIL_ALREADY_EXISTS)
+            }
+            Model(model::Error::UniqueViolation { .. }) => {
+                (StatusCode::CONFLICT, ClientError::ALREADY_EXISTS)
+            }
+            Model(model::Error::EntityNotFound { .. }) => {
+                (StatusCode::NOT_FOUND, ClientError::ENTITY_NOT_FOUND)
+            }
+            Model(model::Error::ApiKeyScopeUnknown { scope }) => (
+                StatusCode::BAD_REQUEST,
+                ClientError::INVALID_SCOPE {
+                    scope: scope.to_string(),
+                },
+            ),
+            Model(model::Error::PwdPolicy { issues }) => (
+                StatusCode::BAD_REQUEST,
+                ClientError::INVALID_FIELDS {
+                    fields: pwd_field_errors(issues),
+                },
+            ),
+            Model(model::Error::InvitationRoleUnknown { role }) => (
+                StatusCode::BAD_REQUEST,
+                ClientError::INVALID_ROLE {
+                    role: role.to_string(),
+                },
+            ),
+            Model(model::Error::ListQueryInvalid { reason }) => (
+                StatusCode::BAD_REQUEST,
+                ClientError::INVALID_LIST_QUERY {
+                    reason: reason.to_string(),
+                },
+            ),
+
             // fallback
             _ => (
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -0,0 +1,204 @@
+use crate::web::{Error, Result};
+use axum::{
+    async_trait,
+    body::Body,
+    extract::{FromRequestParts, State},
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+use lib_core::ctx::Ctx;
+use lib_core::model::{
+    api_key::resolve_api_key,
+    app_state::AppState,
+    impersonation::log_impersonated_request,
+    refresh_token::rotate_refresh_token,
+    session::{create_session, touch_session},
+    ModelManager,
+};
+use lib_core::token::{validate_web_token, Token};
+use lib_core::web::{
+    get_refresh_token, remove_refresh_cookie, remove_token_cookie, set_refresh_cookie,
+    set_token_cookie, AUTH_TOKEN,
+};
+use serde::Serialize;
+use tower_cookies::Cookies;
+use tracing::{debug, info};
+
+// region:        --- Ctx Require
+
+/// Rejects the request with a 401 when no valid `Ctx` was resolved.
+pub async fn mw_ctx_require(ctx: Result<CtxW>, req: Request<Body>, next: Next) -> Result<Response> {
+    debug!("{:<12} - mw_ctx_require - {ctx:?}", "MIDDLEWARE");
+
+    ctx?;
+
+    Ok(next.run(req).await)
+}
+
+// endregion:     --- Ctx Require
+
+// region:        --- Ctx Resolve
+
+/// Resolves the `Ctx` from the `Authorization: Bearer` API key or else from the
+/// auth token cookie, and stores the result in the request extensions. On cookie
+/// success the session is extended and the token reissued. Without a valid auth
+/// token, a "remember me" refresh token silently opens a new session. Requests
+/// made while impersonating are audited, and rejected if the audit cannot be
+/// written.
+pub async fn mw_ctx_resolve(
+    State(app_state): State<AppState>,
+    cookies: Cookies,
+    mut req: Request<Body>,
+    next: Next,
+) -> Response {
+    debug!("{:<12} - mw_ctx_resolve", "MIDDLEWARE");
+
+    let ctx_ext_result = match bearer_key(req.headers()) {
+        Some(key) => ctx_resolve_api_key(app_state.mm.clone(), key).await,
+        None => {
+            let res = match ctx_resolve(app_state.mm.clone(), &cookies).await {
+                Err(_) if get_refresh_token(&cookies).is_some() => {
+                    ctx_resolve_refresh(app_state.mm.clone(), &cookies).await
+                }
+                res => res,
+            };
+            if res.is_err() && !matches!(res, Err(CtxExtError::TokenNotInCookie)) {
+                remove_token_cookie(&cookies);
+            }
+            res
+        }
+    };
+
+    let ctx_ext_result = match ctx_ext_result {
+        Ok(ctx) if ctx.is_impersonating() => {
+            let request = format!("{} {}", req.method(), req.uri().path());
+            log_impersonated_request(app_state.mm.clone(), &ctx, &request)
+                .await
+                .map(|_| ctx)
+                .map_err(|ex| CtxExtError::ModelAccessError(ex.to_string()))
+        }
+        res => res,
+    };
+
+    req.extensions_mut().insert(ctx_ext_result);
+
+    next.run(req).await
+}
+
+async fn ctx_resolve(mm: ModelManager, cookies: &Cookies) -> CtxExtResult {
+    // -- Get token string
+    let token = cookies
+        .get(AUTH_TOKEN)
+        .map(|c| c.value().to_string())
+        .ok_or(CtxExtError::TokenNotInCookie)?;
+
+    // -- Parse & validate token
+    let token: Token = token.parse().map_err(|_| CtxExtError::TokenWrongFormat)?;
+    validate_web_token(&token).map_err(|_| CtxExtError::FailValidate)?;
+
+    // -- Extend session
+    let session = touch_session(mm, &token.ident)
+        .await
+        .map_err(|ex| CtxExtError::ModelAccessError(ex.to_string()))?
+        .ok_or(CtxExtError::SessionNotFound)?;
+
+    // -- Reissue token
+    set_token_cookie(cookies, &session.id).map_err(|_| CtxExtError::CannotSetTokenCookie)?;
+
+    match session.impersonated_user_id {
+        Some(user_id) => Ok(Ctx::new_impersonating(session.user_id, user_id, session.id)),
+        None => Ok(Ctx::new(session.user_id, session.id)),
+    }
+}
+
+/// Rotates the refresh token and opens a session with it, the refresh cookie is
+/// dropped when the token is rejected.
+async fn ctx_resolve_refresh(mm: ModelManager, cookies: &Cookies) -> CtxExtResult {
+    let token = get_refresh_token(cookies).ok_or(CtxExtError::TokenNotInCookie)?;
+
+    let rotated = match rotate_refresh_token(mm.clone(), &token).await {
+        Ok(Some(rotated)) => rotated,
+        Ok(None) => {
+            remove_refresh_cookie(cookies);
+            return Err(CtxExtError::RefreshTokenNotValid);
+        }
+        Err(ex) => {
+            remove_refresh_cookie(cookies);
+            return Err(CtxExtError::ModelAccessError(ex.to_string()));
+        }
+    };
+
+    let session = create_session(mm, rotated.user_id)
+        .await
+        .map_err(|ex| CtxExtError::ModelAccessError(ex.to_string()))?;
+    set_token_cookie(cookies, &session.id).map_err(|_| CtxExtError::CannotSetTokenCookie)?;
+    set_refresh_cookie(cookies, &rotated.token);
+    info!(
+        "{:<12} - session reopened for user {}",
+        "REFRESH", session.user_id
+    );
+
+    Ok(Ctx::new(session.user_id, session.id))
+}
+
+fn bearer_key(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    value
+        .strip_prefix("Bearer ")
+        .map(|key| key.trim().to_string())
+}
+
+async fn ctx_resolve_api_key(mm: ModelManager, key: String) -> CtxExtResult {
+    resolve_api_key(mm, &key)
+        .await
+        .map_err(|ex| CtxExtError::ModelAccessError(ex.to_string()))?
+        .ok_or(CtxExtError::ApiKeyNotValid)
+}
+
+// endregion:     --- Ctx Resolve
+
+// region:        --- Ctx Extractor
+
+/// Axum extractor for the `Ctx` resolved by `mw_ctx_resolve`.
+#[derive(Debug, Clone)]
+pub struct CtxW(pub Ctx);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for CtxW {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self> {
+        debug!("{:<12} - Ctx", "EXTRACTOR");
+
+        parts
+            .extensions
+            .get::<CtxExtResult>()
+            .ok_or(Error::CtxExt(CtxExtError::CtxNotInRequestExt))?
+            .clone()
+            .map(CtxW)
+            .map_err(Error::CtxExt)
+    }
+}
+
+// endregion:     --- Ctx Extractor
+
+// region:        --- Ctx Extractor Result/Error
+
+pub type CtxExtResult = core::result::Result<Ctx, CtxExtError>;
+
+#[derive(Clone, Serialize, Debug)]
+pub enum CtxExtError {
+    TokenNotInCookie,
+    TokenWrongFormat,
+    FailValidate,
+    SessionNotFound,
+    CannotSetTokenCookie,
+    ApiKeyNotValid,
+    RefreshTokenNotValid,
+
+    ModelAccessError(String),
+    CtxNotInRequestExt,
+}
+
+// endregion:     --- Ctx Extractor Result/Error
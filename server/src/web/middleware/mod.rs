@@ -1,2 +1,3 @@
-pub mod stamp;
+pub mod auth;
+pub mod permission;
 pub mod response_map;
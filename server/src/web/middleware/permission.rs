@@ -0,0 +1,42 @@
+use super::auth::CtxW;
+use crate::web::Result;
+use axum::{body::Body, extract::State, http::Request, middleware::Next, response::Response};
+use lib_core::model::{rbac::check_permission, ModelManager};
+use tracing::debug;
+
+/// State of `mw_permission_require`, the permission a route needs.
+#[derive(Clone)]
+pub struct RequirePermission {
+    mm: ModelManager,
+    permission: &'static str,
+}
+
+impl RequirePermission {
+    pub fn new(mm: ModelManager, permission: &'static str) -> Self {
+        Self { mm, permission }
+    }
+}
+
+/// Rejects the request with a 403 when the `Ctx` user misses the permission.
+///
+/// ```ignore
+/// get(handler).route_layer(middleware::from_fn_with_state(
+///     RequirePermission::new(mm.clone(), perms::USER_READ),
+///     mw_permission_require,
+/// ))
+/// ```
+pub async fn mw_permission_require(
+    State(guard): State<RequirePermission>,
+    CtxW(ctx): CtxW,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response> {
+    debug!(
+        "{:<12} - mw_permission_require - {}",
+        "MIDDLEWARE", guard.permission
+    );
+
+    check_permission(guard.mm, &ctx, guard.permission).await?;
+
+    Ok(next.run(req).await)
+}
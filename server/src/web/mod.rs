@@ -2,5 +2,7 @@ mod error;
 pub mod middleware;
 pub mod routes_api;
 pub mod routes_leptos;
+pub mod routes_logout;
+pub mod routes_oidc;
 
 pub use error::{Error, Result};
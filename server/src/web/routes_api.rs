@@ -1,27 +1,136 @@
+use super::middleware::auth::{mw_ctx_require, CtxW};
+use super::middleware::permission::{mw_permission_require, RequirePermission};
 use super::Result;
 use axum::{
-    extract::State,
-    routing::{get, post},
+    extract::{Path, Query, State},
+    middleware,
+    routing::{delete, get, patch, post},
     Json, Router,
 };
 use lib_core::model::{
-    user::{create_user, list_users, UserForCreate},
-    ModelManager,
+    api_key::{create_api_key, list_api_keys, revoke_api_key, ApiKeyForCreate},
+    app_state::AppState,
+    email_verify::register_user,
+    impersonation::{
+        check_not_impersonating, list_impersonation_log, start_impersonation, stop_impersonation,
+    },
+    invitation::{create_invitation, list_invitations, revoke_invitation, InvitationForCreate},
+    list::{ListFilter, ListOptions},
+    login_throttle::unlock_account,
+    rbac::perms,
+    user::{
+        check_not_self, delete_user, get_user, get_user_for_login_by_id, list_users, update_user,
+        UserForCreate, UserForUpdate,
+    },
+    user_totp::reset_totp,
+    Error, ModelManager,
 };
 
+use serde::Deserialize;
 use serde_json::{json, Value};
-use tracing::debug;
+use tracing::{debug, info};
+
+pub fn routes(app_state: AppState) -> Router {
+    let require = |permission| {
+        middleware::from_fn_with_state(
+            RequirePermission::new(app_state.mm.clone(), permission),
+            mw_permission_require,
+        )
+    };
 
-pub fn routes(mm: ModelManager) -> Router {
     Router::new()
-        .route("/res/users", get(get_users_handler))
-        .route("/res/user", post(create_user_handler))
-        .with_state(mm)
+        .route(
+            "/res/users",
+            get(get_users_handler).route_layer(require(perms::USER_READ)),
+        )
+        .route(
+            "/res/users/:id",
+            get(get_user_handler).route_layer(require(perms::USER_READ)),
+        )
+        .route(
+            "/res/users/:id",
+            patch(update_user_handler).route_layer(require(perms::USER_UPDATE)),
+        )
+        .route(
+            "/res/users/:id",
+            delete(delete_user_handler).route_layer(require(perms::USER_DELETE)),
+        )
+        .route(
+            "/res/user",
+            post(create_user_handler).route_layer(require(perms::USER_CREATE)),
+        )
+        .route(
+            "/res/users/:id/totp",
+            delete(reset_user_totp_handler).route_layer(require(perms::USER_TOTP_RESET)),
+        )
+        .route(
+            "/res/users/:id/lockout",
+            delete(unlock_user_handler).route_layer(require(perms::USER_UNLOCK)),
+        )
+        .route(
+            "/res/invitations",
+            get(list_invitations_handler)
+                .post(create_invitation_handler)
+                .route_layer(require(perms::USER_INVITE)),
+        )
+        .route(
+            "/res/invitations/:id",
+            delete(revoke_invitation_handler).route_layer(require(perms::USER_INVITE)),
+        )
+        .route(
+            "/res/users/:id/impersonate",
+            post(start_impersonation_handler).route_layer(require(perms::USER_IMPERSONATE)),
+        )
+        .route(
+            "/res/impersonation-log",
+            get(get_impersonation_log_handler).route_layer(require(perms::USER_IMPERSONATE)),
+        )
+        // the impersonated user may lack the permission, no check to stop
+        .route("/res/impersonation", delete(stop_impersonation_handler))
+        // own keys, no permission needed
+        .route(
+            "/res/api-keys",
+            get(list_api_keys_handler).post(create_api_key_handler),
+        )
+        .route("/res/api-keys/:id", delete(revoke_api_key_handler))
+        .route_layer(middleware::from_fn(mw_ctx_require))
+        .with_state(app_state)
+}
+
+/// Query of the lists, e.g. `?filters={"is_active":true}&limit=20&order_bys=!created_at`.
+#[derive(Deserialize)]
+struct ListParams {
+    /// JSON, see `lib_core::model::list`.
+    filters: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+    order_bys: Option<String>,
+}
+
+impl ListParams {
+    fn into_parts(self) -> Result<(Option<ListFilter>, Option<ListOptions>)> {
+        let filter = self
+            .filters
+            .as_deref()
+            .map(ListFilter::from_json)
+            .transpose()?;
+        let list_options = ListOptions {
+            limit: self.limit,
+            offset: self.offset,
+            order_bys: self.order_bys,
+        };
+
+        Ok((filter, Some(list_options)))
+    }
 }
 
-async fn get_users_handler(State(mm): State<ModelManager>) -> Result<Json<Value>> {
+async fn get_users_handler(
+    State(mm): State<ModelManager>,
+    Query(params): Query<ListParams>,
+) -> Result<Json<Value>> {
     debug!("{:<12} - users", "API GET");
-    let users = list_users(mm).await?;
+    let (filter, list_options) = params.into_parts()?;
+    let users = list_users(mm, filter, list_options).await?;
 
     let body = Json(json!({
         "result":users
@@ -31,12 +140,180 @@ async fn get_users_handler(State(mm): State<ModelManager>) -> Result<Json<Value>
 }
 
 async fn create_user_handler(
-    State(mm): State<ModelManager>,
+    State(app_state): State<AppState>,
+    CtxW(ctx): CtxW,
     Json(user): Json<UserForCreate>,
 ) -> Result<Json<Value>> {
     debug!("{:<12} - user", "API POST");
 
-    let id = create_user(mm, &user.email, &user.pwd).await?;
+    check_not_impersonating(&ctx)?;
+    let id = register_user(
+        app_state.mm,
+        app_state.notifier.as_ref(),
+        &user.email,
+        &user.pwd,
+    )
+    .await?;
+
+    let body = Json(json!({
+        "result":id
+    }));
+
+    Ok(body)
+}
+
+async fn get_user_handler(
+    State(mm): State<ModelManager>,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>> {
+    debug!("{:<12} - user {id}", "API GET");
+
+    let user = get_user(mm, id).await?;
+
+    let body = Json(json!({
+        "result":user
+    }));
+
+    Ok(body)
+}
+
+async fn update_user_handler(
+    State(app_state): State<AppState>,
+    CtxW(ctx): CtxW,
+    Path(id): Path<i64>,
+    Json(user_u): Json<UserForUpdate>,
+) -> Result<Json<Value>> {
+    debug!("{:<12} - user {id}", "API PATCH");
+
+    check_not_impersonating(&ctx)?;
+    if user_u.is_active == Some(false) {
+        check_not_self(&ctx, id)?;
+    }
+
+    let mm = app_state.mm;
+    update_user(mm.clone(), app_state.notifier.as_ref(), id, user_u).await?;
+    let user = get_user(mm, id).await?;
+
+    let body = Json(json!({
+        "result":user
+    }));
+
+    Ok(body)
+}
+
+async fn delete_user_handler(
+    State(mm): State<ModelManager>,
+    CtxW(ctx): CtxW,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>> {
+    debug!("{:<12} - user {id}", "API DELETE");
+
+    check_not_impersonating(&ctx)?;
+    check_not_self(&ctx, id)?;
+
+    delete_user(mm, id).await?;
+
+    let body = Json(json!({
+        "result":id
+    }));
+
+    Ok(body)
+}
+
+async fn reset_user_totp_handler(
+    State(mm): State<ModelManager>,
+    CtxW(ctx): CtxW,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>> {
+    debug!("{:<12} - user {id} totp", "API DELETE");
+
+    check_not_impersonating(&ctx)?;
+    reset_totp(mm, id).await?;
+
+    let body = Json(json!({
+        "result":id
+    }));
+
+    Ok(body)
+}
+
+async fn unlock_user_handler(
+    State(mm): State<ModelManager>,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>> {
+    debug!("{:<12} - user {id} lockout", "API DELETE");
+
+    let user = get_user_for_login_by_id(mm.clone(), id)
+        .await?
+        .ok_or(Error::EntityNotFound { entity: "user", id })?;
+    unlock_account(mm, &user.email).await?;
+    info!("{:<12} - {} unlocked", "LOCKOUT", user.email);
+
+    let body = Json(json!({
+        "result":id
+    }));
+
+    Ok(body)
+}
+
+// region:        --- Invitations
+
+async fn list_invitations_handler(State(mm): State<ModelManager>) -> Result<Json<Value>> {
+    debug!("{:<12} - invitations", "API GET");
+
+    let invitations = list_invitations(mm).await?;
+
+    let body = Json(json!({
+        "result":invitations
+    }));
+
+    Ok(body)
+}
+
+/// The link goes to the invitee only, the response has the invitation id.
+async fn create_invitation_handler(
+    State(app_state): State<AppState>,
+    CtxW(ctx): CtxW,
+    Json(inv_c): Json<InvitationForCreate>,
+) -> Result<Json<Value>> {
+    debug!("{:<12} - invitation", "API POST");
+
+    let id = create_invitation(app_state.mm, app_state.notifier.as_ref(), &ctx, inv_c).await?;
+
+    let body = Json(json!({
+        "result":id
+    }));
+
+    Ok(body)
+}
+
+async fn revoke_invitation_handler(
+    State(mm): State<ModelManager>,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>> {
+    debug!("{:<12} - invitation {id}", "API DELETE");
+
+    revoke_invitation(mm, id).await?;
+
+    let body = Json(json!({
+        "result":id
+    }));
+
+    Ok(body)
+}
+
+// endregion:     --- Invitations
+
+// region:        --- Impersonation
+
+async fn start_impersonation_handler(
+    State(mm): State<ModelManager>,
+    CtxW(ctx): CtxW,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>> {
+    debug!("{:<12} - user {id} impersonate", "API POST");
+
+    start_impersonation(mm, &ctx, id).await?;
 
     let body = Json(json!({
         "result":id
@@ -44,3 +321,84 @@ async fn create_user_handler(
 
     Ok(body)
 }
+
+async fn stop_impersonation_handler(
+    State(mm): State<ModelManager>,
+    CtxW(ctx): CtxW,
+) -> Result<Json<Value>> {
+    debug!("{:<12} - impersonation", "API DELETE");
+
+    stop_impersonation(mm, &ctx).await?;
+
+    let body = Json(json!({
+        "result":ctx.real_user_id()
+    }));
+
+    Ok(body)
+}
+
+async fn get_impersonation_log_handler(State(mm): State<ModelManager>) -> Result<Json<Value>> {
+    debug!("{:<12} - impersonation log", "API GET");
+
+    let entries = list_impersonation_log(mm).await?;
+
+    let body = Json(json!({
+        "result":entries
+    }));
+
+    Ok(body)
+}
+
+// endregion:     --- Impersonation
+
+// region:        --- API keys
+
+async fn list_api_keys_handler(
+    State(mm): State<ModelManager>,
+    CtxW(ctx): CtxW,
+) -> Result<Json<Value>> {
+    debug!("{:<12} - api keys", "API GET");
+
+    let keys = list_api_keys(mm, &ctx).await?;
+
+    let body = Json(json!({
+        "result":keys
+    }));
+
+    Ok(body)
+}
+
+/// The key is in the response, it cannot be read again later.
+async fn create_api_key_handler(
+    State(mm): State<ModelManager>,
+    CtxW(ctx): CtxW,
+    Json(key_c): Json<ApiKeyForCreate>,
+) -> Result<Json<Value>> {
+    debug!("{:<12} - api key", "API POST");
+
+    let created = create_api_key(mm, &ctx, key_c).await?;
+
+    let body = Json(json!({
+        "result":created
+    }));
+
+    Ok(body)
+}
+
+async fn revoke_api_key_handler(
+    State(mm): State<ModelManager>,
+    CtxW(ctx): CtxW,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>> {
+    debug!("{:<12} - api key {id}", "API DELETE");
+
+    revoke_api_key(mm, &ctx, id).await?;
+
+    let body = Json(json!({
+        "result":id
+    }));
+
+    Ok(body)
+}
+
+// endregion:     --- API keys
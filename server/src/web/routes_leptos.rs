@@ -1,5 +1,6 @@
 use crate::AppState;
 
+use super::middleware::auth::CtxExtResult;
 use super::{Error, Result};
 use app::App;
 use axum::body::Body;
@@ -14,6 +15,7 @@ use axum::{Json, Router};
 use leptos::server_fn::middleware;
 use leptos::{get_configuration, provide_context, view, LeptosOptions};
 use leptos_axum::{generate_route_list, handle_server_fns_with_context, LeptosRoutes};
+use lib_core::ctx::Ctx;
 use serde_json::{json, Value};
 use std::net::SocketAddr;
 use tower::ServiceExt;
@@ -60,10 +62,14 @@ async fn server_fns_handler(
     req: Request<Body>,
 ) -> impl IntoResponse {
     debug!("{:<12} - {} {}", "SERVER FN", req.method(), req.uri());
+    let ctx = resolved_ctx(&req);
 
     handle_server_fns_with_context(
         move || {
             provide_context(app_state.clone());
+            if let Some(ctx) = ctx.clone() {
+                provide_context(ctx);
+            }
         },
         req,
     )
@@ -75,15 +81,28 @@ pub async fn leptos_routes_handler(
     req: Request<Body>,
 ) -> AxumResponse {
     debug!("{:<12} - {} {}", "BROWSER REQ", req.method(), req.uri());
+    let ctx = resolved_ctx(&req);
 
     let handler = leptos_axum::render_app_to_stream_with_context(
         app_state.leptos_options.clone(),
-        move || provide_context(app_state.clone()),
+        move || {
+            provide_context(app_state.clone());
+            if let Some(ctx) = ctx.clone() {
+                provide_context(ctx);
+            }
+        },
         move || view! { <App/> },
     );
     handler(req).await.into_response()
 }
 
+/// `Ctx` resolved by `mw_ctx_resolve`, if the user is authenticated.
+fn resolved_ctx(req: &Request<Body>) -> Option<Ctx> {
+    req.extensions()
+        .get::<CtxExtResult>()
+        .and_then(|ctx| ctx.clone().ok())
+}
+
 // endregion:     --- Leptos handler
 
 pub fn routes(app_state: AppState) -> Router {
@@ -0,0 +1,79 @@
+use super::middleware::auth::CtxW;
+use super::Result;
+use axum::{extract::State, routing::post, Json, Router};
+use lib_core::model::{
+    self,
+    app_state::AppState,
+    refresh_token::revoke_refresh_token,
+    session::{delete_session, revoke_user_sessions},
+    ModelManager,
+};
+use lib_core::web::{get_refresh_token, remove_refresh_cookie, remove_token_cookie};
+use serde_json::{json, Value};
+use tower_cookies::Cookies;
+use tracing::{debug, info};
+
+pub fn routes(app_state: AppState) -> Router {
+    Router::new()
+        .route("/auth/logout", post(logout_handler))
+        .route("/auth/logout-all", post(logout_all_handler))
+        .with_state(app_state)
+}
+
+/// Always clears the cookies, the session and "remember me" token are revoked
+/// when there are some.
+async fn logout_handler(
+    State(mm): State<ModelManager>,
+    cookies: Cookies,
+    ctx: Result<CtxW>,
+) -> Result<Json<Value>> {
+    debug!("{:<12} - logout", "HANDLER");
+
+    if let Some(session_id) = ctx.as_ref().ok().and_then(|CtxW(ctx)| ctx.session_id()) {
+        delete_session(mm.clone(), session_id).await?;
+    }
+    if let Some(token) = get_refresh_token(&cookies) {
+        revoke_refresh_token(mm, &token).await?;
+    }
+    remove_token_cookie(&cookies);
+    remove_refresh_cookie(&cookies);
+
+    let body = Json(json!({
+        "result":{
+            "logged_out": true
+        }
+    }));
+
+    Ok(body)
+}
+
+/// Revokes every session of the user, this browser included. While
+/// impersonating, the user is the one who signed in.
+async fn logout_all_handler(
+    State(mm): State<ModelManager>,
+    cookies: Cookies,
+    CtxW(ctx): CtxW,
+) -> Result<Json<Value>> {
+    debug!("{:<12} - logout all", "HANDLER");
+
+    // an API key cannot sign its user out
+    ctx.session_id()
+        .ok_or(model::Error::ApiKeyRequiresSession)?;
+
+    let count = revoke_user_sessions(mm, ctx.real_user_id()).await?;
+    remove_token_cookie(&cookies);
+    remove_refresh_cookie(&cookies);
+    info!(
+        "{:<12} - user {} signed out of {count} sessions",
+        "LOGOUT",
+        ctx.real_user_id()
+    );
+
+    let body = Json(json!({
+        "result":{
+            "sessions_revoked": count
+        }
+    }));
+
+    Ok(body)
+}
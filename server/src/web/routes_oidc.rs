@@ -0,0 +1,82 @@
+use super::{Error, Result};
+use axum::{
+    extract::{Query, State},
+    response::Redirect,
+    routing::get,
+    Router,
+};
+use lib_core::model::{
+    app_state::AppState,
+    email_verify::is_registration_open,
+    user_oidc::{sign_in_oidc_user, OidcSignIn},
+    ModelManager,
+};
+use lib_core::oidc::OidcClient;
+use lib_core::web::{
+    set_login_2fa_cookie, set_oidc_flow_cookie, set_token_cookie, take_oidc_flow_cookie,
+};
+use serde::Deserialize;
+use tower_cookies::Cookies;
+use tracing::{debug, info};
+
+pub fn routes(app_state: AppState) -> Router {
+    Router::new()
+        .route("/auth/oidc/login", get(oidc_login_handler))
+        .route("/auth/oidc/callback", get(oidc_callback_handler))
+        .with_state(app_state)
+}
+
+#[derive(Deserialize)]
+struct CallbackParams {
+    code: Option<String>,
+    state: Option<String>,
+    /// Set by the provider when the user or the provider refused the sign-in.
+    error: Option<String>,
+}
+
+async fn oidc_login_handler(cookies: Cookies) -> Result<Redirect> {
+    debug!("{:<12} - oidc login", "HANDLER");
+
+    let auth = OidcClient::from_config().await?.authorization_request()?;
+    set_oidc_flow_cookie(&cookies, &auth.flow)?;
+
+    Ok(Redirect::to(&auth.url))
+}
+
+/// A user with TOTP enabled is sent to the TOTP step of the login form, the
+/// provider may not have checked a second factor.
+async fn oidc_callback_handler(
+    State(mm): State<ModelManager>,
+    cookies: Cookies,
+    Query(params): Query<CallbackParams>,
+) -> Result<Redirect> {
+    debug!("{:<12} - oidc callback", "HANDLER");
+
+    if let Some(error) = params.error {
+        return Err(Error::OidcCallbackDenied { error });
+    }
+    let flow = take_oidc_flow_cookie(&cookies).ok_or(Error::OidcFlowNotValid)?;
+    let (Some(code), Some(state)) = (params.code, params.state) else {
+        return Err(Error::OidcFlowNotValid);
+    };
+
+    let client = OidcClient::from_config().await?;
+    let claims = client.exchange_code(&flow, &state, &code).await?;
+    // no new user from SSO either when the registration is closed
+    let provision = client.config().provision && is_registration_open();
+    let session = match sign_in_oidc_user(mm, &claims, provision).await? {
+        OidcSignIn::Session(session) => session,
+        OidcSignIn::TotpRequired { user_id } => {
+            set_login_2fa_cookie(&cookies, user_id)?;
+            debug!("{:<12} - user {user_id} SSO, TOTP step", "OIDC");
+            return Ok(Redirect::to("/"));
+        }
+    };
+    set_token_cookie(&cookies, &session.id)?;
+    info!(
+        "{:<12} - user {} signed in with SSO",
+        "OIDC", session.user_id
+    );
+
+    Ok(Redirect::to("/"))
+}